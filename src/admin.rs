@@ -2,6 +2,8 @@ use near_sdk::{env, AccountId, PublicKey, Gas};
 use crate::{ext_auth, state::Relayer};
 use crate::errors::RelayerError;
 use crate::events::RelayerEvent;
+use crate::types::{Action, GovernanceAction, ManagerProposal, Role, Subsystem, WebAuthnConfig};
+use crate::{fees, governance, multisig};
 
 pub fn register_existing_account(
     relayer: &mut Relayer,
@@ -11,15 +13,19 @@ pub fn register_existing_account(
     is_multi_sig: bool,
     multi_sig_threshold: Option<u32>,
 ) -> Result<(), RelayerError> {
+    relayer.ensure_not_paused(Subsystem::Keys)?;
     let caller = env::predecessor_account_id();
     if caller != account_id {
         return Err(RelayerError::Unauthorized);
     }
-    if public_key.as_bytes().len() != 33 || public_key.as_bytes()[0] != 0 {
+    let key_bytes = public_key.as_bytes();
+    let is_ed25519 = key_bytes.len() == 33 && key_bytes[0] == 0;
+    let is_secp256k1 = key_bytes.len() == 65 && key_bytes[0] == 1;
+    if !is_ed25519 && !is_secp256k1 {
         return Err(RelayerError::InvalidSignature);
     }
     ext_auth::ext(relayer.auth_contract.clone())
-        .with_static_gas(Gas::from_tgas(relayer.cross_contract_gas))
+        .with_static_gas(Gas::from_gas(relayer.cross_contract_gas))
         .register_key(
             account_id.clone(),
             public_key.clone(),
@@ -27,30 +33,31 @@ pub fn register_existing_account(
             is_multi_sig,
             multi_sig_threshold,
         );
-    let key_hash = hex::encode(env::sha256(&public_key.as_bytes()));
+    let key_hash = hex::encode(env::sha256(public_key.as_bytes()));
     RelayerEvent::AuthAdded { auth_account: account_id, key_hash }.emit();
     Ok(())
 }
 
 pub fn remove_key(relayer: &mut Relayer, account_id: AccountId, public_key: PublicKey) -> Result<(), RelayerError> {
+    relayer.ensure_not_paused(Subsystem::Keys)?;
     let caller = env::predecessor_account_id();
     if caller != account_id {
         return Err(RelayerError::Unauthorized);
     }
     ext_auth::ext(relayer.auth_contract.clone())
-        .with_static_gas(Gas::from_tgas(relayer.cross_contract_gas))
+        .with_static_gas(Gas::from_gas(relayer.cross_contract_gas))
         .remove_key(account_id.clone(), public_key.clone());
-    let key_hash = hex::encode(env::sha256(&public_key.as_bytes()));
+    let key_hash = hex::encode(env::sha256(public_key.as_bytes()));
     RelayerEvent::AuthRemoved { auth_account: account_id, key_hash }.emit();
     Ok(())
 }
 
 pub fn set_cross_contract_gas(relayer: &mut Relayer, new_gas: u64) -> Result<(), RelayerError> {
     let caller = env::predecessor_account_id();
-    if !relayer.is_manager(&caller) {
+    if !relayer.has_role(Role::ConfigAdmin, &caller) {
         return Err(RelayerError::Unauthorized);
     }
-    if new_gas < 15_000_000_000_000 || new_gas > 100_000_000_000_000 {
+    if !(15_000_000_000_000..=100_000_000_000_000).contains(&new_gas) {
         return Err(RelayerError::AmountTooLow);
     }
     relayer.cross_contract_gas = new_gas;
@@ -67,10 +74,10 @@ pub fn set_cross_contract_gas(relayer: &mut Relayer, new_gas: u64) -> Result<(),
 
 pub fn set_migration_gas(relayer: &mut Relayer, new_gas: u64) -> Result<(), RelayerError> {
     let caller = env::predecessor_account_id();
-    if !relayer.is_manager(&caller) {
+    if !relayer.has_role(Role::ConfigAdmin, &caller) {
         return Err(RelayerError::Unauthorized);
     }
-    if new_gas < 15_000_000_000_000 || new_gas > 200_000_000_000_000 {
+    if !(15_000_000_000_000..=200_000_000_000_000).contains(&new_gas) {
         return Err(RelayerError::AmountTooLow);
     }
     relayer.migration_gas = new_gas;
@@ -85,39 +92,75 @@ pub fn set_migration_gas(relayer: &mut Relayer, new_gas: u64) -> Result<(), Rela
     Ok(())
 }
 
-pub fn set_omni_locker_contract(relayer: &mut Relayer, new_locker_contract: AccountId) -> Result<(), RelayerError> {
+pub fn set_omni_locker_contract(relayer: &mut Relayer, new_locker_contract: AccountId) -> Result<u64, RelayerError> {
+    governance::schedule(relayer, GovernanceAction::SetOmniLockerContract { new_locker_contract })
+}
+
+pub fn set_offload_recipient(relayer: &mut Relayer, new_recipient: AccountId) -> Result<(), RelayerError> {
     let caller = env::predecessor_account_id();
-    if !relayer.is_manager(&caller) {
+    if !relayer.has_role(Role::Owner, &caller) {
         return Err(RelayerError::Unauthorized);
     }
-    relayer.omni_locker_contract.set(Some(new_locker_contract.clone()));
-    RelayerEvent::OmniLockerContractUpdated { new_locker_contract }.emit();
+    relayer.offload_recipient = new_recipient.clone();
+    RelayerEvent::OffloadRecipientUpdated { new_recipient }.emit();
     Ok(())
 }
 
-pub fn set_offload_recipient(relayer: &mut Relayer, new_recipient: AccountId) -> Result<(), RelayerError> {
+// The manager only changes once the proposed account accepts, so a mistyped account can't
+// take the contract with it. A new proposal replaces any pending one.
+pub fn propose_manager(relayer: &mut Relayer, new_manager: AccountId, expires_in_ms: Option<u64>) -> Result<(), RelayerError> {
     let caller = env::predecessor_account_id();
-    if !relayer.is_manager(&caller) {
+    if !relayer.has_role(Role::Owner, &caller) {
         return Err(RelayerError::Unauthorized);
     }
-    relayer.offload_recipient = new_recipient.clone();
-    RelayerEvent::OffloadRecipientUpdated { new_recipient }.emit();
+    if new_manager == relayer.manager {
+        return Err(RelayerError::InvalidAccountId);
+    }
+    let expires_at_ms = expires_in_ms.map(|ms| env::block_timestamp_ms().saturating_add(ms));
+    relayer.pending_manager = Some(ManagerProposal {
+        proposed_manager: new_manager.clone(),
+        proposed_by: caller,
+        expires_at_ms,
+    });
+    RelayerEvent::ManagerProposed { current_manager: relayer.manager.clone(), proposed_manager: new_manager, expires_at_ms }.emit();
+    Ok(())
+}
+
+pub fn accept_manager(relayer: &mut Relayer) -> Result<(), RelayerError> {
+    let caller = env::predecessor_account_id();
+    let proposal = relayer.pending_manager.as_ref().ok_or(RelayerError::NoPendingProposal)?;
+    if proposal.proposed_manager != caller {
+        return Err(RelayerError::Unauthorized);
+    }
+    if proposal.expires_at_ms.is_some_and(|expires_at| env::block_timestamp_ms() > expires_at) {
+        return Err(RelayerError::ProposalExpired);
+    }
+    relayer.pending_manager = None;
+    let old_manager = relayer.manager.clone();
+    let mut owners = relayer.role_holders(Role::Owner);
+    owners.retain(|owner| *owner != old_manager && *owner != caller);
+    owners.insert(0, caller.clone());
+    relayer.roles.insert(Role::Owner, owners);
+    relayer.manager = caller.clone();
+    RelayerEvent::ManagerChanged { old_manager, new_manager: caller, timestamp: env::block_timestamp_ms() }.emit();
     Ok(())
 }
 
-pub fn set_manager(relayer: &mut Relayer, new_manager: AccountId) -> Result<(), RelayerError> {
+pub fn cancel_manager_proposal(relayer: &mut Relayer) -> Result<(), RelayerError> {
     let caller = env::predecessor_account_id();
-    if !relayer.is_manager(&caller) {
+    let proposal = relayer.pending_manager.as_ref().ok_or(RelayerError::NoPendingProposal)?;
+    if proposal.proposed_manager != caller && !relayer.has_role(Role::Owner, &caller) {
         return Err(RelayerError::Unauthorized);
     }
-    relayer.manager = new_manager.clone();
-    RelayerEvent::ManagerChanged { old_manager: caller, new_manager, timestamp: env::block_timestamp_ms() }.emit();
+    let proposed_manager = proposal.proposed_manager.clone();
+    relayer.pending_manager = None;
+    RelayerEvent::ManagerProposalCancelled { proposed_manager, cancelled_by: caller }.emit();
     Ok(())
 }
 
 pub fn set_sponsor_amount(relayer: &mut Relayer, new_amount: u128) -> Result<(), RelayerError> {
     let caller = env::predecessor_account_id();
-    if !relayer.is_manager(&caller) {
+    if !relayer.has_role(Role::ConfigAdmin, &caller) {
         return Err(RelayerError::Unauthorized);
     }
     if new_amount < 10_000_000_000_000_000_000_000 {
@@ -130,10 +173,10 @@ pub fn set_sponsor_amount(relayer: &mut Relayer, new_amount: u128) -> Result<(),
 
 pub fn set_sponsor_gas(relayer: &mut Relayer, new_gas: u64) -> Result<(), RelayerError> {
     let caller = env::predecessor_account_id();
-    if !relayer.is_manager(&caller) {
+    if !relayer.has_role(Role::ConfigAdmin, &caller) {
         return Err(RelayerError::Unauthorized);
     }
-    if new_gas < 50_000_000_000_000 || new_gas > 300_000_000_000_000 {
+    if !(50_000_000_000_000..=300_000_000_000_000).contains(&new_gas) {
         return Err(RelayerError::AmountTooLow);
     }
     relayer.sponsor_gas = new_gas;
@@ -143,10 +186,10 @@ pub fn set_sponsor_gas(relayer: &mut Relayer, new_gas: u64) -> Result<(), Relaye
 
 pub fn set_chunk_size(relayer: &mut Relayer, new_size: usize) -> Result<(), RelayerError> {
     let caller = env::predecessor_account_id();
-    if !relayer.is_manager(&caller) {
+    if !relayer.has_role(Role::ConfigAdmin, &caller) {
         return Err(RelayerError::Unauthorized);
     }
-    if new_size < 1 || new_size > 5 {
+    if !(1..=5).contains(&new_size) {
         return Err(RelayerError::AmountTooLow);
     }
     relayer.chunk_size = new_size;
@@ -161,73 +204,107 @@ pub fn set_chunk_size(relayer: &mut Relayer, new_size: usize) -> Result<(), Rela
     Ok(())
 }
 
-pub fn add_chain_mpc_mapping(relayer: &mut Relayer, chain: String, mpc_contract: AccountId) -> Result<(), RelayerError> {
+pub fn set_max_actions(relayer: &mut Relayer, new_max: usize) -> Result<(), RelayerError> {
     let caller = env::predecessor_account_id();
-    if !relayer.is_manager(&caller) {
+    if !relayer.has_role(Role::ConfigAdmin, &caller) {
         return Err(RelayerError::Unauthorized);
     }
-    relayer.chain_mpc_mapping.insert(chain.clone(), mpc_contract.clone());
-    RelayerEvent::ChainMpcMappingAdded { chain, mpc_contract }.emit();
+    if !(1..=10).contains(&new_max) {
+        return Err(RelayerError::AmountTooLow);
+    }
+    relayer.max_actions = new_max;
+    RelayerEvent::MaxActionsUpdated { new_max }.emit();
     Ok(())
 }
 
-pub fn remove_chain_mpc_mapping(relayer: &mut Relayer, chain: String) -> Result<(), RelayerError> {
+pub fn set_nonce_oracle(relayer: &mut Relayer, nonce_oracle: Option<AccountId>) -> Result<u64, RelayerError> {
+    governance::schedule(relayer, GovernanceAction::SetNonceOracle { nonce_oracle })
+}
+
+pub fn set_webauthn_config(relayer: &mut Relayer, config: Option<WebAuthnConfig>) -> Result<u64, RelayerError> {
+    if let Some(config) = &config {
+        if config.rp_id.is_empty() || config.origins.is_empty() || config.origins.iter().any(|origin| !origin.starts_with("https://")) {
+            return Err(RelayerError::InvalidWebAuthnConfig);
+        }
+    }
+    governance::schedule(relayer, GovernanceAction::SetWebAuthnConfig { config })
+}
+
+pub fn set_auth_contract(relayer: &mut Relayer, new_auth_contract: AccountId) -> Result<u64, RelayerError> {
+    governance::schedule(relayer, GovernanceAction::SetAuthContract { new_auth_contract })
+}
+
+pub fn set_ft_wrapper_contract(relayer: &mut Relayer, new_ft_wrapper_contract: AccountId) -> Result<u64, RelayerError> {
+    governance::schedule(relayer, GovernanceAction::SetFtWrapperContract { new_ft_wrapper_contract })
+}
+
+pub fn set_base_fee(relayer: &mut Relayer, new_fee: u128, signatures: Option<Vec<Vec<u8>>>) -> Result<(), RelayerError> {
     let caller = env::predecessor_account_id();
-    if !relayer.is_manager(&caller) {
+    if !relayer.has_role(Role::FeeManager, &caller) {
         return Err(RelayerError::Unauthorized);
     }
-    relayer.chain_mpc_mapping.remove(&chain);
-    RelayerEvent::ChainMpcMappingRemoved { chain }.emit();
+    // Allow zero fee without signatures for flexibility
+    if new_fee > 0 {
+        let args = near_sdk::borsh::to_vec(&new_fee).map_err(|_| RelayerError::InvalidNonce)?;
+        multisig::verify_admin(relayer, "set_base_fee", &args, signatures)?;
+        let min_fee = 100_000_000_000_000_000_000; // 0.0001 NEAR
+        if new_fee < min_fee {
+            return Err(RelayerError::FeeTooLow);
+        }
+    }
+    relayer.base_fee = new_fee;
+    RelayerEvent::BaseFeeUpdated { new_fee }.emit();
     Ok(())
 }
 
-pub fn set_auth_contract(relayer: &mut Relayer, new_auth_contract: AccountId) -> Result<(), RelayerError> {
+pub fn set_action_fee(relayer: &mut Relayer, action_type: String, token: Option<String>, fee: u128) -> Result<(), RelayerError> {
     let caller = env::predecessor_account_id();
-    if !relayer.is_manager(&caller) {
+    if !relayer.has_role(Role::FeeManager, &caller) {
         return Err(RelayerError::Unauthorized);
     }
-    relayer.auth_contract = new_auth_contract.clone();
-    RelayerEvent::AuthContractUpdated { new_auth_contract }.emit();
+    if !Action::TYPE_NAMES.contains(&action_type.as_str()) {
+        return Err(RelayerError::InvalidFeeAction);
+    }
+    let asset = token.unwrap_or_else(|| fees::NEAR_ASSET.to_string());
+    if asset != fees::NEAR_ASSET && !relayer.fee_tokens.contains(&asset) {
+        return Err(RelayerError::FeeTokenNotAllowed);
+    }
+    let key = fees::fee_key(&action_type, &asset);
+    if fee == 0 {
+        relayer.action_fees.remove(&key);
+    } else {
+        relayer.action_fees.insert(key, fee);
+    }
+    RelayerEvent::ActionFeeUpdated { action_type, asset, fee }.emit();
     Ok(())
 }
 
-pub fn set_ft_wrapper_contract(relayer: &mut Relayer, new_ft_wrapper_contract: AccountId) -> Result<(), RelayerError> {
+pub fn add_fee_token(relayer: &mut Relayer, token: String) -> Result<(), RelayerError> {
     let caller = env::predecessor_account_id();
-    if !relayer.is_manager(&caller) {
+    if !relayer.has_role(Role::FeeManager, &caller) {
         return Err(RelayerError::Unauthorized);
     }
-    relayer.ft_wrapper_contract = new_ft_wrapper_contract.clone();
-    RelayerEvent::FtWrapperContractUpdated { new_ft_wrapper_contract }.emit();
+    if token == fees::NEAR_ASSET {
+        return Err(RelayerError::InvalidFeeAction);
+    }
+    relayer.fee_tokens.insert(token.clone());
+    RelayerEvent::FeeTokenAdded { token }.emit();
     Ok(())
 }
 
-pub fn set_base_fee(relayer: &mut Relayer, new_fee: u128, signatures: Option<Vec<Vec<u8>>>) -> Result<(), RelayerError> {
+pub fn remove_fee_token(relayer: &mut Relayer, token: String) -> Result<(), RelayerError> {
     let caller = env::predecessor_account_id();
-    if !relayer.is_manager(&caller) {
+    if !relayer.has_role(Role::FeeManager, &caller) {
         return Err(RelayerError::Unauthorized);
     }
-    // Allow zero fee without signatures for flexibility
-    if new_fee > 0 {
-        if let Some(sigs) = signatures {
-            if sigs.len() < 2 {
-                return Err(RelayerError::InsufficientSignatures);
-            }
-        } else {
-            return Err(RelayerError::InsufficientSignatures);
-        }
-        let min_fee = 100_000_000_000_000_000_000; // 0.0001 NEAR
-        if new_fee < min_fee {
-            return Err(RelayerError::FeeTooLow);
-        }
-    }
-    relayer.base_fee = new_fee;
-    RelayerEvent::BaseFeeUpdated { new_fee }.emit();
+    relayer.fee_tokens.remove(&token);
+    RelayerEvent::FeeTokenRemoved { token }.emit();
     Ok(())
 }
 
 pub fn set_min_balance(relayer: &mut Relayer, new_min: u128) -> Result<(), RelayerError> {
     let caller = env::predecessor_account_id();
-    if !relayer.is_manager(&caller) {
+    if !relayer.has_role(Role::ConfigAdmin, &caller) {
         return Err(RelayerError::Unauthorized);
     }
     if new_min > relayer.max_balance {
@@ -240,7 +317,7 @@ pub fn set_min_balance(relayer: &mut Relayer, new_min: u128) -> Result<(), Relay
 
 pub fn set_max_balance(relayer: &mut Relayer, new_max: u128) -> Result<(), RelayerError> {
     let caller = env::predecessor_account_id();
-    if !relayer.is_manager(&caller) {
+    if !relayer.has_role(Role::ConfigAdmin, &caller) {
         return Err(RelayerError::Unauthorized);
     }
     if new_max < relayer.min_balance {
@@ -249,4 +326,80 @@ pub fn set_max_balance(relayer: &mut Relayer, new_max: u128) -> Result<(), Relay
     relayer.max_balance = new_max;
     RelayerEvent::MaxBalanceUpdated { new_max }.emit();
     Ok(())
-}
\ No newline at end of file
+}
+
+// Pausing without a subsystem stops everything; admin setters stay available either way.
+pub fn pause(relayer: &mut Relayer, subsystem: Option<Subsystem>) -> Result<(), RelayerError> {
+    let caller = env::predecessor_account_id();
+    if !relayer.has_role(Role::Pauser, &caller) {
+        return Err(RelayerError::Unauthorized);
+    }
+    let subsystems: Vec<Subsystem> = subsystem.map_or(Subsystem::ALL.to_vec(), |s| vec![s])
+        .into_iter()
+        .filter(|s| !relayer.is_paused(*s))
+        .collect();
+    relayer.paused.extend(subsystems.iter().copied());
+    RelayerEvent::Paused { subsystems, manager: caller, timestamp: env::block_timestamp_ms() }.emit();
+    Ok(())
+}
+
+pub fn unpause(relayer: &mut Relayer, subsystem: Option<Subsystem>) -> Result<(), RelayerError> {
+    let caller = env::predecessor_account_id();
+    if !relayer.has_role(Role::Pauser, &caller) {
+        return Err(RelayerError::Unauthorized);
+    }
+    let subsystems: Vec<Subsystem> = subsystem.map_or(Subsystem::ALL.to_vec(), |s| vec![s])
+        .into_iter()
+        .filter(|s| relayer.is_paused(*s))
+        .collect();
+    relayer.paused.retain(|s| !subsystems.contains(s));
+    RelayerEvent::Unpaused { subsystems, manager: caller, timestamp: env::block_timestamp_ms() }.emit();
+    Ok(())
+}
+
+pub fn grant_role(relayer: &mut Relayer, role: Role, account_id: AccountId) -> Result<(), RelayerError> {
+    let caller = env::predecessor_account_id();
+    if !relayer.has_role(Role::Owner, &caller) {
+        return Err(RelayerError::Unauthorized);
+    }
+    let mut holders = relayer.role_holders(role);
+    if !holders.contains(&account_id) {
+        holders.push(account_id.clone());
+        relayer.roles.insert(role, holders);
+        RelayerEvent::RoleGranted { role, account_id, sender: caller }.emit();
+    }
+    Ok(())
+}
+
+pub fn revoke_role(relayer: &mut Relayer, role: Role, account_id: AccountId) -> Result<(), RelayerError> {
+    let caller = env::predecessor_account_id();
+    if !relayer.has_role(Role::Owner, &caller) {
+        return Err(RelayerError::Unauthorized);
+    }
+    remove_role_holder(relayer, role, account_id, caller)
+}
+
+pub fn renounce_role(relayer: &mut Relayer, role: Role) -> Result<(), RelayerError> {
+    let caller = env::predecessor_account_id();
+    remove_role_holder(relayer, role, caller.clone(), caller)
+}
+
+// The contract must always keep an owner; `manager` follows the first remaining one.
+fn remove_role_holder(relayer: &mut Relayer, role: Role, account_id: AccountId, sender: AccountId) -> Result<(), RelayerError> {
+    let mut holders = relayer.role_holders(role);
+    if !holders.contains(&account_id) {
+        return Ok(());
+    }
+    holders.retain(|holder| *holder != account_id);
+    if role == Role::Owner {
+        let Some(first_owner) = holders.first() else {
+            return Err(RelayerError::LastOwner);
+        };
+        if relayer.manager == account_id {
+            relayer.manager = first_owner.clone();
+        }
+    }
+    relayer.roles.insert(role, holders);
+    RelayerEvent::RoleRevoked { role, account_id, sender }.emit();
+    Ok(())
+}
@@ -0,0 +1,457 @@
+use near_sdk::{env, borsh, AccountId, Gas, NearToken, Promise};
+use near_sdk::borsh::{BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use crate::state::Relayer;
+use crate::types::{Action, BridgeFeeQuote, BridgeFeeSchedule, BridgeTransferRecord, ChainConfig, Role, SignedDelegateAction, TransferResolution, TransferStatus, TransferStep};
+use crate::errors::RelayerError;
+use crate::events::RelayerEvent;
+use crate::ext_self;
+use crate::relay::SignRequest;
+use crate::{chains, fees, ledger};
+
+// Bookkeeping callbacks only touch the transfer record, so they get a fixed budget.
+pub const TRANSFER_CALLBACK_GAS: Gas = Gas::from_tgas(10);
+
+// handle_transfer_locked runs, then requests the MPC signature and its final callback.
+pub fn transfer_locked_gas(relayer: &Relayer) -> Gas {
+    TRANSFER_CALLBACK_GAS.saturating_mul(2)
+        .saturating_add(Gas::from_gas(relayer.cross_contract_gas.saturating_add(ledger::RECEIPT_GAS.saturating_mul(2))))
+}
+pub const MIN_BRIDGE_TIMEOUT_MS: u64 = 600_000;
+// Transfers kept per sender. Making room drops the sender's oldest finished transfer along
+// with its record, so its id stops resolving; the last event carries the full record.
+pub const MAX_SENDER_TRANSFERS: usize = 50;
+
+#[derive(BorshSerialize, BorshDeserialize)]
+struct BridgeTransferPayload {
+    token: String,
+    amount: u128,
+    destination_chain: String,
+    recipient: String,
+    sender: AccountId,
+    nonce: u64,
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn initiate(
+    relayer: &mut Relayer,
+    sender_id: AccountId,
+    token: String,
+    amount: U128,
+    destination_chain: String,
+    recipient: String,
+    nonce: u64,
+    fee: u128,
+    fee_asset: String,
+) -> BridgeTransferRecord {
+    let id = relayer.next_transfer_id;
+    relayer.next_transfer_id += 1;
+    let record = BridgeTransferRecord {
+        id,
+        sender_id: sender_id.clone(),
+        token,
+        amount,
+        destination_chain: destination_chain.clone(),
+        recipient,
+        nonce,
+        fee: U128(fee),
+        fee_asset,
+        status: TransferStatus::Initiated,
+        signature: None,
+        history: vec![TransferStep { status: TransferStatus::Initiated, timestamp_ms: env::block_timestamp_ms() }],
+    };
+    relayer.bridge_transfers.insert(id, record.clone());
+    relayer.sender_transfers.entry(sender_id).or_default().push(id);
+    relayer.pending_chain_transfers.entry(destination_chain).or_default().push(id);
+    record
+}
+
+// Frees a slot for the sender's next transfer, refusing it while all MAX_SENDER_TRANSFERS
+// kept ones are still in flight.
+pub fn make_room(relayer: &mut Relayer, sender_id: &AccountId) -> Result<(), RelayerError> {
+    let Some(ids) = relayer.sender_transfers.get_mut(sender_id) else {
+        return Ok(());
+    };
+    if ids.len() < MAX_SENDER_TRANSFERS {
+        return Ok(());
+    }
+    let finished = ids.iter()
+        .position(|id| relayer.bridge_transfers.get(id).is_none_or(|record| record.status.is_terminal()))
+        .ok_or(RelayerError::TooManyPendingTransfers)?;
+    let evicted = ids.remove(finished);
+    if let Some(record) = relayer.bridge_transfers.remove(&evicted) {
+        RelayerEvent::BridgeTransferEvicted { record }.emit();
+    }
+    Ok(())
+}
+
+// Moves a transfer one step along its lifecycle.
+pub fn advance(relayer: &mut Relayer, id: u64, status: TransferStatus) -> Result<BridgeTransferRecord, RelayerError> {
+    let record = relayer.bridge_transfers.get(&id).ok_or(RelayerError::TransferNotFound)?;
+    if !record.status.can_become(status) {
+        return Err(RelayerError::InvalidTransferStatus);
+    }
+    record_step(relayer, id, status)
+}
+
+// Terminal transfers leave the chain's pending list.
+fn record_step(relayer: &mut Relayer, id: u64, status: TransferStatus) -> Result<BridgeTransferRecord, RelayerError> {
+    let record = relayer.bridge_transfers.get_mut(&id).ok_or(RelayerError::TransferNotFound)?;
+    let timestamp_ms = env::block_timestamp_ms();
+    record.status = status;
+    record.history.push(TransferStep { status, timestamp_ms });
+    let record = record.clone();
+    if status.is_terminal() {
+        if let Some(pending) = relayer.pending_chain_transfers.get_mut(&record.destination_chain) {
+            pending.retain(|pending_id| *pending_id != id);
+        }
+    }
+    RelayerEvent::BridgeTransferStatusChanged { id, status, timestamp_ms }.emit();
+    Ok(record)
+}
+
+// Marks a live transfer Failed and, if it charged a fee, refunds the fee and marks it Refunded.
+pub fn fail(relayer: &mut Relayer, id: u64) -> Result<BridgeTransferRecord, RelayerError> {
+    let record = advance(relayer, id, TransferStatus::Failed)?;
+    env::log_str(&format!("Bridge transfer {} failed for sender {} to chain {}", id, record.sender_id, record.destination_chain));
+    RelayerEvent::BridgeTransferFailed {
+        id,
+        token: record.token.clone(),
+        amount: record.amount,
+        destination_chain: record.destination_chain.clone(),
+        recipient: record.recipient.clone(),
+        sender: record.sender_id.clone(),
+        nonce: record.nonce,
+    }.emit();
+    if record.fee.0 == 0 {
+        return Ok(record);
+    }
+    refund_fee(relayer, &record);
+    advance(relayer, id, TransferStatus::Refunded)
+}
+
+pub fn fee_asset(chain: &ChainConfig) -> &str {
+    chain.fee_token.as_deref().unwrap_or(fees::NEAR_ASSET)
+}
+
+pub fn schedule_key(chain: &str, token: &str) -> String {
+    format!("{}:{}", chain, token)
+}
+
+// Identifies one BridgeTransfer action of an admitted delegate.
+pub fn quote_key(signed_delegate: &SignedDelegateAction, index: usize) -> String {
+    indexed_quote_key(&fees::hold_key(signed_delegate), index)
+}
+
+fn indexed_quote_key(hold_key: &str, index: usize) -> String {
+    format!("{}:{}", hold_key, index)
+}
+
+pub fn set_fee_schedule(relayer: &mut Relayer, chain: String, token: String, schedule: Option<BridgeFeeSchedule>) -> Result<(), RelayerError> {
+    let caller = env::predecessor_account_id();
+    if !relayer.has_role(Role::FeeManager, &caller) {
+        return Err(RelayerError::Unauthorized);
+    }
+    if !relayer.chains.contains_key(&chain) {
+        return Err(RelayerError::MpcNotConfigured);
+    }
+    let key = schedule_key(&chain, &token);
+    match &schedule {
+        Some(fee_schedule) => {
+            let surge_valid = fee_schedule.surge.as_ref().is_none_or(|surge| surge.multiplier_bps >= 10_000);
+            if fee_schedule.bps > 10_000 || fee_schedule.min_fee.0 > fee_schedule.max_fee.0 || !surge_valid {
+                return Err(RelayerError::InvalidFeeSchedule);
+            }
+            // Basis points are taken of the bridged amount, so they only make sense when the
+            // fee is paid in that same token
+            if fee_schedule.bps > 0 && relayer.chains.get(&chain).is_some_and(|config| fee_asset(config) != token) {
+                return Err(RelayerError::InvalidFeeSchedule);
+            }
+            relayer.bridge_fee_schedules.insert(key, fee_schedule.clone());
+        }
+        None => {
+            relayer.bridge_fee_schedules.remove(&key);
+        }
+    }
+    RelayerEvent::BridgeFeeScheduleUpdated { chain, token, schedule }.emit();
+    Ok(())
+}
+
+// The fee for bridging `amount` of `token`, in the chain's fee asset. Without a schedule the
+// chain's flat fee applies. Surge counts the chain's pending transfers plus `queued` ones
+// that will start first; caps are applied last.
+pub fn quote(relayer: &Relayer, chain: &str, token: &str, amount: u128, queued: u64) -> Result<BridgeFeeQuote, RelayerError> {
+    let config = chains::enabled_chain(relayer, chain)?;
+    let asset = fee_asset(config).to_string();
+    let Some(schedule) = relayer.bridge_fee_schedules.get(&schedule_key(chain, token)) else {
+        return Ok(BridgeFeeQuote { fee: config.fee, asset, surge_applied: false });
+    };
+    // A chain re-registered with another fee asset leaves only the flat part meaningful
+    let proportional = if asset == token { amount.saturating_mul(schedule.bps as u128) / 10_000 } else { 0 };
+    let mut fee = schedule.flat.0.saturating_add(proportional);
+    let pending = relayer.pending_chain_transfers.get(chain).map_or(0, |ids| ids.len() as u64).saturating_add(queued);
+    let surge = schedule.surge.as_ref().filter(|surge| pending >= surge.pending_threshold);
+    if let Some(surge) = surge {
+        fee = fee.saturating_mul(surge.multiplier_bps as u128) / 10_000;
+    }
+    Ok(BridgeFeeQuote {
+        fee: U128(fee.clamp(schedule.min_fee.0, schedule.max_fee.0)),
+        asset,
+        surge_applied: surge.is_some(),
+    })
+}
+
+// Bridge fees are taken while the delegate is admitted, when the relay call's deposit is
+// still available, and held in the sender's escrow until execute_action binds them to a
+// transfer. NEAR fees come from the attached deposit, token fees from fee credits. Each
+// transfer's quote is kept so execution charges exactly what was escrowed for it, and its
+// fee stays locked in escrow until the transfer claims it or the quote is released.
+pub fn escrow_fees(relayer: &mut Relayer, signed_delegate: &SignedDelegateAction, available_deposit: &mut u128) -> Result<(), RelayerError> {
+    let sender_id = &signed_delegate.delegate_action.sender_id;
+    let actions = &signed_delegate.delegate_action.actions;
+    for (index, action) in actions.iter().enumerate() {
+        let Action::BridgeTransfer { token, amount, destination_chain, .. } = action else {
+            continue;
+        };
+        // Earlier transfers in this delegate will be pending by the time this one starts
+        let queued = actions[..index].iter()
+            .filter(|earlier| matches!(earlier, Action::BridgeTransfer { destination_chain: chain, .. } if chain == destination_chain))
+            .count() as u64;
+        let quote = quote(relayer, destination_chain, token, amount.0, queued)?;
+        let (fee, asset) = (quote.fee.0, quote.asset.clone());
+        if fee == 0 {
+            continue;
+        }
+        relayer.bridge_fee_quotes.insert(quote_key(signed_delegate, index), quote);
+        if asset == fees::NEAR_ASSET {
+            if *available_deposit < fee {
+                return Err(RelayerError::InsufficientDeposit);
+            }
+            *available_deposit -= fee;
+        } else {
+            let key = fees::credit_key(sender_id, &asset);
+            let credit = relayer.fee_credits.get(&key).copied().unwrap_or(0);
+            if credit < fee {
+                return Err(RelayerError::InsufficientDeposit);
+            }
+            relayer.fee_credits.insert(key, credit - fee);
+        }
+        let key = fees::credit_key(sender_id, &asset);
+        *relayer.bridge_escrow.entry(key.clone()).or_insert(0) += fee;
+        *relayer.locked_escrow.entry(key).or_insert(0) += fee;
+        RelayerEvent::BridgeFeeEscrowed { sender_id: sender_id.clone(), asset, amount: U128(fee) }.emit();
+    }
+    Ok(())
+}
+
+// Moves a transfer's fee out of the sender's unbound escrow onto the transfer.
+pub fn claim_escrow(relayer: &mut Relayer, sender_id: &AccountId, asset: &str, fee: u128) -> Result<(), RelayerError> {
+    if fee == 0 {
+        return Ok(());
+    }
+    let key = fees::credit_key(sender_id, asset);
+    let escrowed = relayer.bridge_escrow.get(&key).copied().unwrap_or(0);
+    if escrowed < fee {
+        return Err(RelayerError::FeeRequired);
+    }
+    if escrowed == fee {
+        relayer.bridge_escrow.remove(&key);
+    } else {
+        relayer.bridge_escrow.insert(key.clone(), escrowed - fee);
+    }
+    unlock(relayer, &key, fee);
+    Ok(())
+}
+
+fn unlock(relayer: &mut Relayer, key: &str, fee: u128) {
+    let locked = relayer.locked_escrow.get(key).copied().unwrap_or(0).saturating_sub(fee);
+    if locked == 0 {
+        relayer.locked_escrow.remove(key);
+    } else {
+        relayer.locked_escrow.insert(key.to_string(), locked);
+    }
+}
+
+// Drops the quotes of a delegate's transfers that never started, e.g. when the auth callback
+// rejected the delegate or an earlier step failed. Their fees stay in escrow, unlocked, for the
+// sender to withdraw.
+pub fn release_quotes(relayer: &mut Relayer, hold_key: &str, sender_id: &AccountId, action_count: usize) {
+    for index in 0..action_count {
+        if let Some(quote) = relayer.bridge_fee_quotes.remove(&indexed_quote_key(hold_key, index)) {
+            unlock(relayer, &fees::credit_key(sender_id, &quote.asset), quote.fee.0);
+        }
+    }
+}
+
+// Escrowed NEAR goes back to the sender; token fees go back to their fee credits.
+fn refund_fee(relayer: &mut Relayer, record: &BridgeTransferRecord) {
+    refund(relayer, &record.sender_id, &record.fee_asset, record.fee.0);
+}
+
+fn refund(relayer: &mut Relayer, sender_id: &AccountId, asset: &str, amount: u128) {
+    if asset == fees::NEAR_ASSET {
+        Promise::new(sender_id.clone()).transfer(NearToken::from_yoctonear(amount));
+    } else {
+        *relayer.fee_credits.entry(fees::credit_key(sender_id, asset)).or_insert(0) += amount;
+    }
+    env::log_str(&format!("Refunded {} {} to {}", amount, asset, sender_id));
+}
+
+fn release_fee(record: &BridgeTransferRecord) {
+    if record.fee.0 > 0 {
+        RelayerEvent::BridgeFeeCollected { id: record.id, asset: record.fee_asset.clone(), amount: record.fee }.emit();
+    }
+}
+
+// Returns escrowed fees that no transfer claimed and no in-flight delegate still holds.
+pub fn withdraw_escrow(relayer: &mut Relayer, asset: String) -> Result<(), RelayerError> {
+    let sender_id = env::predecessor_account_id();
+    let key = fees::credit_key(&sender_id, &asset);
+    let escrowed = relayer.bridge_escrow.get(&key).copied().unwrap_or(0);
+    let amount = escrowed.saturating_sub(relayer.locked_escrow.get(&key).copied().unwrap_or(0));
+    if amount == 0 {
+        return Err(RelayerError::FeeRequired);
+    }
+    if amount == escrowed {
+        relayer.bridge_escrow.remove(&key);
+    } else {
+        relayer.bridge_escrow.insert(key, escrowed - amount);
+    }
+    refund(relayer, &sender_id, &asset, amount);
+    RelayerEvent::BridgeEscrowWithdrawn { sender_id, asset, amount: U128(amount) }.emit();
+    Ok(())
+}
+
+// Builds the MPC sign call for a locked transfer, settled by handle_bridge_transfer_result.
+pub fn sign_promise(relayer: &Relayer, record: &BridgeTransferRecord, request_id: u64) -> Result<Promise, RelayerError> {
+    let chain = relayer.chains.get(&record.destination_chain).ok_or(RelayerError::MpcNotConfigured)?;
+    let payload = BridgeTransferPayload {
+        token: record.token.clone(),
+        amount: record.amount.0,
+        destination_chain: record.destination_chain.clone(),
+        recipient: record.recipient.clone(),
+        sender: record.sender_id.clone(),
+        nonce: record.nonce,
+    };
+    let payload_bytes = borsh::to_vec(&payload).map_err(|_| RelayerError::InvalidAccountId)?;
+    let sign_promise = Promise::new(chain.mpc_contract.clone())
+        .function_call(
+            "sign".to_string(),
+            borsh::to_vec(&SignRequest {
+                payload: payload_bytes,
+                path: chain.derivation_path(&record.sender_id, ""),
+                key_version: chain.key_version,
+                request_id,
+            }).map_err(|_| RelayerError::InvalidAccountId)?,
+            NearToken::from_yoctonear(0),
+            Gas::from_gas(relayer.cross_contract_gas)
+        );
+    Ok(sign_promise.then(
+        ext_self::ext(env::current_account_id())
+            .with_static_gas(TRANSFER_CALLBACK_GAS)
+            .handle_bridge_transfer_result(record.id)
+    ))
+}
+
+// Signed transfers are completed once a bridge operator sees them land on the destination chain.
+pub fn complete(relayer: &mut Relayer, id: u64) -> Result<(), RelayerError> {
+    let caller = env::predecessor_account_id();
+    if !relayer.has_role(Role::BridgeOperator, &caller) {
+        return Err(RelayerError::Unauthorized);
+    }
+    let record = advance(relayer, id, TransferStatus::Completed)?;
+    release_fee(&record);
+    RelayerEvent::BridgeTransferCompleted {
+        id,
+        token: record.token,
+        amount: record.amount,
+        destination_chain: record.destination_chain,
+        recipient: record.recipient,
+        sender: record.sender_id,
+        signature: record.signature.map(|signature| signature.0).unwrap_or_default(),
+    }.emit();
+    Ok(())
+}
+
+pub fn by_sender(relayer: &Relayer, account_id: &AccountId, from_index: Option<u64>, limit: Option<u64>) -> Vec<BridgeTransferRecord> {
+    relayer.sender_transfers.get(account_id).map(|ids| ids.as_slice()).unwrap_or_default().iter()
+        .skip(from_index.unwrap_or(0) as usize)
+        .take(limit.unwrap_or(50) as usize)
+        .filter_map(|id| relayer.bridge_transfers.get(id).cloned())
+        .collect()
+}
+
+pub fn pending(relayer: &Relayer, chain: &str) -> Vec<BridgeTransferRecord> {
+    relayer.pending_chain_transfers.get(chain).map(|ids| ids.as_slice()).unwrap_or_default().iter()
+        .filter_map(|id| relayer.bridge_transfers.get(id).cloned())
+        .collect()
+}
+
+pub fn set_bridge_timeout(relayer: &mut Relayer, timeout_ms: u64) -> Result<(), RelayerError> {
+    let caller = env::predecessor_account_id();
+    if !relayer.has_role(Role::ConfigAdmin, &caller) {
+        return Err(RelayerError::Unauthorized);
+    }
+    if timeout_ms < MIN_BRIDGE_TIMEOUT_MS {
+        return Err(RelayerError::InvalidBridgeTimeout);
+    }
+    relayer.bridge_timeout_ms = timeout_ms;
+    RelayerEvent::BridgeTimeoutUpdated { timeout_ms }.emit();
+    Ok(())
+}
+
+// A transfer whose lock or sign callback hasn't moved it for bridge_timeout_ms can be failed
+// and refunded by its sender or a bridge operator. Signed transfers carry a usable signature,
+// so only force_resolve can settle them. A lock or sign receipt that still lands afterwards
+// finds the transfer settled and leaves it alone.
+pub fn reclaim(relayer: &mut Relayer, id: u64) -> Result<(), RelayerError> {
+    let caller = env::predecessor_account_id();
+    let record = relayer.bridge_transfers.get(&id).ok_or(RelayerError::TransferNotFound)?;
+    if caller != record.sender_id && !relayer.has_role(Role::BridgeOperator, &caller) {
+        return Err(RelayerError::Unauthorized);
+    }
+    if !matches!(record.status, TransferStatus::Initiated | TransferStatus::Locked) {
+        return Err(RelayerError::InvalidTransferStatus);
+    }
+    let last_update_ms = record.history.last().map(|step| step.timestamp_ms).unwrap_or(0);
+    if env::block_timestamp_ms() < last_update_ms.saturating_add(relayer.bridge_timeout_ms) {
+        return Err(RelayerError::TransferNotExpired);
+    }
+    fail(relayer, id)?;
+    RelayerEvent::BridgeTransferReclaimed { id, reclaimed_by: caller }.emit();
+    Ok(())
+}
+
+// Whether the lock and sign callbacks should ignore the transfer, because it was reclaimed or
+// resolved while their receipts were in flight.
+pub fn is_settled(relayer: &Relayer, id: u64) -> bool {
+    relayer.bridge_transfers.get(&id).is_none_or(|record| record.status.is_terminal())
+}
+
+// Settles a transfer outside the normal lifecycle, e.g. after an off-chain investigation.
+// Refunded returns the fee unless it was already refunded.
+pub fn force_resolve(relayer: &mut Relayer, id: u64, outcome: TransferResolution) -> Result<(), RelayerError> {
+    let caller = env::predecessor_account_id();
+    if !relayer.has_role(Role::Owner, &caller) {
+        return Err(RelayerError::Unauthorized);
+    }
+    let record = relayer.bridge_transfers.get(&id).ok_or(RelayerError::TransferNotFound)?.clone();
+    if matches!(record.status, TransferStatus::Completed | TransferStatus::Refunded) {
+        return Err(RelayerError::InvalidTransferStatus);
+    }
+    let status = match outcome {
+        TransferResolution::Completed => {
+            release_fee(&record);
+            TransferStatus::Completed
+        }
+        TransferResolution::Refunded => {
+            if record.fee.0 > 0 {
+                refund_fee(relayer, &record);
+            }
+            TransferStatus::Refunded
+        }
+    };
+    record_step(relayer, id, status)?;
+    RelayerEvent::BridgeTransferForceResolved { id, previous_status: record.status, outcome: status, resolved_by: caller }.emit();
+    Ok(())
+}
@@ -0,0 +1,79 @@
+use near_sdk::{env, AccountId};
+use near_sdk::json_types::U128;
+use crate::state::Relayer;
+use crate::types::{AddressFormat, ChainConfig, GovernanceAction, Role};
+use crate::errors::RelayerError;
+use crate::events::RelayerEvent;
+use crate::governance;
+
+// A template without the sender would let accounts sign under each other's keys.
+pub fn validate(config: &ChainConfig) -> Result<(), RelayerError> {
+    if config.min_amount.0 > config.max_amount.0 || config.max_amount.0 == 0 || !config.derivation_path.contains("{sender_id}") {
+        return Err(RelayerError::InvalidChainConfig);
+    }
+    Ok(())
+}
+
+// Chains from the old chain -> signer mapping keep their signer and stay usable; bounds and
+// fees start open and can be tightened by registering the chain again.
+pub fn legacy_config(chain: &str, mpc_contract: AccountId) -> ChainConfig {
+    ChainConfig {
+        mpc_contract,
+        derivation_path: format!("{{sender_id}}/{}/{{path}}", chain),
+        key_version: 0,
+        address_format: AddressFormat::Evm,
+        min_amount: U128(0),
+        max_amount: U128(u128::MAX),
+        fee: U128(0),
+        fee_token: None,
+        enabled: true,
+    }
+}
+
+// Adding a chain or changing its signer goes through the governance delay.
+pub fn register_chain(relayer: &mut Relayer, chain: String, config: ChainConfig) -> Result<u64, RelayerError> {
+    validate(&config)?;
+    if config.fee_token.as_ref().is_some_and(|token| !relayer.fee_tokens.contains(token)) {
+        return Err(RelayerError::FeeTokenNotAllowed);
+    }
+    governance::schedule(relayer, GovernanceAction::RegisterChain { chain, config })
+}
+
+pub fn remove_chain(relayer: &mut Relayer, chain: String) -> Result<(), RelayerError> {
+    let caller = env::predecessor_account_id();
+    if !relayer.has_role(Role::BridgeOperator, &caller) {
+        return Err(RelayerError::Unauthorized);
+    }
+    relayer.chains.remove(&chain).ok_or(RelayerError::MpcNotConfigured)?;
+    RelayerEvent::ChainRemoved { chain }.emit();
+    Ok(())
+}
+
+// Switching a vetted chain on or off takes effect immediately.
+pub fn set_chain_enabled(relayer: &mut Relayer, chain: String, enabled: bool) -> Result<(), RelayerError> {
+    let caller = env::predecessor_account_id();
+    if !relayer.has_role(Role::BridgeOperator, &caller) {
+        return Err(RelayerError::Unauthorized);
+    }
+    let config = relayer.chains.get_mut(&chain).ok_or(RelayerError::MpcNotConfigured)?;
+    config.enabled = enabled;
+    RelayerEvent::ChainStatusUpdated { chain, enabled }.emit();
+    Ok(())
+}
+
+// Chains have no default signer; a chain must be registered and enabled before use.
+pub fn enabled_chain<'a>(relayer: &'a Relayer, chain: &str) -> Result<&'a ChainConfig, RelayerError> {
+    let config = relayer.chains.get(chain).ok_or(RelayerError::MpcNotConfigured)?;
+    if !config.enabled {
+        return Err(RelayerError::ChainDisabled);
+    }
+    Ok(config)
+}
+
+pub fn list(relayer: &Relayer, from_index: Option<u64>, limit: Option<u64>) -> Vec<(String, ChainConfig)> {
+    relayer.chains.iter()
+        .skip(from_index.unwrap_or(0) as usize)
+        .take(limit.unwrap_or(50) as usize)
+        .map(|(chain, config)| (chain.clone(), config.clone()))
+        .collect()
+}
@@ -13,6 +13,49 @@ pub enum RelayerError {
     FeeTooLow,
     InsufficientSignatures,
     MissingInput,
+    StaleNonce,
+    NonceAlreadyUsed,
+    DelegateExpired,
+    Paused,
+    TooManyActions,
+    FeeRequired,
+    InvalidFeeAction,
+    FeeTokenNotAllowed,
+    DuplicateSigner,
+    InvalidSignerSet,
+    LastOwner,
+    NoPendingProposal,
+    ProposalExpired,
+    ProposalNotFound,
+    ProposalNotReady,
+    NoStagedUpgrade,
+    CodeHashMismatch,
+    QuotaExceeded,
+    InvalidTier,
+    BudgetExhausted,
+    ReceiverNotAllowed,
+    MethodNotAllowed,
+    PolicyLimitExceeded,
+    InvalidPolicy,
+    GasRequestTooLarge,
+    UnsupportedAction,
+    InvalidSession,
+    SessionNotFound,
+    SessionExpired,
+    SessionScopeExceeded,
+    SessionSpendCapReached,
+    MpcNotConfigured,
+    ChainDisabled,
+    InvalidChainConfig,
+    AmountTooHigh,
+    TransferNotFound,
+    InvalidTransferStatus,
+    TransferNotExpired,
+    InvalidBridgeTimeout,
+    InvalidFeeSchedule,
+    InvalidWebAuthnConfig,
+    TooManyPendingTransfers,
+    UnsupportedNativeAction(String),
 }
 
 impl FunctionError for RelayerError {
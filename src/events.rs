@@ -1,5 +1,6 @@
-use near_sdk::{near, AccountId};
+use near_sdk::{near, AccountId, PublicKey};
 use near_sdk::json_types::U128;
+use crate::types::{BridgeFeeSchedule, BridgeTransferRecord, ChainConfig, GovernanceAction, QuotaTier, ReceiverPolicy, Role, SessionScope, SpendRecord, Subsystem, TransferStatus, WebAuthnConfig};
 
 #[near(event_json(standard = "nep297"))]
 pub enum RelayerEvent {
@@ -19,6 +20,7 @@ pub enum RelayerEvent {
     BridgeResult { sender_id: AccountId, action_type: String, result: Vec<u8> },
     #[event_version("1.0.0")]
     BridgeTransferInitiated { 
+        id: u64,
         token: String, 
         amount: U128, 
         destination_chain: String, 
@@ -28,6 +30,7 @@ pub enum RelayerEvent {
     },
     #[event_version("1.0.0")]
     BridgeTransferCompleted { 
+        id: u64,
         token: String, 
         amount: U128, 
         destination_chain: String, 
@@ -37,6 +40,7 @@ pub enum RelayerEvent {
     },
     #[event_version("1.0.0")]
     BridgeTransferFailed { 
+        id: u64,
         token: String, 
         amount: U128, 
         destination_chain: String, 
@@ -57,14 +61,42 @@ pub enum RelayerEvent {
     #[event_version("1.0.0")]
     OmniLockerContractUpdated { new_locker_contract: AccountId },
     #[event_version("1.0.0")]
-    ChainMpcMappingAdded { chain: String, mpc_contract: AccountId },
+    ChainRegistered { chain: String, config: ChainConfig },
     #[event_version("1.0.0")]
-    ChainMpcMappingRemoved { chain: String },
+    ChainRemoved { chain: String },
+    #[event_version("1.0.0")]
+    ChainStatusUpdated { chain: String, enabled: bool },
+    #[event_version("1.0.0")]
+    BridgeTransferStatusChanged { id: u64, status: TransferStatus, timestamp_ms: u64 },
+    #[event_version("1.0.0")]
+    BridgeTransferReclaimed { id: u64, reclaimed_by: AccountId },
+    #[event_version("1.0.0")]
+    BridgeTransferForceResolved { id: u64, previous_status: TransferStatus, outcome: TransferStatus, resolved_by: AccountId },
+    #[event_version("1.0.0")]
+    BridgeTransferEvicted { record: BridgeTransferRecord },
+    #[event_version("1.0.0")]
+    BridgeTimeoutUpdated { timeout_ms: u64 },
+    #[event_version("1.0.0")]
+    BridgeFeeEscrowed { sender_id: AccountId, asset: String, amount: U128 },
+    #[event_version("1.0.0")]
+    BridgeFeeCollected { id: u64, asset: String, amount: U128 },
+    #[event_version("1.0.0")]
+    BridgeEscrowWithdrawn { sender_id: AccountId, asset: String, amount: U128 },
+    #[event_version("1.0.0")]
+    BridgeFeeScheduleUpdated { chain: String, token: String, schedule: Option<BridgeFeeSchedule> },
     #[event_version("1.0.0")]
     ChunkSizeUpdated { new_size: usize },
     #[event_version("1.0.0")]
+    MaxActionsUpdated { new_max: usize },
+    #[event_version("1.0.0")]
+    ActionsExecuted { sender_id: AccountId, action_types: Vec<String>, results: Vec<bool> },
+    #[event_version("1.0.0")]
     AuthContractUpdated { new_auth_contract: AccountId },
     #[event_version("1.0.0")]
+    NonceOracleUpdated { nonce_oracle: Option<AccountId> },
+    #[event_version("1.0.0")]
+    WebAuthnConfigUpdated { config: Option<WebAuthnConfig> },
+    #[event_version("1.0.0")]
     FtWrapperContractUpdated { new_ft_wrapper_contract: AccountId },
     #[event_version("1.0.0")]
     MinBalanceUpdated { new_min: u128 },
@@ -73,11 +105,73 @@ pub enum RelayerEvent {
     #[event_version("1.0.0")]
     BaseFeeUpdated { new_fee: u128 },
     #[event_version("1.0.0")]
-    FeeCharged { action: String, fee: u128, sender: AccountId },
+    FeeCharged { action: String, fee: u128, sender: AccountId, asset: String },
+    #[event_version("1.0.0")]
+    FeeRefunded { fee: u128, sender: AccountId, payer: AccountId, asset: String },
+    #[event_version("1.0.0")]
+    DelegateRejected { sender_id: AccountId, public_key: PublicKey, nonce: u64 },
+    #[event_version("1.0.0")]
+    ActionFeeUpdated { action_type: String, asset: String, fee: u128 },
+    #[event_version("1.0.0")]
+    FeeTokenAdded { token: String },
+    #[event_version("1.0.0")]
+    FeeTokenRemoved { token: String },
+    #[event_version("1.0.0")]
+    MultisigConfigured { account_id: AccountId, signers: Vec<PublicKey>, threshold: u32 },
+    #[event_version("1.0.0")]
+    RoleGranted { role: Role, account_id: AccountId, sender: AccountId },
+    #[event_version("1.0.0")]
+    RoleRevoked { role: Role, account_id: AccountId, sender: AccountId },
+    #[event_version("1.0.0")]
+    ProposalQueued { id: u64, action: GovernanceAction, proposer: AccountId, eta_ms: u64 },
+    #[event_version("1.0.0")]
+    ProposalExecuted { id: u64, executor: AccountId },
+    #[event_version("1.0.0")]
+    ProposalCancelled { id: u64, cancelled_by: AccountId },
+    #[event_version("1.0.0")]
+    GovernanceDelayUpdated { delay_ms: u64 },
+    #[event_version("1.0.0")]
+    QuotaExceeded { account_id: AccountId, quota: String, limit: u64 },
+    #[event_version("1.0.0")]
+    QuotaTierUpdated { name: String, tier: Option<QuotaTier> },
+    #[event_version("1.0.0")]
+    AccountTierUpdated { account_id: AccountId, tier: Option<String> },
+    #[event_version("1.0.0")]
+    BudgetExhausted { account_id: AccountId, kind: String, budget: u128, spent: u128 },
+    #[event_version("1.0.0")]
+    BudgetUpdated { account_id: AccountId, kind: String, budget: Option<u128> },
+    #[event_version("1.0.0")]
+    SpendReset { account_id: AccountId, kind: String, spent: SpendRecord },
+    #[event_version("1.0.0")]
+    SpendPruned { account_id: AccountId, kind: String, spent: SpendRecord },
+    #[event_version("1.0.0")]
+    CallPolicyUpdated { receiver_id: AccountId, policy: Option<ReceiverPolicy> },
+    #[event_version("1.0.0")]
+    SessionRegistered { account_id: AccountId, public_key: PublicKey, session_nonce: u64, expires_at_ms: u64, scope: SessionScope },
+    #[event_version("1.0.0")]
+    SessionRevoked { account_id: AccountId, public_key: PublicKey, session_nonce: u64 },
+    #[event_version("1.0.0")]
+    FeeCreditDeposited { account_id: AccountId, token: String, amount: u128 },
     #[event_version("1.0.0")]
     ManagerChanged { old_manager: AccountId, new_manager: AccountId, timestamp: u64 },
     #[event_version("1.0.0")]
-    ContractUpgraded { manager: AccountId, timestamp: u64 },
+    ManagerProposed { current_manager: AccountId, proposed_manager: AccountId, expires_at_ms: Option<u64> },
+    #[event_version("1.0.0")]
+    ManagerProposalCancelled { proposed_manager: AccountId, cancelled_by: AccountId },
+    #[event_version("1.0.0")]
+    ContractUpgraded { manager: AccountId, timestamp: u64, old_code_hash: Option<String>, new_code_hash: String },
+    #[event_version("1.0.0")]
+    UpgradeStaged { code_hash: String, size: u64, staged_by: AccountId, eta_ms: u64 },
+    #[event_version("1.0.0")]
+    UpgradeAborted { code_hash: String, aborted_by: AccountId },
+    #[event_version("1.0.0")]
+    UpgradeFailed { code_hash: String },
+    #[event_version("1.0.0")]
+    UpgradeApproverUpdated { required: bool },
+    #[event_version("1.0.0")]
+    Paused { subsystems: Vec<Subsystem>, manager: AccountId, timestamp: u64 },
+    #[event_version("1.0.0")]
+    Unpaused { subsystems: Vec<Subsystem>, manager: AccountId, timestamp: u64 },
     #[event_version("1.0.0")]
     StateMigrated { old_version: String, new_version: String },
 }
@@ -0,0 +1,124 @@
+use near_sdk::{env, AccountId, Gas, NearToken, Promise};
+use crate::state::Relayer;
+use crate::types::{Action, HeldFee, SignedDelegateAction};
+use crate::errors::RelayerError;
+use crate::events::RelayerEvent;
+
+pub const NEAR_ASSET: &str = "near";
+pub const SETTLE_FEE_GAS: Gas = Gas::from_tgas(10);
+
+pub fn fee_key(action_type: &str, asset: &str) -> String {
+    format!("{}:{}", action_type, asset)
+}
+
+pub fn credit_key(account_id: &AccountId, token: &str) -> String {
+    format!("{}:{}", account_id, token)
+}
+
+pub fn hold_key(signed_delegate: &SignedDelegateAction) -> String {
+    let delegate = &signed_delegate.delegate_action;
+    format!("{}:{}:{}", delegate.sender_id, String::from(&signed_delegate.public_key), delegate.nonce)
+}
+
+pub fn required_fee(relayer: &Relayer, signed_delegate: &SignedDelegateAction, asset: &str) -> u128 {
+    signed_delegate.delegate_action.actions.iter()
+        .map(|action| relayer.action_fees.get(&fee_key(action.type_name(), asset)).copied().unwrap_or(0))
+        .sum()
+}
+
+// Runs the delegate's fee action before any of its actions are scheduled. NEAR fees are
+// taken from `available_deposit` (the deposit attached to the relay call), token fees from
+// credits the sender topped up through ft_transfer_call. The fee is held until the auth
+// callback settles, so a delegate the auth contract rejects gets it back.
+pub fn collect_fee(relayer: &mut Relayer, signed_delegate: &SignedDelegateAction, available_deposit: &mut u128) -> Result<(), RelayerError> {
+    let sender_id = &signed_delegate.delegate_action.sender_id;
+    let (fee, asset, payer) = match &signed_delegate.fee_action {
+        None => {
+            if required_fee(relayer, signed_delegate, NEAR_ASSET) > 0 {
+                return Err(RelayerError::FeeRequired);
+            }
+            return Ok(());
+        }
+        Some(Action::Transfer { deposit }) => {
+            let fee = deposit.as_yoctonear();
+            if fee < required_fee(relayer, signed_delegate, NEAR_ASSET) {
+                return Err(RelayerError::FeeTooLow);
+            }
+            if *available_deposit < fee {
+                return Err(RelayerError::InsufficientDeposit);
+            }
+            *available_deposit -= fee;
+            (fee, NEAR_ASSET.to_string(), env::predecessor_account_id())
+        }
+        Some(Action::FtTransfer { token, receiver_id, amount, .. }) => {
+            if *receiver_id != env::current_account_id() {
+                return Err(RelayerError::InvalidFeeAction);
+            }
+            if !relayer.fee_tokens.contains(token) {
+                return Err(RelayerError::FeeTokenNotAllowed);
+            }
+            // An action priced in NEAR but not in this token isn't free, it just can't be paid with it
+            let unpriced = signed_delegate.delegate_action.actions.iter().any(|action| {
+                relayer.action_fees.get(&fee_key(action.type_name(), NEAR_ASSET)).is_some_and(|fee| *fee > 0)
+                    && !relayer.action_fees.contains_key(&fee_key(action.type_name(), token))
+            });
+            if unpriced {
+                return Err(RelayerError::FeeTokenNotAllowed);
+            }
+            if amount.0 < required_fee(relayer, signed_delegate, token) {
+                return Err(RelayerError::FeeTooLow);
+            }
+            let key = credit_key(sender_id, token);
+            let credit = relayer.fee_credits.get(&key).copied().unwrap_or(0);
+            if credit < amount.0 {
+                return Err(RelayerError::InsufficientDeposit);
+            }
+            relayer.fee_credits.insert(key, credit - amount.0);
+            (amount.0, token.clone(), sender_id.clone())
+        }
+        Some(_) => return Err(RelayerError::InvalidFeeAction),
+    };
+    let action = signed_delegate.delegate_action.actions.iter()
+        .map(|action| action.type_name())
+        .collect::<Vec<_>>()
+        .join(",");
+    relayer.held_fees.insert(hold_key(signed_delegate), HeldFee { sender_id: sender_id.clone(), payer, asset: asset.clone(), amount: fee });
+    RelayerEvent::FeeCharged { action, fee, sender: sender_id.clone(), asset }.emit();
+    Ok(())
+}
+
+// Releases a held fee once the delegate's auth callback has run, refunding it to whoever
+// paid when the callback failed.
+pub fn settle_held_fee(relayer: &mut Relayer, key: &str, authorized: bool) -> Option<HeldFee> {
+    let held = relayer.held_fees.remove(key)?;
+    if authorized {
+        return Some(held);
+    }
+    if held.asset == NEAR_ASSET {
+        Promise::new(held.payer.clone()).transfer(NearToken::from_yoctonear(held.amount));
+    } else {
+        let credit_key = credit_key(&held.payer, &held.asset);
+        let credit = relayer.fee_credits.get(&credit_key).copied().unwrap_or(0);
+        relayer.fee_credits.insert(credit_key, credit + held.amount);
+    }
+    RelayerEvent::FeeRefunded { fee: held.amount, sender: held.sender_id.clone(), payer: held.payer.clone(), asset: held.asset.clone() }.emit();
+    Some(held)
+}
+
+pub fn refund_deposit(available_deposit: u128) {
+    if available_deposit > 0 {
+        Promise::new(env::predecessor_account_id())
+            .transfer(NearToken::from_yoctonear(available_deposit));
+    }
+}
+
+pub fn credit_fee_token(relayer: &mut Relayer, sender_id: AccountId, token: String, amount: u128) -> Result<(), RelayerError> {
+    if !relayer.fee_tokens.contains(&token) {
+        return Err(RelayerError::FeeTokenNotAllowed);
+    }
+    let key = credit_key(&sender_id, &token);
+    let credit = relayer.fee_credits.get(&key).copied().unwrap_or(0);
+    relayer.fee_credits.insert(key, credit + amount);
+    RelayerEvent::FeeCreditDeposited { account_id: sender_id, token, amount }.emit();
+    Ok(())
+}
@@ -0,0 +1,111 @@
+use near_sdk::{env, AccountId};
+use crate::state::Relayer;
+use crate::types::{ChainConfig, GovernanceAction, GovernanceProposal, Role};
+use crate::errors::RelayerError;
+use crate::chains;
+use crate::events::RelayerEvent;
+
+pub const MAX_GOVERNANCE_DELAY_MS: u64 = 30 * 24 * 60 * 60 * 1000;
+
+pub fn required_role(action: &GovernanceAction) -> Role {
+    match action {
+        GovernanceAction::SetAuthContract { .. } | GovernanceAction::SetFtWrapperContract { .. } | GovernanceAction::SetNonceOracle { .. } | GovernanceAction::SetWebAuthnConfig { .. } => Role::ConfigAdmin,
+        GovernanceAction::SetOmniLockerContract { .. } | GovernanceAction::AddChainMpcMapping { .. } | GovernanceAction::RegisterChain { .. } => Role::BridgeOperator,
+        GovernanceAction::SetGovernanceDelay { .. } | GovernanceAction::SetUpgradeApprover { .. } => Role::Owner,
+    }
+}
+
+// Queues a privileged change; it can be executed once `governance_delay_ms` has passed.
+pub fn schedule(relayer: &mut Relayer, action: GovernanceAction) -> Result<u64, RelayerError> {
+    let caller = env::predecessor_account_id();
+    if !relayer.has_role(required_role(&action), &caller) {
+        return Err(RelayerError::Unauthorized);
+    }
+    let id = relayer.next_proposal_id;
+    relayer.next_proposal_id += 1;
+    let eta_ms = env::block_timestamp_ms().saturating_add(relayer.governance_delay_ms);
+    RelayerEvent::ProposalQueued { id, action: action.clone(), proposer: caller.clone(), eta_ms }.emit();
+    relayer.proposals.insert(id, GovernanceProposal { id, action, proposer: caller, eta_ms });
+    Ok(id)
+}
+
+// Removes a proposal that is past its ETA and may be run by the caller.
+fn take_ready(relayer: &mut Relayer, id: u64, caller: &AccountId) -> Result<GovernanceProposal, RelayerError> {
+    let proposal = relayer.proposals.get(&id).ok_or(RelayerError::ProposalNotFound)?;
+    if !relayer.has_role(required_role(&proposal.action), caller) {
+        return Err(RelayerError::Unauthorized);
+    }
+    if env::block_timestamp_ms() < proposal.eta_ms {
+        return Err(RelayerError::ProposalNotReady);
+    }
+    let proposal = relayer.proposals.remove(&id).ok_or(RelayerError::ProposalNotFound)?;
+    RelayerEvent::ProposalExecuted { id, executor: caller.clone() }.emit();
+    Ok(proposal)
+}
+
+pub fn execute(relayer: &mut Relayer, id: u64) -> Result<(), RelayerError> {
+    let caller = env::predecessor_account_id();
+    let proposal = take_ready(relayer, id, &caller)?;
+    match proposal.action {
+        GovernanceAction::SetAuthContract { new_auth_contract } => {
+            relayer.auth_contract = new_auth_contract.clone();
+            RelayerEvent::AuthContractUpdated { new_auth_contract }.emit();
+        }
+        GovernanceAction::SetFtWrapperContract { new_ft_wrapper_contract } => {
+            relayer.ft_wrapper_contract = new_ft_wrapper_contract.clone();
+            RelayerEvent::FtWrapperContractUpdated { new_ft_wrapper_contract }.emit();
+        }
+        GovernanceAction::SetOmniLockerContract { new_locker_contract } => {
+            relayer.omni_locker_contract.set(Some(new_locker_contract.clone()));
+            RelayerEvent::OmniLockerContractUpdated { new_locker_contract }.emit();
+        }
+        GovernanceAction::AddChainMpcMapping { chain, mpc_contract } => {
+            // Proposals queued before the chain registry only name a signer
+            let config = match relayer.chains.get(&chain) {
+                Some(config) => ChainConfig { mpc_contract, ..config.clone() },
+                None => chains::legacy_config(&chain, mpc_contract),
+            };
+            relayer.chains.insert(chain.clone(), config.clone());
+            RelayerEvent::ChainRegistered { chain, config }.emit();
+        }
+        GovernanceAction::RegisterChain { chain, config } => {
+            relayer.chains.insert(chain.clone(), config.clone());
+            RelayerEvent::ChainRegistered { chain, config }.emit();
+        }
+        GovernanceAction::SetNonceOracle { nonce_oracle } => {
+            relayer.nonce_oracle = nonce_oracle.clone();
+            RelayerEvent::NonceOracleUpdated { nonce_oracle }.emit();
+        }
+        GovernanceAction::SetWebAuthnConfig { config } => {
+            relayer.webauthn = config.clone();
+            RelayerEvent::WebAuthnConfigUpdated { config }.emit();
+        }
+        GovernanceAction::SetGovernanceDelay { delay_ms } => {
+            relayer.governance_delay_ms = delay_ms;
+            RelayerEvent::GovernanceDelayUpdated { delay_ms }.emit();
+        }
+        GovernanceAction::SetUpgradeApprover { required } => {
+            relayer.upgrade_approver_required = required;
+            RelayerEvent::UpgradeApproverUpdated { required }.emit();
+        }
+    }
+    Ok(())
+}
+
+pub fn cancel(relayer: &mut Relayer, id: u64) -> Result<(), RelayerError> {
+    let caller = env::predecessor_account_id();
+    let proposal = relayer.proposals.get(&id).ok_or(RelayerError::ProposalNotFound)?;
+    if !relayer.has_role(required_role(&proposal.action), &caller) {
+        return Err(RelayerError::Unauthorized);
+    }
+    relayer.proposals.remove(&id);
+    RelayerEvent::ProposalCancelled { id, cancelled_by: caller }.emit();
+    Ok(())
+}
+
+pub fn set_governance_delay(relayer: &mut Relayer, delay_ms: u64) -> Result<u64, RelayerError> {
+    if delay_ms > MAX_GOVERNANCE_DELAY_MS {
+        return Err(RelayerError::AmountTooLow);
+    }
+    schedule(relayer, GovernanceAction::SetGovernanceDelay { delay_ms })
+}
@@ -0,0 +1,176 @@
+use near_sdk::{env, AccountId};
+use near_sdk::json_types::U128;
+use near_sdk::store::{IterableMap, LookupMap};
+use crate::state::Relayer;
+use crate::types::{Action, SignedDelegateAction, SpendRecord, Role};
+use crate::errors::RelayerError;
+use crate::events::RelayerEvent;
+use crate::{bridge, policy, quota, relay};
+
+// Protocol minimum gas price in yoctoNEAR per gas unit; used to price committed gas.
+pub const ESTIMATED_GAS_PRICE: u128 = 100_000_000;
+// Fees for creating one chained function call receipt. They come out of the creating call's
+// gas on top of the gas attached to the receipt.
+pub const RECEIPT_GAS: u64 = 15_000_000_000_000;
+// Spend of an account without a budget is dropped after this long without a relay.
+pub const SPEND_RETENTION_MS: u64 = 30 * 86_400_000;
+// Records probed for pruning whenever a new account is added. Probing two per insert keeps
+// stale records at most about as many as active ones.
+const PRUNE_PROBES: usize = 2;
+
+// Gas the relayer attaches for an action, mirroring the static gas used in execute_action
+// and including the callbacks the action schedules for itself and their receipt fees. Receipt
+// actions share their step's receipt, which steps_gas counts.
+pub fn action_gas(relayer: &Relayer, action: &Action) -> u64 {
+    match action {
+        Action::FunctionCall { gas, .. } => policy::function_call_gas(relayer, *gas).as_gas(),
+        // The sign call and handle_mpc_signature
+        Action::ChainSignatureRequest { .. } => relayer.cross_contract_gas.saturating_add(RECEIPT_GAS).saturating_mul(2),
+        // The lock, then handle_transfer_locked with the sign call and its callback
+        Action::BridgeTransfer { .. } => relayer.cross_contract_gas
+            .saturating_add(RECEIPT_GAS.saturating_mul(2))
+            .saturating_add(bridge::transfer_locked_gas(relayer).as_gas()),
+        Action::FtTransfer { .. } => relay::ft_transfer_gas(relayer),
+        Action::Transfer { .. } | Action::AddKey { .. } => 0,
+    }
+}
+
+// NEAR the relayer attaches from its own balance for an action.
+pub fn action_deposit(action: &Action) -> u128 {
+    match action {
+        Action::FunctionCall { deposit, .. } | Action::Transfer { deposit } => deposit.as_yoctonear(),
+        Action::ChainSignatureRequest { .. } => 1,
+        _ => 0,
+    }
+}
+
+pub fn estimate(relayer: &Relayer, signed_delegate: &SignedDelegateAction) -> SpendRecord {
+    let delegate = &signed_delegate.delegate_action;
+    let actions = &delegate.actions;
+    let gas = actions.iter()
+        .map(|action| action_gas(relayer, action))
+        .fold(quota::relay_gas(relayer), u64::saturating_add);
+    let deposit = actions.iter().map(action_deposit).sum::<u128>();
+    SpendRecord {
+        relays: 1,
+        actions: actions.len() as u64,
+        gas,
+        deposit: U128(deposit),
+        estimated_cost: U128(gas as u128 * ESTIMATED_GAS_PRICE + deposit),
+        last_relay_ms: env::block_timestamp_ms(),
+    }
+}
+
+fn check_budget(budget: Option<u128>, spent: &SpendRecord, cost: u128, account_id: &AccountId, kind: &str) -> Result<(), RelayerError> {
+    if let Some(budget) = budget {
+        if spent.estimated_cost.0.saturating_add(cost) > budget {
+            RelayerEvent::BudgetExhausted {
+                account_id: account_id.clone(),
+                kind: kind.to_string(),
+                budget,
+                spent: spent.estimated_cost.0,
+            }.emit();
+            return Err(RelayerError::BudgetExhausted);
+        }
+    }
+    Ok(())
+}
+
+// Records what a delegate costs against its sender and the app it calls (receiver_id),
+// refusing sponsorship once either budget would be exceeded. Returns the recorded spend.
+// The ledger prices the gas the relayer commits when it admits a delegate, not the gas the
+// delegate ends up burning: budgets cap the relayer's exposure, and gas the protocol refunds
+// is not credited back. Only a rejected delegate is taken off again, through release.
+pub fn record(relayer: &mut Relayer, signed_delegate: &SignedDelegateAction) -> Result<SpendRecord, RelayerError> {
+    let delegate = &signed_delegate.delegate_action;
+    let spend = estimate(relayer, signed_delegate);
+    let mut sender_spend = relayer.sender_spend.get(&delegate.sender_id).cloned().unwrap_or_default();
+    let mut app_spend = relayer.app_spend.get(&delegate.receiver_id).cloned().unwrap_or_default();
+    check_budget(relayer.sender_budgets.get(&delegate.sender_id).copied(), &sender_spend, spend.estimated_cost.0, &delegate.sender_id, "sender")?;
+    check_budget(relayer.app_budgets.get(&delegate.receiver_id).copied(), &app_spend, spend.estimated_cost.0, &delegate.receiver_id, "app")?;
+    if sender_spend.relays == 0 {
+        prune_idle(&mut relayer.sender_spend, &relayer.sender_budgets, "sender");
+    }
+    if app_spend.relays == 0 {
+        prune_idle(&mut relayer.app_spend, &relayer.app_budgets, "app");
+    }
+    sender_spend.add(&spend);
+    app_spend.add(&spend);
+    relayer.sender_spend.insert(delegate.sender_id.clone(), sender_spend);
+    relayer.app_spend.insert(delegate.receiver_id.clone(), app_spend);
+    Ok(spend)
+}
+
+// Takes a rejected delegate's spend back off its sender and app, dropping records left empty.
+pub fn release(relayer: &mut Relayer, sender_id: &AccountId, receiver_id: &AccountId, spend: &SpendRecord) {
+    release_from(&mut relayer.sender_spend, sender_id, spend);
+    release_from(&mut relayer.app_spend, receiver_id, spend);
+}
+
+fn release_from(spends: &mut IterableMap<AccountId, SpendRecord>, account_id: &AccountId, spend: &SpendRecord) {
+    let Some(record) = spends.get_mut(account_id) else { return };
+    record.sub(spend);
+    if record.relays == 0 {
+        spends.remove(account_id);
+    }
+}
+
+// Drops probed records idle for longer than SPEND_RETENTION_MS, so the ledger grows with
+// the accounts still relaying rather than with every account ever sponsored. Accounts with
+// a budget are kept since their budget is enforced against the record. The dropped spend
+// is emitted for indexers that bill from the ledger.
+fn prune_idle(spends: &mut IterableMap<AccountId, SpendRecord>, budgets: &LookupMap<AccountId, u128>, kind: &str) {
+    let seed = env::random_seed();
+    let now = env::block_timestamp_ms();
+    for probe in 0..PRUNE_PROBES {
+        let len = spends.len() as usize;
+        if len == 0 {
+            return;
+        }
+        let index = u32::from_le_bytes(seed[probe * 4..probe * 4 + 4].try_into().unwrap()) as usize % len;
+        // The map keeps its keys in a store Vector, whose iterator seeks by index: this is one
+        // read of the key at `index` and one of its record, however large the ledger grows
+        let Some(account_id) = spends.keys().nth(index).cloned() else { return };
+        let idle = spends.get(&account_id).is_some_and(|record| now.saturating_sub(record.last_relay_ms) >= SPEND_RETENTION_MS);
+        if !idle || budgets.contains_key(&account_id) {
+            continue;
+        }
+        let spent = spends.remove(&account_id).unwrap_or_default();
+        RelayerEvent::SpendPruned { account_id, kind: kind.to_string(), spent }.emit();
+    }
+}
+
+pub fn set_budget(relayer: &mut Relayer, account_id: AccountId, is_app: bool, budget: Option<u128>) -> Result<(), RelayerError> {
+    let caller = env::predecessor_account_id();
+    if !relayer.has_role(Role::FeeManager, &caller) {
+        return Err(RelayerError::Unauthorized);
+    }
+    let budgets = if is_app { &mut relayer.app_budgets } else { &mut relayer.sender_budgets };
+    match budget {
+        Some(budget) => budgets.insert(account_id.clone(), budget),
+        None => budgets.remove(&account_id),
+    };
+    let kind = if is_app { "app" } else { "sender" }.to_string();
+    RelayerEvent::BudgetUpdated { account_id, kind, budget }.emit();
+    Ok(())
+}
+
+// Clears recorded spend, e.g. once a partner app has been billed for a period.
+pub fn reset_spend(relayer: &mut Relayer, account_id: AccountId, is_app: bool) -> Result<(), RelayerError> {
+    let caller = env::predecessor_account_id();
+    if !relayer.has_role(Role::FeeManager, &caller) {
+        return Err(RelayerError::Unauthorized);
+    }
+    let spent = if is_app { relayer.app_spend.remove(&account_id) } else { relayer.sender_spend.remove(&account_id) };
+    let kind = if is_app { "app" } else { "sender" }.to_string();
+    RelayerEvent::SpendReset { account_id, kind, spent: spent.unwrap_or_default() }.emit();
+    Ok(())
+}
+
+pub fn list(map: &IterableMap<AccountId, SpendRecord>, from_index: Option<u64>, limit: Option<u64>) -> Vec<(AccountId, SpendRecord)> {
+    map.iter()
+        .skip(from_index.unwrap_or(0) as usize)
+        .take(limit.unwrap_or(50) as usize)
+        .map(|(account_id, record)| (account_id.clone(), record.clone()))
+        .collect()
+}
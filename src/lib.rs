@@ -1,8 +1,8 @@
-use near_sdk::{near, AccountId, Promise, PublicKey, NearToken, env, ext_contract, Gas, PromiseError};
+use near_sdk::{near, AccountId, Promise, PromiseOrValue, PublicKey, NearToken, env, ext_contract, Gas, PromiseError, PromiseResult};
 use near_sdk::json_types::U128;
 use near_sdk::{borsh, PanicOnDefault};
 use crate::state::Relayer;
-use crate::types::{SignedDelegateAction, Action};
+use crate::types::{SignedDelegateAction, NativeSignedDelegateAction, Action, BridgeFeeQuote, BridgeFeeSchedule, BridgeTransferRecord, ChainConfig, DelegateAction, GovernanceProposal, ManagerProposal, MultisigConfig, QuotaStatus, QuotaTier, ReceiverPolicy, Role, SessionKey, SessionScope, SigningPayload, SpendRecord, StagedUpgrade, Subsystem, TransferResolution, TransferStatus, WebAuthnConfig};
 use crate::errors::RelayerError;
 use crate::events::RelayerEvent;
 
@@ -15,15 +15,31 @@ mod relay;
 mod sponsor;
 mod balance;
 mod state_versions;
+mod fees;
+mod multisig;
+mod governance;
+mod upgrade;
+mod quota;
+mod ledger;
+mod policy;
+mod chains;
+mod nep366;
+mod session;
+mod bridge;
 
 #[ext_contract(ext_self)]
+#[allow(clippy::too_many_arguments)]
 pub trait SelfCallback {
-    fn handle_mpc_signature(&mut self, chain: String, request_id: u64, result: Vec<u8>, #[callback_result] call_result: Result<(), PromiseError>);
+    fn handle_mpc_signature(&mut self, chain: String, request_id: u64, result: Vec<u8>, #[callback_result] call_result: Result<(), PromiseError>) -> bool;
     fn handle_bridge_result(&mut self, sender_id: AccountId, action_type: String, result: Vec<u8>, #[callback_result] call_result: Result<(), PromiseError>);
-    fn handle_bridge_transfer_result(&mut self, sender_id: AccountId, token: String, amount: U128, destination_chain: String, recipient: String, signature: Vec<u8>, #[callback_result] call_result: Result<(), PromiseError>);
+    fn handle_transfer_locked(&mut self, id: u64, request_id: u64, #[callback_result] call_result: Result<(), PromiseError>) -> PromiseOrValue<bool>;
+    fn handle_bridge_transfer_result(&mut self, id: u64) -> bool;
     #[handle_result]
     fn handle_auth_result(&mut self, sender_id: AccountId, signed_delegate: SignedDelegateAction, is_authorized: bool) -> Result<Promise, RelayerError>;
+    fn handle_actions_result(&mut self, signed_delegate: SignedDelegateAction, request_id: u64, end: u32, results: Vec<bool>) -> PromiseOrValue<Vec<bool>>;
+    fn settle_delegate(&mut self, hold_key: String) -> bool;
     fn handle_registration(&mut self, account_id: AccountId, token: String, is_sender: bool, is_registered: bool) -> Promise;
+    fn handle_upgrade_result(&mut self, code_hash: String, approved_by: AccountId) -> bool;
 }
 
 #[ext_contract(ext_auth)]
@@ -75,21 +91,32 @@ impl OnSocialRelayer {
         balance::deposit(&mut self.relayer).expect("Deposit failed");
     }
 
+    #[payable]
     #[handle_result]
     pub fn relay_meta_transaction(&mut self, #[serializer(borsh)] signed_delegate: SignedDelegateAction) -> Result<Promise, RelayerError> {
         relay::relay_meta_transaction(&mut self.relayer, signed_delegate)
     }
 
+    #[payable]
     #[handle_result]
     pub fn relay_meta_transactions(&mut self, #[serializer(borsh)] signed_delegates: Vec<SignedDelegateAction>) -> Result<Vec<Promise>, RelayerError> {
         relay::relay_meta_transactions(&mut self.relayer, signed_delegates)
     }
 
+    #[payable]
     #[handle_result]
     pub fn relay_chunked_meta_transactions(&mut self, #[serializer(borsh)] signed_delegates: Vec<SignedDelegateAction>) -> Result<Vec<Promise>, RelayerError> {
         relay::relay_chunked_meta_transactions(&mut self.relayer, signed_delegates)
     }
 
+    // Accepts a protocol-level NEP-366 SignedDelegateAction as produced by standard wallets.
+    // The signature is checked against the call's input, the bytes the wallet signed.
+    #[payable]
+    #[handle_result]
+    pub fn relay_signed_delegate(&mut self, #[serializer(borsh)] signed_delegate: NativeSignedDelegateAction) -> Result<Promise, RelayerError> {
+        nep366::relay_signed_delegate(&mut self.relayer, signed_delegate, &env::input().unwrap_or_default())
+    }
+
     #[handle_result]
     pub fn sponsor_account(&mut self, #[serializer(borsh)] args: Vec<u8>) -> Result<Promise, RelayerError> {
         env::log_str(&format!("Raw args: {:?}", args));
@@ -102,6 +129,7 @@ impl OnSocialRelayer {
         sponsor::sponsor_account_with_registrar(&mut self.relayer, new_account_id, public_key, is_multi_sig, multi_sig_threshold)
     }
 
+    #[payable]
     #[handle_result]
     pub fn sponsor_account_signed(&mut self, #[serializer(borsh)] signed_delegate: SignedDelegateAction) -> Result<Promise, RelayerError> {
         sponsor::sponsor_account_signed(&mut self.relayer, signed_delegate)
@@ -111,13 +139,7 @@ impl OnSocialRelayer {
     pub fn register_existing_account(&mut self, account_id: AccountId, public_key: PublicKey, expiration_days: Option<u32>, is_multi_sig: bool, multi_sig_threshold: Option<u32>) -> Result<(), RelayerError> {
         let initial_storage = env::storage_usage();
         let result = admin::register_existing_account(&mut self.relayer, account_id, public_key, expiration_days, is_multi_sig, multi_sig_threshold);
-        let storage_used = env::storage_usage() - initial_storage;
-        let storage_cost = storage_used as u128 * env::storage_byte_cost().as_yoctonear();
-        if env::account_balance().as_yoctonear() < self.relayer.min_balance + storage_cost {
-            RelayerEvent::LowBalance { balance: env::account_balance().as_yoctonear() }.emit();
-            return Err(RelayerError::InsufficientBalance);
-        }
-        env::log_str(&format!("register_existing_account: storage_used={} bytes, storage_cost={} yoctoNEAR", storage_used, storage_cost));
+        self.check_storage_growth(initial_storage)?;
         result
     }
 
@@ -125,13 +147,7 @@ impl OnSocialRelayer {
     pub fn remove_key(&mut self, account_id: AccountId, public_key: PublicKey) -> Result<(), RelayerError> {
         let initial_storage = env::storage_usage();
         let result = admin::remove_key(&mut self.relayer, account_id, public_key);
-        let storage_used = env::storage_usage() - initial_storage;
-        let storage_cost = storage_used as u128 * env::storage_byte_cost().as_yoctonear();
-        if env::account_balance().as_yoctonear() < self.relayer.min_balance + storage_cost {
-            RelayerEvent::LowBalance { balance: env::account_balance().as_yoctonear() }.emit();
-            return Err(RelayerError::InsufficientBalance);
-        }
-        env::log_str(&format!("remove_key: storage_used={} bytes, storage_cost={} yoctoNEAR", storage_used, storage_cost));
+        self.check_storage_growth(initial_storage)?;
         result
     }
 
@@ -139,13 +155,7 @@ impl OnSocialRelayer {
     pub fn set_offload_recipient(&mut self, new_recipient: AccountId) -> Result<(), RelayerError> {
         let initial_storage = env::storage_usage();
         let result = admin::set_offload_recipient(&mut self.relayer, new_recipient);
-        let storage_used = env::storage_usage() - initial_storage;
-        let storage_cost = storage_used as u128 * env::storage_byte_cost().as_yoctonear();
-        if env::account_balance().as_yoctonear() < self.relayer.min_balance + storage_cost {
-            RelayerEvent::LowBalance { balance: env::account_balance().as_yoctonear() }.emit();
-            return Err(RelayerError::InsufficientBalance);
-        }
-        env::log_str(&format!("set_offload_recipient: storage_used={} bytes, storage_cost={} yoctoNEAR", storage_used, storage_cost));
+        self.check_storage_growth(initial_storage)?;
         result
     }
 
@@ -153,13 +163,7 @@ impl OnSocialRelayer {
     pub fn set_sponsor_amount(&mut self, new_amount: U128) -> Result<(), RelayerError> {
         let initial_storage = env::storage_usage();
         let result = admin::set_sponsor_amount(&mut self.relayer, new_amount.0);
-        let storage_used = env::storage_usage() - initial_storage;
-        let storage_cost = storage_used as u128 * env::storage_byte_cost().as_yoctonear();
-        if env::account_balance().as_yoctonear() < self.relayer.min_balance + storage_cost {
-            RelayerEvent::LowBalance { balance: env::account_balance().as_yoctonear() }.emit();
-            return Err(RelayerError::InsufficientBalance);
-        }
-        env::log_str(&format!("set_sponsor_amount: storage_used={} bytes, storage_cost={} yoctoNEAR", storage_used, storage_cost));
+        self.check_storage_growth(initial_storage)?;
         result
     }
 
@@ -167,13 +171,7 @@ impl OnSocialRelayer {
     pub fn set_sponsor_gas(&mut self, new_gas: u64) -> Result<(), RelayerError> {
         let initial_storage = env::storage_usage();
         let result = admin::set_sponsor_gas(&mut self.relayer, new_gas);
-        let storage_used = env::storage_usage() - initial_storage;
-        let storage_cost = storage_used as u128 * env::storage_byte_cost().as_yoctonear();
-        if env::account_balance().as_yoctonear() < self.relayer.min_balance + storage_cost {
-            RelayerEvent::LowBalance { balance: env::account_balance().as_yoctonear() }.emit();
-            return Err(RelayerError::InsufficientBalance);
-        }
-        env::log_str(&format!("set_sponsor_gas: storage_used={} bytes, storage_cost={} yoctoNEAR", storage_used, storage_cost));
+        self.check_storage_growth(initial_storage)?;
         result
     }
 
@@ -181,13 +179,7 @@ impl OnSocialRelayer {
     pub fn set_cross_contract_gas(&mut self, new_gas: u64) -> Result<(), RelayerError> {
         let initial_storage = env::storage_usage();
         let result = admin::set_cross_contract_gas(&mut self.relayer, new_gas);
-        let storage_used = env::storage_usage() - initial_storage;
-        let storage_cost = storage_used as u128 * env::storage_byte_cost().as_yoctonear();
-        if env::account_balance().as_yoctonear() < self.relayer.min_balance + storage_cost {
-            RelayerEvent::LowBalance { balance: env::account_balance().as_yoctonear() }.emit();
-            return Err(RelayerError::InsufficientBalance);
-        }
-        env::log_str(&format!("set_cross_contract_gas: storage_used={} bytes, storage_cost={} yoctoNEAR", storage_used, storage_cost));
+        self.check_storage_growth(initial_storage)?;
         result
     }
 
@@ -195,55 +187,222 @@ impl OnSocialRelayer {
     pub fn set_migration_gas(&mut self, new_gas: u64) -> Result<(), RelayerError> {
         let initial_storage = env::storage_usage();
         let result = admin::set_migration_gas(&mut self.relayer, new_gas);
-        let storage_used = env::storage_usage() - initial_storage;
-        let storage_cost = storage_used as u128 * env::storage_byte_cost().as_yoctonear();
-        if env::account_balance().as_yoctonear() < self.relayer.min_balance + storage_cost {
-            RelayerEvent::LowBalance { balance: env::account_balance().as_yoctonear() }.emit();
-            return Err(RelayerError::InsufficientBalance);
-        }
-        env::log_str(&format!("set_migration_gas: storage_used={} bytes, storage_cost={} yoctoNEAR", storage_used, storage_cost));
+        self.check_storage_growth(initial_storage)?;
         result
     }
 
     #[handle_result]
-    pub fn set_omni_locker_contract(&mut self, new_locker_contract: AccountId) -> Result<(), RelayerError> {
+    pub fn set_omni_locker_contract(&mut self, new_locker_contract: AccountId) -> Result<u64, RelayerError> {
         let initial_storage = env::storage_usage();
         let result = admin::set_omni_locker_contract(&mut self.relayer, new_locker_contract);
-        let storage_used = env::storage_usage() - initial_storage;
-        let storage_cost = storage_used as u128 * env::storage_byte_cost().as_yoctonear();
-        if env::account_balance().as_yoctonear() < self.relayer.min_balance + storage_cost {
-            RelayerEvent::LowBalance { balance: env::account_balance().as_yoctonear() }.emit();
-            return Err(RelayerError::InsufficientBalance);
-        }
-        env::log_str(&format!("set_omni_locker_contract: storage_used={} bytes, storage_cost={} yoctoNEAR", storage_used, storage_cost));
+        self.check_storage_growth(initial_storage)?;
         result
     }
 
     #[handle_result]
-    pub fn add_chain_mpc_mapping(&mut self, chain: String, mpc_contract: AccountId) -> Result<(), RelayerError> {
+    pub fn set_action_fee(&mut self, action_type: String, token: Option<String>, fee: U128) -> Result<(), RelayerError> {
         let initial_storage = env::storage_usage();
-        let result = admin::add_chain_mpc_mapping(&mut self.relayer, chain, mpc_contract);
-        let storage_used = env::storage_usage() - initial_storage;
-        let storage_cost = storage_used as u128 * env::storage_byte_cost().as_yoctonear();
-        if env::account_balance().as_yoctonear() < self.relayer.min_balance + storage_cost {
-            RelayerEvent::LowBalance { balance: env::account_balance().as_yoctonear() }.emit();
-            return Err(RelayerError::InsufficientBalance);
-        }
-        env::log_str(&format!("add_chain_mpc_mapping: storage_used={} bytes, storage_cost={} yoctoNEAR", storage_used, storage_cost));
+        let result = admin::set_action_fee(&mut self.relayer, action_type, token, fee.0);
+        self.check_storage_growth(initial_storage)?;
         result
     }
 
     #[handle_result]
-    pub fn remove_chain_mpc_mapping(&mut self, chain: String) -> Result<(), RelayerError> {
+    pub fn add_fee_token(&mut self, token: String) -> Result<(), RelayerError> {
         let initial_storage = env::storage_usage();
-        let result = admin::remove_chain_mpc_mapping(&mut self.relayer, chain);
-        let storage_used = env::storage_usage() - initial_storage;
-        let storage_cost = storage_used as u128 * env::storage_byte_cost().as_yoctonear();
-        if env::account_balance().as_yoctonear() < self.relayer.min_balance + storage_cost {
-            RelayerEvent::LowBalance { balance: env::account_balance().as_yoctonear() }.emit();
-            return Err(RelayerError::InsufficientBalance);
+        let result = admin::add_fee_token(&mut self.relayer, token);
+        self.check_storage_growth(initial_storage)?;
+        result
+    }
+
+    #[handle_result]
+    pub fn remove_fee_token(&mut self, token: String) -> Result<(), RelayerError> {
+        let initial_storage = env::storage_usage();
+        let result = admin::remove_fee_token(&mut self.relayer, token);
+        self.check_storage_growth(initial_storage)?;
+        result
+    }
+
+    // NEP-141 receiver: whitelisted tokens sent here become fee credits for `sender_id`.
+    // Anything else is returned to the sender in full. `msg` is part of the NEP-141 interface
+    // (its name is the JSON key) and is unused here.
+    #[allow(unused_variables)]
+    pub fn ft_on_transfer(&mut self, sender_id: AccountId, amount: U128, msg: String) -> U128 {
+        let token = env::predecessor_account_id().to_string();
+        match fees::credit_fee_token(&mut self.relayer, sender_id, token, amount.0) {
+            Ok(()) => U128(0),
+            Err(_) => amount,
         }
-        env::log_str(&format!("remove_chain_mpc_mapping: storage_used={} bytes, storage_cost={} yoctoNEAR", storage_used, storage_cost));
+    }
+
+    #[handle_result]
+    pub fn set_quota_tier(&mut self, name: String, tier: QuotaTier) -> Result<(), RelayerError> {
+        let initial_storage = env::storage_usage();
+        let result = quota::set_quota_tier(&mut self.relayer, name, tier);
+        self.check_storage_growth(initial_storage)?;
+        result
+    }
+
+    #[handle_result]
+    pub fn remove_quota_tier(&mut self, name: String) -> Result<(), RelayerError> {
+        let initial_storage = env::storage_usage();
+        let result = quota::remove_quota_tier(&mut self.relayer, name);
+        self.check_storage_growth(initial_storage)?;
+        result
+    }
+
+    #[handle_result]
+    pub fn set_account_tier(&mut self, account_id: AccountId, tier: Option<String>) -> Result<(), RelayerError> {
+        let initial_storage = env::storage_usage();
+        let result = quota::set_account_tier(&mut self.relayer, account_id, tier);
+        self.check_storage_growth(initial_storage)?;
+        result
+    }
+
+    #[handle_result]
+    pub fn set_sender_budget(&mut self, account_id: AccountId, budget: Option<U128>) -> Result<(), RelayerError> {
+        let initial_storage = env::storage_usage();
+        let result = ledger::set_budget(&mut self.relayer, account_id, false, budget.map(|b| b.0));
+        self.check_storage_growth(initial_storage)?;
+        result
+    }
+
+    #[handle_result]
+    pub fn set_app_budget(&mut self, app_id: AccountId, budget: Option<U128>) -> Result<(), RelayerError> {
+        let initial_storage = env::storage_usage();
+        let result = ledger::set_budget(&mut self.relayer, app_id, true, budget.map(|b| b.0));
+        self.check_storage_growth(initial_storage)?;
+        result
+    }
+
+    #[handle_result]
+    pub fn reset_sender_spend(&mut self, account_id: AccountId) -> Result<(), RelayerError> {
+        let initial_storage = env::storage_usage();
+        let result = ledger::reset_spend(&mut self.relayer, account_id, false);
+        self.check_storage_growth(initial_storage)?;
+        result
+    }
+
+    #[handle_result]
+    pub fn reset_app_spend(&mut self, app_id: AccountId) -> Result<(), RelayerError> {
+        let initial_storage = env::storage_usage();
+        let result = ledger::reset_spend(&mut self.relayer, app_id, true);
+        self.check_storage_growth(initial_storage)?;
+        result
+    }
+
+    #[handle_result]
+    pub fn set_call_policy(&mut self, receiver_id: AccountId, policy: Option<ReceiverPolicy>) -> Result<(), RelayerError> {
+        let initial_storage = env::storage_usage();
+        let result = policy::set_call_policy(&mut self.relayer, receiver_id, policy);
+        self.check_storage_growth(initial_storage)?;
+        result
+    }
+
+    // Session keys are registered by the account itself, with a direct call that attaches
+    // the storage deposit for the session.
+    #[payable]
+    #[handle_result]
+    pub fn register_session(&mut self, public_key: PublicKey, expires_in_ms: u64, scope: SessionScope) -> Result<u64, RelayerError> {
+        let initial_storage = env::storage_usage();
+        let result = session::register_session(&mut self.relayer, public_key, expires_in_ms, scope);
+        self.check_storage_growth(initial_storage)?;
+        result
+    }
+
+    #[handle_result]
+    pub fn revoke_session(&mut self, public_key: PublicKey) -> Result<(), RelayerError> {
+        let initial_storage = env::storage_usage();
+        let result = session::revoke_session(&mut self.relayer, public_key);
+        self.check_storage_growth(initial_storage)?;
+        result
+    }
+
+    #[handle_result]
+    pub fn revoke_sessions(&mut self) -> Result<(), RelayerError> {
+        let initial_storage = env::storage_usage();
+        let result = session::revoke_sessions(&mut self.relayer);
+        self.check_storage_growth(initial_storage)?;
+        result
+    }
+
+    #[handle_result]
+    pub fn set_max_actions(&mut self, new_max: usize) -> Result<(), RelayerError> {
+        let initial_storage = env::storage_usage();
+        let result = admin::set_max_actions(&mut self.relayer, new_max);
+        self.check_storage_growth(initial_storage)?;
+        result
+    }
+
+    #[handle_result]
+    pub fn register_chain(&mut self, chain: String, config: ChainConfig) -> Result<u64, RelayerError> {
+        let initial_storage = env::storage_usage();
+        let result = chains::register_chain(&mut self.relayer, chain, config);
+        self.check_storage_growth(initial_storage)?;
+        result
+    }
+
+    #[handle_result]
+    pub fn remove_chain(&mut self, chain: String) -> Result<(), RelayerError> {
+        let initial_storage = env::storage_usage();
+        let result = chains::remove_chain(&mut self.relayer, chain);
+        self.check_storage_growth(initial_storage)?;
+        result
+    }
+
+    #[handle_result]
+    pub fn set_chain_enabled(&mut self, chain: String, enabled: bool) -> Result<(), RelayerError> {
+        let initial_storage = env::storage_usage();
+        let result = chains::set_chain_enabled(&mut self.relayer, chain, enabled);
+        self.check_storage_growth(initial_storage)?;
+        result
+    }
+
+    #[handle_result]
+    pub fn complete_bridge_transfer(&mut self, id: u64) -> Result<(), RelayerError> {
+        let initial_storage = env::storage_usage();
+        let result = bridge::complete(&mut self.relayer, id);
+        self.check_storage_growth(initial_storage)?;
+        result
+    }
+
+    #[handle_result]
+    pub fn reclaim_bridge_transfer(&mut self, id: u64) -> Result<(), RelayerError> {
+        let initial_storage = env::storage_usage();
+        let result = bridge::reclaim(&mut self.relayer, id);
+        self.check_storage_growth(initial_storage)?;
+        result
+    }
+
+    #[handle_result]
+    pub fn force_resolve_transfer(&mut self, id: u64, outcome: TransferResolution) -> Result<(), RelayerError> {
+        let initial_storage = env::storage_usage();
+        let result = bridge::force_resolve(&mut self.relayer, id, outcome);
+        self.check_storage_growth(initial_storage)?;
+        result
+    }
+
+    #[handle_result]
+    pub fn set_bridge_timeout(&mut self, timeout_ms: u64) -> Result<(), RelayerError> {
+        let initial_storage = env::storage_usage();
+        let result = bridge::set_bridge_timeout(&mut self.relayer, timeout_ms);
+        self.check_storage_growth(initial_storage)?;
+        result
+    }
+
+    #[handle_result]
+    pub fn withdraw_bridge_escrow(&mut self, asset: String) -> Result<(), RelayerError> {
+        let initial_storage = env::storage_usage();
+        let result = bridge::withdraw_escrow(&mut self.relayer, asset);
+        self.check_storage_growth(initial_storage)?;
+        result
+    }
+
+    #[handle_result]
+    pub fn set_bridge_fee_schedule(&mut self, chain: String, token: String, schedule: Option<BridgeFeeSchedule>) -> Result<(), RelayerError> {
+        let initial_storage = env::storage_usage();
+        let result = bridge::set_fee_schedule(&mut self.relayer, chain, token, schedule);
+        self.check_storage_growth(initial_storage)?;
         result
     }
 
@@ -251,41 +410,39 @@ impl OnSocialRelayer {
     pub fn set_chunk_size(&mut self, new_size: usize) -> Result<(), RelayerError> {
         let initial_storage = env::storage_usage();
         let result = admin::set_chunk_size(&mut self.relayer, new_size);
-        let storage_used = env::storage_usage() - initial_storage;
-        let storage_cost = storage_used as u128 * env::storage_byte_cost().as_yoctonear();
-        if env::account_balance().as_yoctonear() < self.relayer.min_balance + storage_cost {
-            RelayerEvent::LowBalance { balance: env::account_balance().as_yoctonear() }.emit();
-            return Err(RelayerError::InsufficientBalance);
-        }
-        env::log_str(&format!("set_chunk_size: storage_used={} bytes, storage_cost={} yoctoNEAR", storage_used, storage_cost));
+        self.check_storage_growth(initial_storage)?;
+        result
+    }
+
+    #[handle_result]
+    pub fn set_nonce_oracle(&mut self, nonce_oracle: Option<AccountId>) -> Result<u64, RelayerError> {
+        let initial_storage = env::storage_usage();
+        let result = admin::set_nonce_oracle(&mut self.relayer, nonce_oracle);
+        self.check_storage_growth(initial_storage)?;
+        result
+    }
+
+    #[handle_result]
+    pub fn set_webauthn_config(&mut self, config: Option<WebAuthnConfig>) -> Result<u64, RelayerError> {
+        let initial_storage = env::storage_usage();
+        let result = admin::set_webauthn_config(&mut self.relayer, config);
+        self.check_storage_growth(initial_storage)?;
         result
     }
 
     #[handle_result]
-    pub fn set_auth_contract(&mut self, new_auth_contract: AccountId) -> Result<(), RelayerError> {
+    pub fn set_auth_contract(&mut self, new_auth_contract: AccountId) -> Result<u64, RelayerError> {
         let initial_storage = env::storage_usage();
         let result = admin::set_auth_contract(&mut self.relayer, new_auth_contract);
-        let storage_used = env::storage_usage() - initial_storage;
-        let storage_cost = storage_used as u128 * env::storage_byte_cost().as_yoctonear();
-        if env::account_balance().as_yoctonear() < self.relayer.min_balance + storage_cost {
-            RelayerEvent::LowBalance { balance: env::account_balance().as_yoctonear() }.emit();
-            return Err(RelayerError::InsufficientBalance);
-        }
-        env::log_str(&format!("set_auth_contract: storage_used={} bytes, storage_cost={} yoctoNEAR", storage_used, storage_cost));
+        self.check_storage_growth(initial_storage)?;
         result
     }
 
     #[handle_result]
-    pub fn set_ft_wrapper_contract(&mut self, new_ft_wrapper_contract: AccountId) -> Result<(), RelayerError> {
+    pub fn set_ft_wrapper_contract(&mut self, new_ft_wrapper_contract: AccountId) -> Result<u64, RelayerError> {
         let initial_storage = env::storage_usage();
         let result = admin::set_ft_wrapper_contract(&mut self.relayer, new_ft_wrapper_contract);
-        let storage_used = env::storage_usage() - initial_storage;
-        let storage_cost = storage_used as u128 * env::storage_byte_cost().as_yoctonear();
-        if env::account_balance().as_yoctonear() < self.relayer.min_balance + storage_cost {
-            RelayerEvent::LowBalance { balance: env::account_balance().as_yoctonear() }.emit();
-            return Err(RelayerError::InsufficientBalance);
-        }
-        env::log_str(&format!("set_ft_wrapper_contract: storage_used={} bytes, storage_cost={} yoctoNEAR", storage_used, storage_cost));
+        self.check_storage_growth(initial_storage)?;
         result
     }
 
@@ -293,56 +450,147 @@ impl OnSocialRelayer {
     pub fn set_base_fee(&mut self, new_fee: U128, signatures: Option<Vec<Vec<u8>>>) -> Result<(), RelayerError> {
         let initial_storage = env::storage_usage();
         let result = admin::set_base_fee(&mut self.relayer, new_fee.0, signatures);
-        let storage_used = env::storage_usage() - initial_storage;
-        let storage_cost = storage_used as u128 * env::storage_byte_cost().as_yoctonear();
-        if env::account_balance().as_yoctonear() < self.relayer.min_balance + storage_cost {
-            RelayerEvent::LowBalance { balance: env::account_balance().as_yoctonear() }.emit();
-            return Err(RelayerError::InsufficientBalance);
-        }
-        env::log_str(&format!("set_base_fee: storage_used={} bytes, storage_cost={} yoctoNEAR", storage_used, storage_cost));
+        self.check_storage_growth(initial_storage)?;
         result
     }
 
     #[handle_result]
-    pub fn set_manager(&mut self, new_manager: AccountId) -> Result<(), RelayerError> {
+    pub fn grant_role(&mut self, role: Role, account_id: AccountId) -> Result<(), RelayerError> {
         let initial_storage = env::storage_usage();
-        let result = admin::set_manager(&mut self.relayer, new_manager);
-        let storage_used = env::storage_usage() - initial_storage;
-        let storage_cost = storage_used as u128 * env::storage_byte_cost().as_yoctonear();
-        if env::account_balance().as_yoctonear() < self.relayer.min_balance + storage_cost {
-            RelayerEvent::LowBalance { balance: env::account_balance().as_yoctonear() }.emit();
-            return Err(RelayerError::InsufficientBalance);
-        }
-        env::log_str(&format!("set_manager: storage_used={} bytes, storage_cost={} yoctoNEAR", storage_used, storage_cost));
+        let result = admin::grant_role(&mut self.relayer, role, account_id);
+        self.check_storage_growth(initial_storage)?;
         result
     }
 
     #[handle_result]
-    pub fn update_contract(&mut self) -> Result<Promise, RelayerError> {
-        let caller = env::predecessor_account_id();
-        if !self.relayer.is_manager(&caller) {
-            return Err(RelayerError::Unauthorized);
-        }
-        let code = env::input().ok_or(RelayerError::MissingInput)?.to_vec();
-        RelayerEvent::ContractUpgraded { manager: caller, timestamp: env::block_timestamp_ms() }.emit();
-        let promise = Promise::new(env::current_account_id())
-            .deploy_contract(code)
-            .function_call("migrate".to_string(), vec![], NearToken::from_yoctonear(0), Gas::from_tgas(self.relayer.migration_gas));
-        env::log_str(&format!("Gas used in update_contract: {} TGas", env::used_gas().as_tgas()));
-        Ok(promise)
+    pub fn revoke_role(&mut self, role: Role, account_id: AccountId) -> Result<(), RelayerError> {
+        let initial_storage = env::storage_usage();
+        let result = admin::revoke_role(&mut self.relayer, role, account_id);
+        self.check_storage_growth(initial_storage)?;
+        result
+    }
+
+    #[handle_result]
+    pub fn renounce_role(&mut self, role: Role) -> Result<(), RelayerError> {
+        let initial_storage = env::storage_usage();
+        let result = admin::renounce_role(&mut self.relayer, role);
+        self.check_storage_growth(initial_storage)?;
+        result
+    }
+
+    #[handle_result]
+    pub fn set_multisig_config(&mut self, account_id: AccountId, config: Option<MultisigConfig>, signatures: Option<Vec<Vec<u8>>>) -> Result<(), RelayerError> {
+        let initial_storage = env::storage_usage();
+        let result = multisig::set_multisig_config(&mut self.relayer, account_id, config, signatures);
+        self.check_storage_growth(initial_storage)?;
+        result
+    }
+
+    #[handle_result]
+    pub fn propose_manager(&mut self, new_manager: AccountId, expires_in_ms: Option<u64>) -> Result<(), RelayerError> {
+        let initial_storage = env::storage_usage();
+        let result = admin::propose_manager(&mut self.relayer, new_manager, expires_in_ms);
+        self.check_storage_growth(initial_storage)?;
+        result
+    }
+
+    #[handle_result]
+    pub fn accept_manager(&mut self) -> Result<(), RelayerError> {
+        let initial_storage = env::storage_usage();
+        let result = admin::accept_manager(&mut self.relayer);
+        self.check_storage_growth(initial_storage)?;
+        result
+    }
+
+    #[handle_result]
+    pub fn cancel_manager_proposal(&mut self) -> Result<(), RelayerError> {
+        let initial_storage = env::storage_usage();
+        let result = admin::cancel_manager_proposal(&mut self.relayer);
+        self.check_storage_growth(initial_storage)?;
+        result
+    }
+
+    #[handle_result]
+    pub fn pause(&mut self, subsystem: Option<Subsystem>) -> Result<(), RelayerError> {
+        let initial_storage = env::storage_usage();
+        let result = admin::pause(&mut self.relayer, subsystem);
+        self.check_storage_growth(initial_storage)?;
+        result
+    }
+
+    #[handle_result]
+    pub fn unpause(&mut self, subsystem: Option<Subsystem>) -> Result<(), RelayerError> {
+        let initial_storage = env::storage_usage();
+        let result = admin::unpause(&mut self.relayer, subsystem);
+        self.check_storage_growth(initial_storage)?;
+        result
+    }
+
+    #[handle_result]
+    pub fn execute_proposal(&mut self, id: u64) -> Result<(), RelayerError> {
+        let initial_storage = env::storage_usage();
+        let result = governance::execute(&mut self.relayer, id);
+        self.check_storage_growth(initial_storage)?;
+        result
+    }
+
+    #[handle_result]
+    pub fn cancel_proposal(&mut self, id: u64) -> Result<(), RelayerError> {
+        let initial_storage = env::storage_usage();
+        let result = governance::cancel(&mut self.relayer, id);
+        self.check_storage_growth(initial_storage)?;
+        result
+    }
+
+    #[handle_result]
+    pub fn set_governance_delay(&mut self, delay_ms: u64) -> Result<u64, RelayerError> {
+        let initial_storage = env::storage_usage();
+        let result = governance::set_governance_delay(&mut self.relayer, delay_ms);
+        self.check_storage_growth(initial_storage)?;
+        result
+    }
+
+    // Stages the raw code passed as input; see deploy_staged_upgrade.
+    #[handle_result]
+    pub fn stage_upgrade(&mut self) -> Result<(), RelayerError> {
+        let initial_storage = env::storage_usage();
+        let code = env::input().ok_or(RelayerError::MissingInput)?;
+        let result = upgrade::stage_upgrade(&mut self.relayer, code);
+        self.check_storage_growth(initial_storage)?;
+        result
+    }
+
+    #[handle_result]
+    pub fn deploy_staged_upgrade(&mut self, code_hash: String, migrate_chains: Option<Vec<String>>) -> Result<Promise, RelayerError> {
+        let result = upgrade::deploy_staged_upgrade(&mut self.relayer, code_hash, migrate_chains);
+        env::log_str(&format!("Gas used in deploy_staged_upgrade: {} TGas", env::used_gas().as_tgas()));
+        result
+    }
+
+    #[handle_result]
+    pub fn abort_upgrade(&mut self) -> Result<(), RelayerError> {
+        upgrade::abort_upgrade(&mut self.relayer)
+    }
+
+    #[handle_result]
+    pub fn set_upgrade_approver(&mut self, required: bool) -> Result<u64, RelayerError> {
+        let initial_storage = env::storage_usage();
+        let result = upgrade::set_upgrade_approver(&mut self.relayer, required);
+        self.check_storage_growth(initial_storage)?;
+        result
+    }
+
+    #[private]
+    pub fn handle_upgrade_result(&mut self, code_hash: String, approved_by: AccountId) -> bool {
+        let deployed = matches!(env::promise_result(0), PromiseResult::Successful(_));
+        upgrade::settle_upgrade(&mut self.relayer, code_hash, approved_by, deployed)
     }
 
     #[handle_result]
     pub fn set_min_balance(&mut self, new_min: U128) -> Result<(), RelayerError> {
         let initial_storage = env::storage_usage();
         let result = admin::set_min_balance(&mut self.relayer, new_min.0);
-        let storage_used = env::storage_usage() - initial_storage;
-        let storage_cost = storage_used as u128 * env::storage_byte_cost().as_yoctonear();
-        if env::account_balance().as_yoctonear() < self.relayer.min_balance + storage_cost {
-            RelayerEvent::LowBalance { balance: env::account_balance().as_yoctonear() }.emit();
-            return Err(RelayerError::InsufficientBalance);
-        }
-        env::log_str(&format!("set_min_balance: storage_used={} bytes, storage_cost={} yoctoNEAR", storage_used, storage_cost));
+        self.check_storage_growth(initial_storage)?;
         result
     }
 
@@ -350,13 +598,7 @@ impl OnSocialRelayer {
     pub fn set_max_balance(&mut self, new_max: U128) -> Result<(), RelayerError> {
         let initial_storage = env::storage_usage();
         let result = admin::set_max_balance(&mut self.relayer, new_max.0);
-        let storage_used = env::storage_usage() - initial_storage;
-        let storage_cost = storage_used as u128 * env::storage_byte_cost().as_yoctonear();
-        if env::account_balance().as_yoctonear() < self.relayer.min_balance + storage_cost {
-            RelayerEvent::LowBalance { balance: env::account_balance().as_yoctonear() }.emit();
-            return Err(RelayerError::InsufficientBalance);
-        }
-        env::log_str(&format!("set_max_balance: storage_used={} bytes, storage_cost={} yoctoNEAR", storage_used, storage_cost));
+        self.check_storage_growth(initial_storage)?;
         result
     }
 
@@ -389,13 +631,186 @@ impl OnSocialRelayer {
     }
 
     pub fn get_omni_locker_contract(&self) -> AccountId {
-        self.relayer.omni_locker_contract.get().clone().map(|x| x.clone()).unwrap_or_else(|| env::current_account_id())
+        self.relayer.omni_locker_contract.get().clone().unwrap_or_else(env::current_account_id)
     }
 
     pub fn get_chunk_size(&self) -> usize {
         self.relayer.chunk_size
     }
 
+    pub fn get_max_actions(&self) -> usize {
+        self.relayer.max_actions
+    }
+
+    pub fn get_action_fee(&self, action_type: String, token: Option<String>) -> U128 {
+        let asset = token.unwrap_or_else(|| fees::NEAR_ASSET.to_string());
+        U128(self.relayer.action_fees.get(&fees::fee_key(&action_type, &asset)).copied().unwrap_or(0))
+    }
+
+    pub fn get_proposal(&self, id: u64) -> Option<GovernanceProposal> {
+        self.relayer.proposals.get(&id).cloned()
+    }
+
+    pub fn get_proposals(&self, from_index: Option<u64>, limit: Option<u64>) -> Vec<GovernanceProposal> {
+        self.relayer.proposals.values()
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit.unwrap_or(50) as usize)
+            .cloned()
+            .collect()
+    }
+
+    pub fn get_governance_delay(&self) -> u64 {
+        self.relayer.governance_delay_ms
+    }
+
+    pub fn get_staged_upgrade(&self) -> Option<StagedUpgrade> {
+        self.relayer.staged_upgrade.clone()
+    }
+
+    pub fn get_code_hash(&self) -> Option<String> {
+        self.relayer.code_hash.clone()
+    }
+
+    pub fn get_upgrade_approver_required(&self) -> bool {
+        self.relayer.upgrade_approver_required
+    }
+
+    pub fn get_quota_status(&self, account_id: AccountId) -> QuotaStatus {
+        quota::status(&self.relayer, &account_id)
+    }
+
+    pub fn get_quota_tier(&self, name: String) -> Option<QuotaTier> {
+        self.relayer.quota_tiers.get(&name).cloned()
+    }
+
+    pub fn get_sender_spend(&self, account_id: AccountId) -> SpendRecord {
+        self.relayer.sender_spend.get(&account_id).cloned().unwrap_or_default()
+    }
+
+    pub fn get_app_spend(&self, app_id: AccountId) -> SpendRecord {
+        self.relayer.app_spend.get(&app_id).cloned().unwrap_or_default()
+    }
+
+    pub fn get_sender_spends(&self, from_index: Option<u64>, limit: Option<u64>) -> Vec<(AccountId, SpendRecord)> {
+        ledger::list(&self.relayer.sender_spend, from_index, limit)
+    }
+
+    pub fn get_app_spends(&self, from_index: Option<u64>, limit: Option<u64>) -> Vec<(AccountId, SpendRecord)> {
+        ledger::list(&self.relayer.app_spend, from_index, limit)
+    }
+
+    pub fn get_sender_budget(&self, account_id: AccountId) -> Option<U128> {
+        self.relayer.sender_budgets.get(&account_id).copied().map(U128)
+    }
+
+    pub fn get_app_budget(&self, app_id: AccountId) -> Option<U128> {
+        self.relayer.app_budgets.get(&app_id).copied().map(U128)
+    }
+
+    pub fn get_sessions(&self, account_id: AccountId) -> Vec<SessionKey> {
+        session::list(&self.relayer, &account_id)
+    }
+
+    pub fn get_call_policy(&self, receiver_id: AccountId) -> Option<ReceiverPolicy> {
+        self.relayer.call_policies.get(&receiver_id).cloned()
+    }
+
+    // Exactly what a client signs for a delegate sent to this relayer; session keys pass the
+    // session_nonce they were registered with.
+    #[handle_result]
+    pub fn get_signing_payload(&self, delegate_action: DelegateAction, session_nonce: Option<u64>) -> Result<SigningPayload, RelayerError> {
+        let session_nonce = session_nonce.unwrap_or(0);
+        let payload = relay::signing_payload(&delegate_action, session_nonce)?;
+        Ok(SigningPayload {
+            domain: relay::signing_domain(session_nonce),
+            hash: env::sha256(&payload).into(),
+            payload: payload.into(),
+        })
+    }
+
+    pub fn get_pending_manager(&self) -> Option<ManagerProposal> {
+        self.relayer.pending_manager.clone()
+    }
+
+    pub fn get_role_holders(&self, role: Role) -> Vec<AccountId> {
+        self.relayer.role_holders(role)
+    }
+
+    pub fn get_roles(&self) -> Vec<(Role, Vec<AccountId>)> {
+        Role::ALL.iter().map(|role| (*role, self.relayer.role_holders(*role))).collect()
+    }
+
+    pub fn has_role(&self, role: Role, account_id: AccountId) -> bool {
+        self.relayer.has_role(role, &account_id)
+    }
+
+    pub fn get_multisig_config(&self, account_id: AccountId) -> Option<MultisigConfig> {
+        self.relayer.multisig_configs.get(&account_id).cloned()
+    }
+
+    pub fn get_admin_nonce(&self) -> u64 {
+        self.relayer.admin_nonce
+    }
+
+    pub fn is_fee_token(&self, token: String) -> bool {
+        self.relayer.fee_tokens.contains(&token)
+    }
+
+    pub fn get_fee_credit(&self, account_id: AccountId, token: String) -> U128 {
+        U128(self.relayer.fee_credits.get(&fees::credit_key(&account_id, &token)).copied().unwrap_or(0))
+    }
+
+    pub fn get_chain(&self, chain: String) -> Option<ChainConfig> {
+        self.relayer.chains.get(&chain).cloned()
+    }
+
+    pub fn get_chains(&self, from_index: Option<u64>, limit: Option<u64>) -> Vec<(String, ChainConfig)> {
+        chains::list(&self.relayer, from_index, limit)
+    }
+
+    // None once the transfer is evicted to make room for the sender's newer ones.
+    pub fn get_bridge_transfer(&self, id: u64) -> Option<BridgeTransferRecord> {
+        self.relayer.bridge_transfers.get(&id).cloned()
+    }
+
+    pub fn get_transfers_by_sender(&self, account_id: AccountId, from_index: Option<u64>, limit: Option<u64>) -> Vec<BridgeTransferRecord> {
+        bridge::by_sender(&self.relayer, &account_id, from_index, limit)
+    }
+
+    // Transfers to a chain that haven't reached Completed, Failed or Refunded yet.
+    pub fn get_pending_transfers(&self, chain: String) -> Vec<BridgeTransferRecord> {
+        bridge::pending(&self.relayer, &chain)
+    }
+
+    pub fn get_bridge_timeout(&self) -> u64 {
+        self.relayer.bridge_timeout_ms
+    }
+
+    // Bridge fees escrowed for `account_id` that no transfer has claimed yet.
+    pub fn get_bridge_escrow(&self, account_id: AccountId, asset: String) -> U128 {
+        U128(self.relayer.bridge_escrow.get(&fees::credit_key(&account_id, &asset)).copied().unwrap_or(0))
+    }
+
+    pub fn get_bridge_fee_schedule(&self, chain: String, token: String) -> Option<BridgeFeeSchedule> {
+        self.relayer.bridge_fee_schedules.get(&bridge::schedule_key(&chain, &token)).cloned()
+    }
+
+    // Exactly what a BridgeTransfer of `amount` would be charged if admitted now.
+    #[handle_result]
+    pub fn quote_bridge_fee(&self, token: String, amount: U128, chain: String) -> Result<BridgeFeeQuote, RelayerError> {
+        bridge::quote(&self.relayer, &chain, &token, amount.0, 0)
+    }
+
+    // The contract relayed delegates get their nonce from, or None if this network has no default.
+    pub fn get_nonce_oracle(&self) -> Option<AccountId> {
+        relay::nonce_oracle(&self.relayer).ok()
+    }
+
+    // The relying party passkey signatures are checked against, if any.
+    pub fn get_webauthn_config(&self) -> Option<WebAuthnConfig> {
+        self.relayer.webauthn.clone()
+    }
+
     pub fn get_auth_contract(&self) -> AccountId {
         self.relayer.auth_contract.clone()
     }
@@ -407,19 +822,32 @@ impl OnSocialRelayer {
     pub fn get_base_fee(&self) -> U128 {
         U128(self.relayer.base_fee)
     }
+
+    pub fn get_paused(&self) -> Vec<Subsystem> {
+        self.relayer.paused.clone()
+    }
+
+    pub fn is_paused(&self, subsystem: Subsystem) -> bool {
+        self.relayer.is_paused(subsystem)
+    }
+
+    pub fn get_delegate_nonce(&self, account_id: AccountId, public_key: PublicKey) -> Option<u64> {
+        self.relayer.get_delegate_nonce(&account_id, &public_key)
+    }
 }
 
 #[near]
 impl OnSocialRelayer {
     #[private]
-    pub fn handle_mpc_signature(&mut self, chain: String, request_id: u64, result: Vec<u8>, #[callback_result] call_result: Result<(), PromiseError>) {
+    pub fn handle_mpc_signature(&mut self, chain: String, request_id: u64, result: Vec<u8>, #[callback_result] call_result: Result<(), PromiseError>) -> bool {
         if call_result.is_err() {
             env::log_str(&format!("MPC signature failed for chain {} request_id {}", chain, request_id));
             // No state changes to revert, just emit event
             RelayerEvent::CrossChainSignatureResult { chain, request_id, result: vec![] }.emit();
-            return;
+            return false;
         }
         RelayerEvent::CrossChainSignatureResult { chain, request_id, result }.emit();
+        true
     }
 
     #[private]
@@ -434,90 +862,112 @@ impl OnSocialRelayer {
     }
 
     #[private]
-    pub fn handle_bridge_transfer_result(
-        &mut self,
-        sender_id: AccountId,
-        token: String,
-        amount: U128,
-        destination_chain: String,
-        recipient: String,
-        signature: Vec<u8>,
-        #[callback_result] call_result: Result<(), PromiseError>,
-    ) {
-        let nonce = self.relayer.get_pending_nonce(&destination_chain);
+    pub fn handle_transfer_locked(&mut self, id: u64, request_id: u64, #[callback_result] call_result: Result<(), PromiseError>) -> PromiseOrValue<bool> {
+        if bridge::is_settled(&self.relayer, id) {
+            return PromiseOrValue::Value(false);
+        }
         if call_result.is_err() {
-            env::log_str(&format!("Bridge transfer failed for sender {} to chain {}", sender_id, destination_chain));
-            // Revert pending transfer and refund fee
-            if let Some(pending) = self.relayer.revert_pending_transfer(&destination_chain, nonce) {
-                if pending.fee > 0 {
-                    Promise::new(sender_id.clone())
-                        .transfer(NearToken::from_yoctonear(pending.fee));
-                    env::log_str(&format!("Refunded {} yoctoNEAR to {}", pending.fee, sender_id));
-                }
+            let _ = bridge::fail(&mut self.relayer, id);
+            return PromiseOrValue::Value(false);
+        }
+        let Ok(record) = bridge::advance(&mut self.relayer, id, TransferStatus::Locked) else {
+            return PromiseOrValue::Value(false);
+        };
+        match bridge::sign_promise(&self.relayer, &record, request_id) {
+            Ok(promise) => PromiseOrValue::Promise(promise),
+            Err(_) => {
+                let _ = bridge::fail(&mut self.relayer, id);
+                PromiseOrValue::Value(false)
             }
-            RelayerEvent::BridgeTransferFailed {
-                token,
-                amount,
-                destination_chain,
-                recipient,
-                sender: sender_id,
-                nonce,
-            }.emit();
-            return;
         }
-        // Confirm transfer and update nonce
-        self.relayer.confirm_pending_transfer(&destination_chain, nonce);
-        RelayerEvent::BridgeTransferCompleted {
-            token,
-            amount,
-            destination_chain,
-            recipient,
-            sender: sender_id,
-            signature,
-        }.emit();
+    }
+
+    // Reads the raw MPC response so the signature is kept on the transfer record.
+    #[private]
+    pub fn handle_bridge_transfer_result(&mut self, id: u64) -> bool {
+        if bridge::is_settled(&self.relayer, id) {
+            return false;
+        }
+        let PromiseResult::Successful(signature) = env::promise_result(0) else {
+            let _ = bridge::fail(&mut self.relayer, id);
+            return false;
+        };
+        if bridge::advance(&mut self.relayer, id, TransferStatus::Signed).is_err() {
+            return false;
+        }
+        if let Some(record) = self.relayer.bridge_transfers.get_mut(&id) {
+            record.signature = Some(signature.into());
+        }
+        true
     }
 
     #[private]
     #[handle_result]
     pub fn handle_auth_result(&mut self, sender_id: AccountId, signed_delegate: SignedDelegateAction, #[callback_unwrap] is_authorized: bool) -> Result<Promise, RelayerError> {
-        if !is_authorized {
+        // The actions run as the delegate's sender, so the account that was checked must be it
+        if sender_id != signed_delegate.delegate_action.sender_id {
             return Err(RelayerError::Unauthorized);
         }
-        let tx_hash = env::sha256(&borsh::to_vec(&signed_delegate.delegate_action).map_err(|_| RelayerError::InvalidNonce)?);
-        relay::verify_signature(&signed_delegate, &tx_hash)?;
-        let delegate = signed_delegate.delegate_action;
-        let action = delegate.actions.first().unwrap();
+        // Session keys live in the relayer's registry, so the auth contract doesn't know them
+        if signed_delegate.session_nonce != 0 {
+            session::authorize(&self.relayer, &signed_delegate)?;
+        } else if !is_authorized {
+            return Err(RelayerError::Unauthorized);
+        }
+        self.relayer.ensure_not_paused(Subsystem::Relay)?;
+        let tx_hash = relay::delegate_hash(&signed_delegate.delegate_action, signed_delegate.session_nonce)?;
+        relay::verify_signature(&self.relayer, &signed_delegate, &tx_hash)?;
+        relay::mark_authorized(&mut self.relayer, &signed_delegate);
         let request_id = env::block_timestamp();
-        let promise = relay::execute_action(&mut self.relayer, action, &sender_id, action.type_name(), Some(request_id))?;
-        let promise = match action {
-            Action::ChainSignatureRequest { target_chain, .. } => {
-                promise.then(
-                    ext_self::ext(env::current_account_id())
-                        .with_static_gas(Gas::from_tgas(self.relayer.cross_contract_gas))
-                        .handle_mpc_signature(target_chain.clone(), request_id, Vec::new())
-                )
-            }
-            Action::BridgeTransfer { token, amount, destination_chain, recipient, .. } => {
-                promise.then(
-                    ext_self::ext(env::current_account_id())
-                        .with_static_gas(Gas::from_tgas(self.relayer.cross_contract_gas))
-                        .handle_bridge_transfer_result(sender_id.clone(), token.clone(), *amount, destination_chain.clone(), recipient.clone(), Vec::new())
-                )
-            }
-            _ => promise.then(
-                ext_self::ext(env::current_account_id())
-                    .with_static_gas(Gas::from_tgas(self.relayer.cross_contract_gas))
-                    .handle_bridge_result(sender_id.clone(), action.type_name().to_string(), Vec::new())
-            ),
+        // Actions run in the order they were signed, one step at a time. Consecutive receipt
+        // actions share one receipt to receiver_id, so they succeed or fail together; every other
+        // action is a step of its own. Each step's callback starts the next only on success.
+        let (step, end) = self.dispatch_step(&signed_delegate, 0, request_id)?;
+        Ok(step.then(self.actions_callback(signed_delegate, request_id, end, Vec::new())))
+    }
+
+    // Runs after handle_auth_result, whatever it returned: the flag it recorded decides
+    // whether the delegate's admission is kept or rolled back.
+    #[private]
+    pub fn settle_delegate(&mut self, hold_key: String) -> bool {
+        relay::settle_delegate(&mut self.relayer, &hold_key)
+    }
+
+    // Runs after each step of a multi-action delegate. `results` holds one entry per action
+    // that already ran, so the step that just finished starts at results.len() and ends at `end`.
+    #[private]
+    pub fn handle_actions_result(&mut self, signed_delegate: SignedDelegateAction, request_id: u64, end: u32, results: Vec<bool>) -> PromiseOrValue<Vec<bool>> {
+        let actions = &signed_delegate.delegate_action.actions;
+        let (start, end) = (results.len(), (end as usize).min(actions.len()));
+        // A receipt step counts whatever its calls returned; the other steps end in one of the
+        // relayer's callbacks, which return false when the action didn't go through
+        let succeeded = start < end && match env::promise_result(0) {
+            PromiseResult::Successful(value) => actions[start].is_receipt_action() || value != b"false",
+            PromiseResult::Failed => false,
         };
-        Ok(promise)
+        let mut results = results;
+        results.resize(end, succeeded);
+        if succeeded && end < actions.len() {
+            match self.dispatch_step(&signed_delegate, end, request_id) {
+                Ok((step, next_end)) => {
+                    return PromiseOrValue::Promise(step.then(self.actions_callback(signed_delegate, request_id, next_end, results)));
+                }
+                Err(error) => env::log_str(&format!("Action {} not dispatched: {:?}", end, error)),
+            }
+        }
+        // Actions after a failed step never run, so their bridge fees become withdrawable
+        results.resize(actions.len(), false);
+        bridge::release_quotes(&mut self.relayer, &fees::hold_key(&signed_delegate), &signed_delegate.delegate_action.sender_id, actions.len());
+        let action_types = actions.iter().map(|action| action.type_name().to_string()).collect();
+        RelayerEvent::ActionsExecuted { sender_id: signed_delegate.delegate_action.sender_id, action_types, results: results.clone() }.emit();
+        PromiseOrValue::Value(results)
     }
 
     #[private]
     pub fn handle_registration(&mut self, account_id: AccountId, token: String, _is_sender: bool, #[callback_unwrap] is_registered: bool) -> Promise {
         if !is_registered {
             ext_ft_wrapper::ext(self.relayer.ft_wrapper_contract.clone())
-                .with_static_gas(Gas::from_tgas(self.relayer.cross_contract_gas))
+                .with_static_gas(Gas::from_gas(self.relayer.cross_contract_gas))
                 .with_attached_deposit(NearToken::from_yoctonear(1_250_000_000_000_000_000_000))
                 .storage_deposit(token, account_id, U128(1_250_000_000_000_000_000_000))
         } else {
@@ -525,14 +975,74 @@ impl OnSocialRelayer {
         }
     }
 
+    // `chains` lists the chains of the pre-registry mapping to keep.
     #[private]
     #[init(ignore_state)]
-    pub fn migrate() -> Self {
+    pub fn migrate(chains: Option<Vec<String>>) -> Self {
         Self {
-            relayer: Relayer::migrate(),
+            relayer: Relayer::migrate(&chains.unwrap_or_default()),
+        }
+    }
+}
+
+impl OnSocialRelayer {
+    // Refuses a call whose new storage would leave less than min_balance once paid for. It runs
+    // after the change, so the returned error reverts it; freed storage costs nothing.
+    fn check_storage_growth(&self, initial: u64) -> Result<(), RelayerError> {
+        let storage_used = env::storage_usage().saturating_sub(initial);
+        let storage_cost = storage_used as u128 * env::storage_byte_cost().as_yoctonear();
+        if env::account_balance().as_yoctonear() < self.relayer.min_balance + storage_cost {
+            RelayerEvent::LowBalance { balance: env::account_balance().as_yoctonear() }.emit();
+            return Err(RelayerError::InsufficientBalance);
+        }
+        env::log_str(&format!("storage_used={} bytes, storage_cost={} yoctoNEAR", storage_used, storage_cost));
+        Ok(())
+    }
+
+    // Schedules the step of a delegate that starts at action `start`: the run of
+    // receipt actions from there, or that one action with its callback. Returns where it ends.
+    fn dispatch_step(&mut self, signed_delegate: &SignedDelegateAction, start: usize, request_id: u64) -> Result<(Promise, usize), RelayerError> {
+        let delegate = &signed_delegate.delegate_action;
+        let action = delegate.actions.get(start).ok_or(RelayerError::MissingInput)?;
+        if action.is_receipt_action() {
+            let mut receipt = Promise::new(delegate.receiver_id.clone());
+            let mut end = start;
+            for action in delegate.actions[start..].iter().take_while(|action| action.is_receipt_action()) {
+                receipt = relay::receipt_action(&self.relayer, receipt, action)?;
+                end += 1;
+            }
+            return Ok((receipt, end));
+        }
+        let action_request_id = request_id + start as u64;
+        let quote_key = bridge::quote_key(signed_delegate, start);
+        let mut promise = relay::execute_action(&mut self.relayer, action, &delegate.sender_id, &delegate.receiver_id, Some(&quote_key), Some(action_request_id))?;
+        if let Some(callback) = self.action_callback(action, action_request_id) {
+            promise = promise.then(callback);
+        }
+        Ok((promise, start + 1))
+    }
+
+    // Reports the step ending at `end` and carries the gas for the steps after it.
+    fn actions_callback(&self, signed_delegate: SignedDelegateAction, request_id: u64, end: usize, results: Vec<bool>) -> Promise {
+        ext_self::ext(env::current_account_id())
+            .with_static_gas(relay::step_callback_gas(&self.relayer, &signed_delegate.delegate_action, end))
+            .handle_actions_result(signed_delegate, request_id, end as u32, results)
+    }
+
+    // Chain signature requests need their own callback to settle state (BridgeTransfer gets
+    // one from execute_action); other actions report through the caller's callback.
+    fn action_callback(&self, action: &Action, request_id: u64) -> Option<Promise> {
+        let callback = ext_self::ext(env::current_account_id())
+            .with_static_gas(Gas::from_gas(self.relayer.cross_contract_gas));
+        match action {
+            Action::ChainSignatureRequest { target_chain, .. } => {
+                Some(callback.handle_mpc_signature(target_chain.clone(), request_id, Vec::new()))
+            }
+            _ => None,
         }
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests;
@@ -0,0 +1,136 @@
+use near_sdk::{env, AccountId, PublicKey};
+use near_sdk::borsh;
+use near_crypto::KeyType;
+use ed25519_dalek::{Verifier, Signature as Ed25519Signature, VerifyingKey};
+use crate::state::Relayer;
+use crate::types::{MultisigConfig, Role, SignedDelegateAction};
+use crate::errors::RelayerError;
+use crate::events::RelayerEvent;
+use crate::relay;
+
+pub const MAX_SIGNERS: usize = 10;
+
+// Admin operations are signed over sha256(borsh(contract, operation, args, admin_nonce)),
+// so a signature set can't be replayed on another contract, operation or nonce.
+pub fn admin_operation_hash(operation: &str, args: &[u8], admin_nonce: u64) -> Vec<u8> {
+    let payload = borsh::to_vec(&(env::current_account_id(), operation, args, admin_nonce))
+        .expect("Failed to serialize admin operation");
+    env::sha256(&payload)
+}
+
+fn verify_signer(signer: &PublicKey, signature: &[u8], hash: &[u8]) -> bool {
+    let key_bytes = signer.as_bytes();
+    if key_bytes[0] == KeyType::SECP256K1 as u8 {
+        return relay::verify_secp256k1(signer, signature, hash).is_ok();
+    }
+    let Ok(signature_bytes) = <[u8; 64]>::try_from(signature) else {
+        return false;
+    };
+    let Ok(key) = <[u8; 32]>::try_from(&key_bytes[1..]) else {
+        return false;
+    };
+    VerifyingKey::from_bytes(&key)
+        .map(|key| key.verify(hash, &Ed25519Signature::from_bytes(&signature_bytes)).is_ok())
+        .unwrap_or(false)
+}
+
+// Each signature must come from a distinct member of the signer set.
+pub fn verify_threshold(config: &MultisigConfig, signatures: &[Vec<u8>], hash: &[u8]) -> Result<(), RelayerError> {
+    let mut seen: Vec<usize> = Vec::new();
+    for signature in signatures {
+        let index = config.signers.iter()
+            .position(|signer| verify_signer(signer, signature, hash))
+            .ok_or(RelayerError::InvalidSignature)?;
+        if seen.contains(&index) {
+            return Err(RelayerError::DuplicateSigner);
+        }
+        seen.push(index);
+    }
+    if (seen.len() as u32) < config.threshold {
+        return Err(RelayerError::InsufficientSignatures);
+    }
+    Ok(())
+}
+
+pub fn verify_delegate(relayer: &Relayer, signed_delegate: &SignedDelegateAction) -> Result<(), RelayerError> {
+    let config = relayer.multisig_configs.get(&signed_delegate.delegate_action.sender_id);
+    match (config, &signed_delegate.multi_signatures) {
+        (None, None) => Ok(()),
+        // No local signer set: the auth contract still judges the signatures it is passed
+        (None, Some(_)) => Ok(()),
+        (Some(_), None) => Err(RelayerError::InsufficientSignatures),
+        (Some(config), Some(signatures)) => {
+            verify_threshold(config, signatures, &relay::delegate_hash(&signed_delegate.delegate_action, signed_delegate.session_nonce)?)
+        }
+    }
+}
+
+// Checks signatures against the admin signer set (registered under the contract's own
+// account) and bumps the admin nonce so the same set can't authorize the call twice.
+pub fn verify_admin(relayer: &mut Relayer, operation: &str, args: &[u8], signatures: Option<Vec<Vec<u8>>>) -> Result<(), RelayerError> {
+    let config = relayer.multisig_configs.get(&env::current_account_id())
+        .ok_or(RelayerError::InsufficientSignatures)?;
+    let signatures = signatures.ok_or(RelayerError::InsufficientSignatures)?;
+    verify_threshold(config, &signatures, &admin_operation_hash(operation, args, relayer.admin_nonce))?;
+    relayer.admin_nonce += 1;
+    Ok(())
+}
+
+fn validate_config(config: &MultisigConfig) -> Result<(), RelayerError> {
+    if config.signers.is_empty() || config.signers.len() > MAX_SIGNERS {
+        return Err(RelayerError::InvalidSignerSet);
+    }
+    if config.threshold == 0 || config.threshold as usize > config.signers.len() {
+        return Err(RelayerError::InvalidSignerSet);
+    }
+    for (i, signer) in config.signers.iter().enumerate() {
+        let key_bytes = signer.as_bytes();
+        let supported = (key_bytes.len() == 33 && key_bytes[0] == KeyType::ED25519 as u8)
+            || (key_bytes.len() == 65 && key_bytes[0] == KeyType::SECP256K1 as u8);
+        if !supported {
+            return Err(RelayerError::InvalidSignerSet);
+        }
+        if config.signers[..i].contains(signer) {
+            return Err(RelayerError::DuplicateSigner);
+        }
+    }
+    Ok(())
+}
+
+// Accounts manage their own signer set. The admin set lives under the contract account:
+// an owner bootstraps it, after which replacing it needs the current set's approval.
+pub fn set_multisig_config(
+    relayer: &mut Relayer,
+    account_id: AccountId,
+    config: Option<MultisigConfig>,
+    signatures: Option<Vec<Vec<u8>>>,
+) -> Result<(), RelayerError> {
+    let caller = env::predecessor_account_id();
+    if account_id == env::current_account_id() {
+        if !relayer.has_role(Role::Owner, &caller) {
+            return Err(RelayerError::Unauthorized);
+        }
+        if relayer.multisig_configs.contains_key(&account_id) {
+            let args = borsh::to_vec(&config).map_err(|_| RelayerError::InvalidSignerSet)?;
+            verify_admin(relayer, "set_multisig_config", &args, signatures)?;
+        }
+    } else if caller != account_id {
+        return Err(RelayerError::Unauthorized);
+    }
+    match config {
+        Some(config) => {
+            validate_config(&config)?;
+            RelayerEvent::MultisigConfigured {
+                account_id: account_id.clone(),
+                signers: config.signers.clone(),
+                threshold: config.threshold,
+            }.emit();
+            relayer.multisig_configs.insert(account_id, config);
+        }
+        None => {
+            relayer.multisig_configs.remove(&account_id);
+            RelayerEvent::MultisigConfigured { account_id, signers: vec![], threshold: 0 }.emit();
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,171 @@
+use near_sdk::{env, borsh, CurveType, Gas, NearToken, Promise, PublicKey};
+use near_crypto::KeyType;
+use ed25519_dalek::{Verifier, Signature as Ed25519Signature, VerifyingKey};
+use crate::state::Relayer;
+use crate::types::{Action, DelegateAction, NativeAction, NativeDelegateAction, NativePublicKey, NativeSignature, NativeSignedDelegateAction, SignatureScheme, SignedDelegateAction};
+use crate::errors::RelayerError;
+use crate::{fees, relay};
+
+// NEP-461 discriminant for on-chain NEP-366 delegate actions: 2^30 + 366.
+pub const SIGNED_DELEGATE_PREFIX: u32 = (1 << 30) + 366;
+
+// Wallets sign sha256(borsh(prefix) || borsh(delegate_action)).
+pub fn signing_hash(delegate: &NativeDelegateAction) -> Result<Vec<u8>, RelayerError> {
+    Ok(prefixed_hash(&borsh::to_vec(delegate).map_err(|_| RelayerError::InvalidNonce)?))
+}
+
+fn prefixed_hash(delegate_bytes: &[u8]) -> Vec<u8> {
+    let mut message = SIGNED_DELEGATE_PREFIX.to_le_bytes().to_vec();
+    message.extend_from_slice(delegate_bytes);
+    env::sha256(&message)
+}
+
+// The delegate action exactly as the wallet encoded it. `input` is the borsh
+// SignedDelegateAction `signed` was decoded from, so it is everything before the signature.
+fn delegate_bytes<'a>(input: &'a [u8], signed: &NativeSignedDelegateAction) -> Result<&'a [u8], RelayerError> {
+    let signature = borsh::to_vec(&signed.signature).map_err(|_| RelayerError::InvalidSignature)?;
+    input.strip_suffix(signature.as_slice()).ok_or(RelayerError::InvalidSignature)
+}
+
+fn to_public_key(public_key: &NativePublicKey) -> PublicKey {
+    match public_key {
+        NativePublicKey::Ed25519(key) => PublicKey::from_parts(CurveType::ED25519, key.to_vec()),
+        NativePublicKey::Secp256k1(key) => PublicKey::from_parts(CurveType::SECP256K1, key.to_vec()),
+    }.unwrap()
+}
+
+fn to_native_public_key(public_key: &PublicKey) -> Result<NativePublicKey, RelayerError> {
+    let bytes = public_key.as_bytes();
+    match bytes[0] {
+        tag if tag == KeyType::ED25519 as u8 => bytes[1..].try_into().map(NativePublicKey::Ed25519),
+        tag if tag == KeyType::SECP256K1 as u8 => bytes[1..].try_into().map(NativePublicKey::Secp256k1),
+        _ => return Err(RelayerError::Unauthorized),
+    }.map_err(|_| RelayerError::Unauthorized)
+}
+
+// Transfers and FunctionCalls are accepted. A contract can't emit a native Delegate action,
+// so both run with the relayer as predecessor: calls are only dispatched to methods the
+// receiver's policy lists, with their gas resolved by policy::function_call_gas like any other
+// sponsored call. DeployContract, AddKey, DeleteKey and the rest would act on the relayer's
+// own account, so they're refused by name before the nonce or fee is spent.
+fn to_action(action: &NativeAction) -> Result<Action, RelayerError> {
+    match action {
+        NativeAction::Transfer { deposit } => Ok(Action::Transfer { deposit: NearToken::from_yoctonear(*deposit) }),
+        NativeAction::FunctionCall { method_name, args, gas, deposit } => Ok(Action::FunctionCall {
+            method_name: method_name.clone(),
+            args: args.clone(),
+            gas: Gas::from_gas(*gas),
+            deposit: NearToken::from_yoctonear(*deposit),
+        }),
+        other => Err(RelayerError::UnsupportedNativeAction(other.type_name().to_string())),
+    }
+}
+
+fn to_native_action(action: &Action) -> Result<NativeAction, RelayerError> {
+    match action {
+        Action::Transfer { deposit } => Ok(NativeAction::Transfer { deposit: deposit.as_yoctonear() }),
+        Action::FunctionCall { method_name, args, gas, deposit } => Ok(NativeAction::FunctionCall {
+            method_name: method_name.clone(),
+            args: args.clone(),
+            gas: gas.as_gas(),
+            deposit: deposit.as_yoctonear(),
+        }),
+        _ => Err(RelayerError::UnsupportedAction),
+    }
+}
+
+pub fn to_native(delegate: &DelegateAction, public_key: &PublicKey) -> Result<NativeDelegateAction, RelayerError> {
+    Ok(NativeDelegateAction {
+        sender_id: delegate.sender_id.clone(),
+        receiver_id: delegate.receiver_id.clone(),
+        actions: delegate.actions.iter().map(to_native_action).collect::<Result<_, _>>()?,
+        nonce: delegate.nonce,
+        max_block_height: delegate.max_block_height,
+        public_key: to_native_public_key(public_key)?,
+    })
+}
+
+// Rebuilds the canonical delegate and checks the wallet signature over its prefixed hash.
+// relay_signed_delegate only admits delegates whose rebuilt encoding is the signed one.
+pub fn verify(delegate: &DelegateAction, public_key: &PublicKey, signature: &[u8]) -> Result<(), RelayerError> {
+    let native = to_native(delegate, public_key)?;
+    verify_hash(&native.public_key, &signing_hash(&native)?, signature)
+}
+
+fn verify_hash(public_key: &NativePublicKey, hash: &[u8], signature: &[u8]) -> Result<(), RelayerError> {
+    match *public_key {
+        NativePublicKey::Ed25519(key) => {
+            let signature: [u8; 64] = signature.try_into().map_err(|_| RelayerError::InvalidSignature)?;
+            let verifying_key = VerifyingKey::from_bytes(&key).map_err(|_| RelayerError::Unauthorized)?;
+            verifying_key.verify(hash, &Ed25519Signature::from_bytes(&signature)).map_err(|_| RelayerError::InvalidSignature)
+        }
+        NativePublicKey::Secp256k1(key) => {
+            if signature.len() != 65 || signature[64] > 1 {
+                return Err(RelayerError::InvalidSignature);
+            }
+            let recovered = env::ecrecover(hash, &signature[..64], signature[64], true).ok_or(RelayerError::InvalidSignature)?;
+            if recovered != key {
+                return Err(RelayerError::InvalidSignature);
+            }
+            Ok(())
+        }
+    }
+}
+
+fn signature_bytes(signature: &NativeSignature) -> Vec<u8> {
+    match signature {
+        NativeSignature::Ed25519(signature) => signature.to_vec(),
+        NativeSignature::Secp256k1(signature) => signature.to_vec(),
+    }
+}
+
+pub fn into_signed_delegate(signed: NativeSignedDelegateAction) -> Result<SignedDelegateAction, RelayerError> {
+    let native = signed.delegate_action;
+    let delegate_action = DelegateAction {
+        sender_id: native.sender_id,
+        receiver_id: native.receiver_id,
+        actions: native.actions.iter().map(to_action).collect::<Result<_, _>>()?,
+        nonce: native.nonce,
+        max_block_height: native.max_block_height,
+    };
+    Ok(SignedDelegateAction {
+        delegate_action,
+        signature: signature_bytes(&signed.signature),
+        public_key: to_public_key(&native.public_key),
+        // NEP-366 has no session field and wallets sign with the account's own access keys,
+        // so a native delegate is never signed by a relayer session key
+        session_nonce: 0,
+        scheme: SignatureScheme::Nep366,
+        fee_action: None,
+        multi_signatures: None,
+    })
+}
+
+// Contracts can't emit a native Delegate action, so the verified delegate goes through the
+// same auth check, admission and dispatch as relay_meta_transaction. The NEP-366 signature
+// over `input`, the call's raw borsh arguments, proves the sender, so anyone may submit it.
+// Transfer and call deposits come out of the relayer's account, so the attached deposit must
+// cover them as well as the relay fee.
+pub fn relay_signed_delegate(relayer: &mut Relayer, signed: NativeSignedDelegateAction, input: &[u8]) -> Result<Promise, RelayerError> {
+    verify_hash(&signed.delegate_action.public_key, &prefixed_hash(delegate_bytes(input, &signed)?), &signature_bytes(&signed.signature))?;
+    let native = signed.delegate_action.clone();
+    let mut signed_delegate = into_signed_delegate(signed)?;
+    // Admission and the auth callback re-check the signature on the converted delegate
+    if to_native(&signed_delegate.delegate_action, &signed_delegate.public_key)? != native {
+        return Err(RelayerError::InvalidSignature);
+    }
+    let action_deposit = signed_delegate.delegate_action.actions.iter()
+        .map(|action| match action {
+            Action::Transfer { deposit } | Action::FunctionCall { deposit, .. } => deposit.as_yoctonear(),
+            _ => 0,
+        })
+        .fold(0u128, u128::saturating_add);
+    let fee = fees::required_fee(relayer, &signed_delegate, fees::NEAR_ASSET);
+    if env::attached_deposit().as_yoctonear() < action_deposit.saturating_add(fee) {
+        return Err(RelayerError::InsufficientDeposit);
+    }
+    if fee > 0 {
+        signed_delegate.fee_action = Some(Action::Transfer { deposit: NearToken::from_yoctonear(fee) });
+    }
+    relay::relay_verified_delegate(relayer, signed_delegate, action_deposit)
+}
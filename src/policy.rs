@@ -0,0 +1,65 @@
+use near_sdk::{env, AccountId, Gas};
+use crate::state::Relayer;
+use crate::types::{Action, DelegateAction, ReceiverPolicy, Role};
+use crate::errors::RelayerError;
+use crate::events::RelayerEvent;
+
+pub const MAX_METHODS_PER_RECEIVER: usize = 50;
+
+// FunctionCalls are only sponsored for receivers with a policy listing the method, and
+// only within that method's deposit and gas limits. Transfers come out of the relayer's
+// own balance, so they need a policy too and together stay within its max_transfer.
+pub fn check(relayer: &Relayer, delegate: &DelegateAction) -> Result<(), RelayerError> {
+    let mut transferred: u128 = 0;
+    for action in &delegate.actions {
+        match action {
+            Action::FunctionCall { method_name, gas, deposit, .. } => {
+                let policy = relayer.call_policies.get(&delegate.receiver_id)
+                    .ok_or(RelayerError::ReceiverNotAllowed)?;
+                let method = policy.methods.iter()
+                    .find(|method| method.method_name == *method_name)
+                    .ok_or(RelayerError::MethodNotAllowed)?;
+                if deposit.as_yoctonear() > method.max_deposit.0 || function_call_gas(relayer, *gas) > method.max_gas {
+                    return Err(RelayerError::PolicyLimitExceeded);
+                }
+            }
+            Action::Transfer { deposit } => transferred = transferred.saturating_add(deposit.as_yoctonear()),
+            _ => {}
+        }
+    }
+    if transferred > 0 {
+        let policy = relayer.call_policies.get(&delegate.receiver_id)
+            .ok_or(RelayerError::ReceiverNotAllowed)?;
+        if transferred > policy.max_transfer.0 {
+            return Err(RelayerError::PolicyLimitExceeded);
+        }
+    }
+    Ok(())
+}
+
+// Gas attached to a FunctionCall: what the user asked for, or cross_contract_gas when they
+// left it at zero. check() holds the result to the method's max_gas, so a zero request can't
+// get past the limit an explicit one would hit.
+pub fn function_call_gas(relayer: &Relayer, requested: Gas) -> Gas {
+    if requested.as_gas() == 0 { Gas::from_gas(relayer.cross_contract_gas) } else { requested }
+}
+
+pub fn set_call_policy(relayer: &mut Relayer, receiver_id: AccountId, policy: Option<ReceiverPolicy>) -> Result<(), RelayerError> {
+    let caller = env::predecessor_account_id();
+    if !relayer.has_role(Role::ConfigAdmin, &caller) {
+        return Err(RelayerError::Unauthorized);
+    }
+    match &policy {
+        Some(policy) => {
+            if (policy.methods.is_empty() && policy.max_transfer.0 == 0) || policy.methods.len() > MAX_METHODS_PER_RECEIVER {
+                return Err(RelayerError::InvalidPolicy);
+            }
+            relayer.call_policies.insert(receiver_id.clone(), policy.clone());
+        }
+        None => {
+            relayer.call_policies.remove(&receiver_id);
+        }
+    }
+    RelayerEvent::CallPolicyUpdated { receiver_id, policy }.emit();
+    Ok(())
+}
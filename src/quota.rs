@@ -0,0 +1,156 @@
+use near_sdk::{env, AccountId};
+use crate::state::Relayer;
+use crate::types::{QuotaStatus, QuotaTier, QuotaUsage, Role};
+use crate::errors::RelayerError;
+use crate::events::RelayerEvent;
+
+pub const DEFAULT_TIER: &str = "default";
+pub const MAX_RELAYS_PER_WINDOW: u32 = 1_000;
+const DAY_MS: u64 = 86_400_000;
+
+pub fn default_tier() -> QuotaTier {
+    QuotaTier {
+        max_relays: 100,
+        window_ms: 3_600_000, // 100 relays per rolling hour
+        max_gas_per_day: 100_000_000_000_000_000, // 100,000 TGas
+    }
+}
+
+// Gas the relayer commits to any delegate: the mpc, auth and callback hops. A delegate is
+// charged its full required gas, which is never less.
+pub fn relay_gas(relayer: &Relayer) -> u64 {
+    relayer.cross_contract_gas.saturating_mul(3)
+}
+
+pub fn tier_of(relayer: &Relayer, account_id: &AccountId) -> (String, QuotaTier) {
+    let name = relayer.account_tiers.get(account_id).cloned().unwrap_or_else(|| DEFAULT_TIER.to_string());
+    let tier = relayer.quota_tiers.get(&name).cloned().unwrap_or_else(default_tier);
+    (name, tier)
+}
+
+// Moves the window and the daily gas bucket up to now. Only two window counters are kept,
+// so a sender's usage takes constant storage whatever the tier allows.
+fn current_usage(relayer: &Relayer, account_id: &AccountId, tier: &QuotaTier) -> QuotaUsage {
+    let now = env::block_timestamp_ms();
+    let mut usage = relayer.quota_usage.get(account_id).cloned().unwrap_or_default();
+    let window_start_ms = now - now % tier.window_ms;
+    if window_start_ms != usage.window_start_ms {
+        let previous_window = usage.window_start_ms.saturating_add(tier.window_ms) == window_start_ms;
+        usage.relays_in_previous_window = if previous_window { usage.relays_in_window } else { 0 };
+        usage.relays_in_window = 0;
+        usage.window_start_ms = window_start_ms;
+    }
+    if now.saturating_sub(usage.day_start_ms) >= DAY_MS {
+        usage.day_start_ms = now - now % DAY_MS;
+        usage.gas_used = 0;
+    }
+    usage
+}
+
+// Relays in the rolling window ending now: the current window's, plus the previous window's
+// weighted by how much of it the rolling window still covers, rounded up.
+fn relays_used(usage: &QuotaUsage, tier: &QuotaTier) -> u32 {
+    let elapsed = env::block_timestamp_ms().saturating_sub(usage.window_start_ms).min(tier.window_ms);
+    let carried = (usage.relays_in_previous_window as u64 * (tier.window_ms - elapsed)).div_ceil(tier.window_ms);
+    usage.relays_in_window.saturating_add(carried as u32)
+}
+
+pub fn consume(relayer: &mut Relayer, account_id: &AccountId, gas: u64) -> Result<(), RelayerError> {
+    let (_, tier) = tier_of(relayer, account_id);
+    let mut usage = current_usage(relayer, account_id, &tier);
+    if relays_used(&usage, &tier) >= tier.max_relays {
+        RelayerEvent::QuotaExceeded { account_id: account_id.clone(), quota: "relays".to_string(), limit: tier.max_relays as u64 }.emit();
+        return Err(RelayerError::QuotaExceeded);
+    }
+    if usage.gas_used.saturating_add(gas) > tier.max_gas_per_day {
+        RelayerEvent::QuotaExceeded { account_id: account_id.clone(), quota: "gas".to_string(), limit: tier.max_gas_per_day }.emit();
+        return Err(RelayerError::QuotaExceeded);
+    }
+    usage.relays_in_window += 1;
+    usage.gas_used += gas;
+    relayer.quota_usage.insert(account_id.clone(), usage);
+    Ok(())
+}
+
+// Gives back the relay and gas a rejected delegate took at `admitted_at_ms`.
+pub fn release(relayer: &mut Relayer, account_id: &AccountId, gas: u64, admitted_at_ms: u64) {
+    let (_, tier) = tier_of(relayer, account_id);
+    if !relayer.quota_usage.contains_key(account_id) {
+        return;
+    }
+    let mut usage = current_usage(relayer, account_id, &tier);
+    if admitted_at_ms >= usage.window_start_ms {
+        usage.relays_in_window = usage.relays_in_window.saturating_sub(1);
+    } else if admitted_at_ms.saturating_add(tier.window_ms) >= usage.window_start_ms {
+        usage.relays_in_previous_window = usage.relays_in_previous_window.saturating_sub(1);
+    }
+    if admitted_at_ms >= usage.day_start_ms {
+        usage.gas_used = usage.gas_used.saturating_sub(gas);
+    }
+    relayer.quota_usage.insert(account_id.clone(), usage);
+}
+
+pub fn status(relayer: &Relayer, account_id: &AccountId) -> QuotaStatus {
+    let (tier_name, tier) = tier_of(relayer, account_id);
+    let usage = current_usage(relayer, account_id, &tier);
+    let relays_used = relays_used(&usage, &tier);
+    QuotaStatus {
+        tier: tier_name,
+        relays_used,
+        relays_remaining: tier.max_relays.saturating_sub(relays_used),
+        window_ms: tier.window_ms,
+        gas_used_today: usage.gas_used,
+        gas_remaining_today: tier.max_gas_per_day.saturating_sub(usage.gas_used),
+    }
+}
+
+pub fn set_quota_tier(relayer: &mut Relayer, name: String, tier: QuotaTier) -> Result<(), RelayerError> {
+    let caller = env::predecessor_account_id();
+    if !relayer.has_role(Role::ConfigAdmin, &caller) {
+        return Err(RelayerError::Unauthorized);
+    }
+    if tier.max_relays == 0 || tier.max_relays > MAX_RELAYS_PER_WINDOW || tier.window_ms == 0 {
+        return Err(RelayerError::AmountTooLow);
+    }
+    // A day's gas must cover at least one relay, and a window can't outlast the day it is
+    // budgeted against
+    if tier.max_gas_per_day < relay_gas(relayer) || tier.window_ms > DAY_MS {
+        return Err(RelayerError::InvalidTier);
+    }
+    RelayerEvent::QuotaTierUpdated { name: name.clone(), tier: Some(tier.clone()) }.emit();
+    relayer.quota_tiers.insert(name, tier);
+    Ok(())
+}
+
+pub fn remove_quota_tier(relayer: &mut Relayer, name: String) -> Result<(), RelayerError> {
+    let caller = env::predecessor_account_id();
+    if !relayer.has_role(Role::ConfigAdmin, &caller) {
+        return Err(RelayerError::Unauthorized);
+    }
+    if name == DEFAULT_TIER {
+        return Err(RelayerError::InvalidTier);
+    }
+    relayer.quota_tiers.remove(&name);
+    RelayerEvent::QuotaTierUpdated { name, tier: None }.emit();
+    Ok(())
+}
+
+pub fn set_account_tier(relayer: &mut Relayer, account_id: AccountId, tier: Option<String>) -> Result<(), RelayerError> {
+    let caller = env::predecessor_account_id();
+    if !relayer.has_role(Role::ConfigAdmin, &caller) {
+        return Err(RelayerError::Unauthorized);
+    }
+    match &tier {
+        Some(name) => {
+            if !relayer.quota_tiers.contains_key(name) {
+                return Err(RelayerError::InvalidTier);
+            }
+            relayer.account_tiers.insert(account_id.clone(), name.clone());
+        }
+        None => {
+            relayer.account_tiers.remove(&account_id);
+        }
+    }
+    RelayerEvent::AccountTierUpdated { account_id, tier }.emit();
+    Ok(())
+}
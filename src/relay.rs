@@ -1,32 +1,94 @@
-use near_sdk::{Promise, Allowance, NearToken, env, AccountId, Gas};
+use near_sdk::{Promise, Allowance, NearToken, env, AccountId, Gas, PublicKey};
 use near_sdk::borsh::{self, BorshSerialize, BorshDeserialize};
 use core::num::NonZeroU128;
-use crate::{ext_self, ext_auth, ext_ft_wrapper, ext_omi_locker, ext_mpc, state::Relayer, types::{SignedDelegateAction, Action, SignatureScheme}, errors::RelayerError, events::RelayerEvent};
+use crate::{bridge, chains, fees, ledger, multisig, nep366, policy, quota, session};
+use crate::{ext_self, ext_auth, ext_ft_wrapper, ext_omi_locker, ext_mpc, state::Relayer, types::{SignedDelegateAction, DelegateAction, Action, SignatureScheme, PendingDelegate, SigningDomain, Subsystem, WebAuthnAssertion, WebAuthnConfig}, errors::RelayerError, events::RelayerEvent};
 use near_crypto::{KeyType};
 use ed25519_dalek::{Verifier, Signature as Ed25519Signature, VerifyingKey};
-use base64::engine::general_purpose::STANDARD as Base64;
+use base64::engine::general_purpose::{STANDARD as Base64, URL_SAFE_NO_PAD as Base64Url};
 use base64::Engine;
+use p256::ecdsa::{Signature as P256Signature, VerifyingKey as P256VerifyingKey};
+use serde::Deserialize;
 
-#[derive(BorshSerialize, BorshDeserialize)]
-struct SignRequest {
-    payload: Vec<u8>,
-    path: String,
-    key_version: u32,
-    request_id: u64,
+#[derive(Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    kind: String,
+    challenge: String,
+    origin: String,
+    #[serde(rename = "crossOrigin", default)]
+    cross_origin: bool,
 }
 
 #[derive(BorshSerialize, BorshDeserialize)]
-struct BridgeTransferPayload {
-    token: String,
-    amount: u128,
-    destination_chain: String,
-    recipient: String,
-    sender: AccountId,
-    nonce: u64,
+pub(crate) struct SignRequest {
+    pub(crate) payload: Vec<u8>,
+    pub(crate) path: String,
+    pub(crate) key_version: u32,
+    pub(crate) request_id: u64,
+}
+
+pub const SIGNING_DOMAIN_TAG: &str = "onsocial-relayer/delegate";
+pub const SIGNING_SCHEMA_VERSION: u32 = 2;
+const USER_PRESENT_AND_VERIFIED: u8 = 0x05;
+
+// Network the relayer is deployed to, told apart the way network_mpc_contract does it:
+// .near is mainnet, .testnet is testnet and anything else (sandbox, localnet) is local.
+pub fn chain_id() -> String {
+    let current_account_id = env::current_account_id();
+    if current_account_id.as_str().ends_with(".near") {
+        "mainnet"
+    } else if current_account_id.as_str().ends_with(".testnet") {
+        "testnet"
+    } else {
+        "local"
+    }
+    .to_string()
 }
 
-pub fn verify_signature(signed_delegate: &SignedDelegateAction, tx_hash: &[u8]) -> Result<(), RelayerError> {
-    let payload = borsh::to_vec(&signed_delegate.delegate_action).map_err(|_| RelayerError::InvalidNonce)?;
+// NEAR's chain-signature contract on the network the relayer is deployed to. Other networks
+// (sandbox, localnet) have no default and must configure a nonce oracle.
+pub fn network_mpc_contract() -> Option<AccountId> {
+    let current_account_id = env::current_account_id();
+    let mpc_contract = if current_account_id.as_str().ends_with(".testnet") {
+        "v1.signer-prod.testnet"
+    } else if current_account_id.as_str().ends_with(".near") {
+        "v1.signer"
+    } else {
+        return None;
+    };
+    mpc_contract.parse().ok()
+}
+
+// Contract that hands out nonces for relayed delegates: the configured one, else the network's.
+pub fn nonce_oracle(relayer: &Relayer) -> Result<AccountId, RelayerError> {
+    relayer.nonce_oracle.clone().or_else(network_mpc_contract).ok_or(RelayerError::MpcNotConfigured)
+}
+
+pub fn signing_domain(session_nonce: u64) -> SigningDomain {
+    SigningDomain {
+        tag: SIGNING_DOMAIN_TAG.to_string(),
+        chain_id: chain_id(),
+        relayer_id: env::current_account_id(),
+        version: SIGNING_SCHEMA_VERSION,
+        session_nonce,
+    }
+}
+
+// Bytes a delegate signature covers: the domain ties it to one chain and one relayer
+// deployment, so it can't be replayed against another, and to the session it was signed for.
+pub fn signing_payload(delegate: &DelegateAction, session_nonce: u64) -> Result<Vec<u8>, RelayerError> {
+    let mut payload = borsh::to_vec(&signing_domain(session_nonce)).map_err(|_| RelayerError::InvalidNonce)?;
+    payload.extend(borsh::to_vec(delegate).map_err(|_| RelayerError::InvalidNonce)?);
+    Ok(payload)
+}
+
+pub fn delegate_hash(delegate: &DelegateAction, session_nonce: u64) -> Result<Vec<u8>, RelayerError> {
+    Ok(env::sha256(&signing_payload(delegate, session_nonce)?))
+}
+
+pub fn verify_signature(relayer: &Relayer, signed_delegate: &SignedDelegateAction, tx_hash: &[u8]) -> Result<(), RelayerError> {
+    let payload = signing_payload(&signed_delegate.delegate_action, signed_delegate.session_nonce)?;
     if env::sha256(&payload) != tx_hash {
         return Err(RelayerError::InvalidSignature);
     }
@@ -42,47 +104,282 @@ pub fn verify_signature(signed_delegate: &SignedDelegateAction, tx_hash: &[u8])
                 .map_err(|_| RelayerError::Unauthorized)?;
             ed25519_key.verify(&payload, &signature).map_err(|_| RelayerError::Unauthorized)?;
         }
+        SignatureScheme::Secp256k1 => verify_secp256k1(&signed_delegate.public_key, &signed_delegate.signature, tx_hash)?,
+        SignatureScheme::WebAuthnP256 => {
+            let config = relayer.webauthn.as_ref().ok_or(RelayerError::Unauthorized)?;
+            verify_webauthn_p256(config, &signed_delegate.public_key, &signed_delegate.signature, tx_hash)?
+        }
+        SignatureScheme::Nep366 => nep366::verify(&signed_delegate.delegate_action, &signed_delegate.public_key, &signed_delegate.signature)?,
     }
     Ok(())
 }
 
-pub fn relay_meta_transaction(relayer: &mut Relayer, signed_delegate: SignedDelegateAction) -> Result<Promise, RelayerError> {
-    assert!(env::prepaid_gas() >= Gas::from_tgas(250), "Attach at least 250 TGas");
-    if signed_delegate.delegate_action.actions.len() > 1 {
-        return Err(RelayerError::InvalidNonce);
+// EVM wallets sign the delegate hash with personal_sign, so the recovered key is compared
+// against the sender's secp256k1 key.
+pub fn verify_secp256k1(public_key: &PublicKey, signature: &[u8], tx_hash: &[u8]) -> Result<(), RelayerError> {
+    let public_key_bytes = public_key.as_bytes();
+    if public_key_bytes.len() != 65 || public_key_bytes[0] != KeyType::SECP256K1 as u8 {
+        return Err(RelayerError::Unauthorized);
     }
-    let sender_id = &signed_delegate.delegate_action.sender_id;
-    // Verify signer matches sender_id to prevent intermediary manipulation
-    if env::signer_account_id() != *sender_id {
+    if signature.len() != 65 {
         return Err(RelayerError::Unauthorized);
     }
-    let balance = env::account_balance().as_yoctonear();
-    if balance < relayer.min_balance {
-        RelayerEvent::LowBalance { balance }.emit();
-        return Err(RelayerError::InsufficientBalance);
+    let v = match signature[64] {
+        v @ 0..=1 => v,
+        v @ 27..=28 => v - 27,
+        _ => return Err(RelayerError::Unauthorized),
+    };
+    let mut message = b"\x19Ethereum Signed Message:\n32".to_vec();
+    message.extend_from_slice(tx_hash);
+    let recovered = env::ecrecover(&env::keccak256(&message), &signature[..64], v, true)
+        .ok_or(RelayerError::Unauthorized)?;
+    if recovered[..] != public_key_bytes[1..] {
+        return Err(RelayerError::Unauthorized);
+    }
+    Ok(())
+}
+
+// P-256 keys cannot be NEAR public keys, so passkey users register an ed25519-shaped key
+// whose data is sha256 of the compressed SEC1 key; that id is what the auth contract checks.
+// The assertion must come from the configured relying party, with the user present and verified.
+pub fn verify_webauthn_p256(config: &WebAuthnConfig, public_key: &PublicKey, signature: &[u8], tx_hash: &[u8]) -> Result<(), RelayerError> {
+    let assertion: WebAuthnAssertion = borsh::from_slice(signature).map_err(|_| RelayerError::InvalidSignature)?;
+    let verifying_key = P256VerifyingKey::from_sec1_bytes(&assertion.public_key).map_err(|_| RelayerError::Unauthorized)?;
+    let key_id = env::sha256(verifying_key.to_encoded_point(true).as_bytes());
+    let public_key_bytes = public_key.as_bytes();
+    if public_key_bytes[0] != KeyType::ED25519 as u8 || public_key_bytes[1..] != key_id[..] {
+        return Err(RelayerError::Unauthorized);
+    }
+    let client_data: ClientData = serde_json::from_str(&assertion.client_data_json).map_err(|_| RelayerError::InvalidSignature)?;
+    if client_data.kind != "webauthn.get" || client_data.challenge != Base64Url.encode(tx_hash) {
+        return Err(RelayerError::InvalidSignature);
+    }
+    if client_data.cross_origin || !config.origins.contains(&client_data.origin) {
+        return Err(RelayerError::InvalidSignature);
+    }
+    // rpIdHash (32) || flags (1) || signCount (4); user present (0x01) and verified (0x04)
+    let authenticator_data = &assertion.authenticator_data;
+    if authenticator_data.len() < 37 || authenticator_data[..32] != env::sha256(config.rp_id.as_bytes())[..] {
+        return Err(RelayerError::InvalidSignature);
+    }
+    if authenticator_data[32] & USER_PRESENT_AND_VERIFIED != USER_PRESENT_AND_VERIFIED {
+        return Err(RelayerError::InvalidSignature);
+    }
+    let signature = if assertion.signature.len() == 64 {
+        P256Signature::from_slice(&assertion.signature)
+    } else {
+        P256Signature::from_der(&assertion.signature)
+    }.map_err(|_| RelayerError::Unauthorized)?;
+    let mut message = assertion.authenticator_data.clone();
+    message.extend_from_slice(&env::sha256(assertion.client_data_json.as_bytes()));
+    verifying_key.verify(&message, &signature).map_err(|_| RelayerError::Unauthorized)
+}
+
+fn ensure_relay_allowed(relayer: &Relayer, signed_delegate: &SignedDelegateAction) -> Result<(), RelayerError> {
+    relayer.ensure_not_paused(Subsystem::Relay)?;
+    let action_count = signed_delegate.delegate_action.actions.len();
+    if action_count == 0 {
+        return Err(RelayerError::MissingInput);
+    }
+    if action_count > relayer.max_actions {
+        return Err(RelayerError::TooManyActions);
+    }
+    let delegate = &signed_delegate.delegate_action;
+    // Calls on the relayer itself would run with its own account as predecessor
+    if delegate.receiver_id == env::current_account_id() {
+        return Err(RelayerError::ReceiverNotAllowed);
+    }
+    // AddKey would run with the relayer as predecessor and can't touch the sender's account;
+    // it has to go through the off-chain relayer as a native delegate instead
+    if delegate.actions.iter().any(|action| matches!(action, Action::AddKey { .. })) {
+        return Err(RelayerError::UnsupportedAction);
+    }
+    if delegate.actions.iter().any(Action::is_cross_chain) {
+        relayer.ensure_not_paused(Subsystem::Bridge)?;
+    }
+    policy::check(relayer, delegate)?;
+    // Reject gas requests the call can't carry now rather than deep in the promise chain
+    ensure_gas_available(required_gas(relayer, signed_delegate))?;
+    multisig::verify_delegate(relayer, signed_delegate)
+}
+
+// Gas a delegate's promise chain reserves from the relay call: the MPC nonce and auth calls,
+// the auth callback with everything it dispatches, and the settlement, each with its receipt.
+pub fn required_gas(relayer: &Relayer, signed_delegate: &SignedDelegateAction) -> u64 {
+    relayer.cross_contract_gas.saturating_mul(2)
+        .saturating_add(auth_callback_gas(relayer, signed_delegate).as_gas())
+        .saturating_add(fees::SETTLE_FEE_GAS.as_gas())
+        .saturating_add(ledger::RECEIPT_GAS.saturating_mul(4))
+}
+
+fn ensure_gas_available(required: u64) -> Result<(), RelayerError> {
+    if required > env::prepaid_gas().as_gas().saturating_sub(env::used_gas().as_gas()) {
+        return Err(RelayerError::GasRequestTooLarge);
+    }
+    Ok(())
+}
+
+// Each delegate fits on its own, but the batch shares one call's gas.
+fn ensure_batch_gas(relayer: &Relayer, signed_delegates: &[SignedDelegateAction]) -> Result<(), RelayerError> {
+    ensure_gas_available(signed_delegates.iter().fold(0u64, |total, signed_delegate| total.saturating_add(required_gas(relayer, signed_delegate))))
+}
+
+// Gas for the steps of a delegate from action `start` on. Each step is a run of receipt
+// actions sharing one receipt or a single other action, and is followed by the callback that
+// reports it.
+pub fn steps_gas(relayer: &Relayer, delegate: &DelegateAction, start: usize) -> u64 {
+    let mut total = 0u64;
+    let mut previous_was_receipt = false;
+    for action in delegate.actions.iter().skip(start) {
+        let joins_step = previous_was_receipt && action.is_receipt_action();
+        if !joins_step {
+            total = total.saturating_add(relayer.cross_contract_gas).saturating_add(ledger::RECEIPT_GAS);
+            if action.is_receipt_action() {
+                total = total.saturating_add(ledger::RECEIPT_GAS);
+            }
+        }
+        total = total.saturating_add(ledger::action_gas(relayer, action));
+        previous_was_receipt = action.is_receipt_action();
     }
-    let tx_hash = env::sha256(&borsh::to_vec(&signed_delegate.delegate_action).map_err(|_| RelayerError::InvalidNonce)?);
-    let mpc_contract = relayer.chain_mpc_mapping.get("testnet").cloned().unwrap_or("v1.signer-prod.testnet".parse().unwrap());
-    let promise = ext_mpc::ext(mpc_contract)
-        .with_static_gas(Gas::from_tgas(relayer.cross_contract_gas))
-        .get_nonce(sender_id.clone(), Base64.encode(tx_hash.clone()))
+    total
+}
+
+// The callback after the step ending at `end` runs itself and dispatches the rest.
+pub fn step_callback_gas(relayer: &Relayer, delegate: &DelegateAction, end: usize) -> Gas {
+    Gas::from_gas(relayer.cross_contract_gas.saturating_add(steps_gas(relayer, delegate, end)))
+}
+
+// The auth callback dispatches the first step, so it carries the gas of every step after it.
+fn auth_callback_gas(relayer: &Relayer, signed_delegate: &SignedDelegateAction) -> Gas {
+    step_callback_gas(relayer, &signed_delegate.delegate_action, 0)
+}
+
+// Spends everything a delegate needs before it is scheduled: its nonce, the session's spend
+// cap, the sender's quota, the sponsorship budgets and the fee, in that order. The signature
+// is checked first, so a malformed or forged delegate can't burn the sender's nonce. What
+// was spent is kept as a pending delegate until settle_delegate runs.
+fn admit_delegate(relayer: &mut Relayer, signed_delegate: &SignedDelegateAction, available_deposit: &mut u128) -> Result<(), RelayerError> {
+    let delegate = &signed_delegate.delegate_action;
+    verify_signature(relayer, signed_delegate, &delegate_hash(delegate, signed_delegate.session_nonce)?)?;
+    let previous_nonce = relayer.consume_delegate_nonce(&delegate.sender_id, &signed_delegate.public_key, delegate.nonce, delegate.max_block_height)?;
+    session::consume(relayer, signed_delegate)?;
+    let quota_gas = required_gas(relayer, signed_delegate);
+    quota::consume(relayer, &delegate.sender_id, quota_gas)?;
+    let spend = ledger::record(relayer, signed_delegate)?;
+    fees::collect_fee(relayer, signed_delegate, available_deposit)?;
+    bridge::escrow_fees(relayer, signed_delegate, available_deposit)?;
+    relayer.pending_delegates.insert(fees::hold_key(signed_delegate), PendingDelegate {
+        sender_id: delegate.sender_id.clone(),
+        receiver_id: delegate.receiver_id.clone(),
+        public_key: signed_delegate.public_key.clone(),
+        nonce: delegate.nonce,
+        previous_nonce,
+        session_nonce: signed_delegate.session_nonce,
+        admitted_at_ms: env::block_timestamp_ms(),
+        quota_gas,
+        spend,
+        action_count: delegate.actions.len() as u32,
+        authorized: false,
+    });
+    Ok(())
+}
+
+// Called by the auth callback once the delegate passed every check, before any action runs.
+pub fn mark_authorized(relayer: &mut Relayer, signed_delegate: &SignedDelegateAction) {
+    let key = fees::hold_key(signed_delegate);
+    if let Some(pending) = relayer.pending_delegates.get_mut(&key) {
+        pending.authorized = true;
+    }
+}
+
+// Runs after the auth callback. A delegate the callback didn't mark as authorized never ran,
+// so its held fee is refunded, its escrowed bridge fees become withdrawable and its nonce,
+// session spend, quota and ledger spend are given back. Returns whether the delegate was authorized.
+pub fn settle_delegate(relayer: &mut Relayer, key: &str) -> bool {
+    let Some(pending) = relayer.pending_delegates.remove(key) else {
+        return false;
+    };
+    fees::settle_held_fee(relayer, key, pending.authorized);
+    if pending.authorized {
+        return true;
+    }
+    bridge::release_quotes(relayer, key, &pending.sender_id, pending.action_count as usize);
+    relayer.release_delegate_nonce(&pending.sender_id, &pending.public_key, pending.nonce, pending.previous_nonce);
+    session::release(relayer, &pending);
+    quota::release(relayer, &pending.sender_id, pending.quota_gas, pending.admitted_at_ms);
+    ledger::release(relayer, &pending.sender_id, &pending.receiver_id, &pending.spend);
+    RelayerEvent::DelegateRejected { sender_id: pending.sender_id, public_key: pending.public_key, nonce: pending.nonce }.emit();
+    false
+}
+
+// Fetches the MPC nonce, asks the auth contract about the signer and hands the delegate to
+// handle_auth_result, then settles the admitted delegate on the flag that callback recorded.
+fn authorize_delegate(relayer: &Relayer, nonce_oracle: AccountId, signed_delegate: &SignedDelegateAction, tx_hash: Vec<u8>) -> Promise {
+    let sender_id = &signed_delegate.delegate_action.sender_id;
+    let promise = ext_mpc::ext(nonce_oracle)
+        .with_static_gas(Gas::from_gas(relayer.cross_contract_gas))
+        .get_nonce(sender_id.clone(), Base64.encode(tx_hash))
         .then(
             ext_auth::ext(relayer.auth_contract.clone())
-                .with_static_gas(Gas::from_tgas(relayer.cross_contract_gas))
+                .with_static_gas(Gas::from_gas(relayer.cross_contract_gas))
                 .is_authorized(sender_id.clone(), signed_delegate.public_key.clone(), signed_delegate.multi_signatures.clone())
         )
         .then(
             ext_self::ext(env::current_account_id())
-                .with_static_gas(Gas::from_tgas(relayer.cross_contract_gas))
+                .with_static_gas(auth_callback_gas(relayer, signed_delegate))
                 .handle_auth_result(sender_id.clone(), signed_delegate.clone(), true)
         );
+    let hold_key = fees::hold_key(signed_delegate);
+    if !relayer.pending_delegates.contains_key(&hold_key) {
+        return promise;
+    }
+    promise.then(
+        ext_self::ext(env::current_account_id())
+            .with_static_gas(fees::SETTLE_FEE_GAS)
+            .settle_delegate(hold_key)
+    )
+}
+
+pub fn relay_meta_transaction(relayer: &mut Relayer, signed_delegate: SignedDelegateAction) -> Result<Promise, RelayerError> {
+    assert!(env::prepaid_gas() >= Gas::from_tgas(250), "Attach at least 250 TGas");
+    ensure_relay_allowed(relayer, &signed_delegate)?;
+    // Verify signer matches sender_id to prevent intermediary manipulation
+    if env::signer_account_id() != signed_delegate.delegate_action.sender_id {
+        return Err(RelayerError::Unauthorized);
+    }
+    schedule_delegate(relayer, signed_delegate, "relay_meta_transaction", 0)
+}
+
+// For delegates whose signature was already checked against the sender's key, so the
+// submitter need not be the sender. `action_deposit` of the attached deposit funds the
+// delegate's own transfers and is kept out of the fee and the refund.
+pub fn relay_verified_delegate(relayer: &mut Relayer, signed_delegate: SignedDelegateAction, action_deposit: u128) -> Result<Promise, RelayerError> {
+    assert!(env::prepaid_gas() >= Gas::from_tgas(250), "Attach at least 250 TGas");
+    ensure_relay_allowed(relayer, &signed_delegate)?;
+    schedule_delegate(relayer, signed_delegate, "relay_signed_delegate", action_deposit)
+}
+
+fn schedule_delegate(relayer: &mut Relayer, signed_delegate: SignedDelegateAction, method: &str, action_deposit: u128) -> Result<Promise, RelayerError> {
+    let nonce_oracle = nonce_oracle(relayer)?;
+    let balance = env::account_balance().as_yoctonear();
+    if balance < relayer.min_balance {
+        RelayerEvent::LowBalance { balance }.emit();
+        return Err(RelayerError::InsufficientBalance);
+    }
+    let mut available_deposit = env::attached_deposit().as_yoctonear().checked_sub(action_deposit)
+        .ok_or(RelayerError::InsufficientDeposit)?;
+    admit_delegate(relayer, &signed_delegate, &mut available_deposit)?;
+    fees::refund_deposit(available_deposit);
+    let tx_hash = delegate_hash(&signed_delegate.delegate_action, signed_delegate.session_nonce)?;
+    let promise = authorize_delegate(relayer, nonce_oracle, &signed_delegate, tx_hash);
     let remaining_gas = env::prepaid_gas().as_tgas().saturating_sub(env::used_gas().as_tgas());
     // Alert if remaining gas is low
     if remaining_gas < 50 {
         RelayerEvent::LowGas { remaining_gas }.emit();
     }
     env::log_str(&format!(
-        "relay_meta_transaction: prepaid={} TGas, used={} TGas, remaining={} TGas",
+        "{}: prepaid={} TGas, used={} TGas, remaining={} TGas",
+        method,
         env::prepaid_gas().as_tgas(),
         env::used_gas().as_tgas(),
         remaining_gas
@@ -100,30 +397,23 @@ pub fn relay_meta_transactions(relayer: &mut Relayer, signed_delegates: Vec<Sign
         RelayerEvent::LowBalance { balance }.emit();
         return Err(RelayerError::InsufficientBalance);
     }
+    ensure_batch_gas(relayer, &signed_delegates)?;
+    let nonce_oracle = nonce_oracle(relayer)?;
     let mut promises: Vec<Promise> = Vec::new();
-    let mpc_contract = relayer.chain_mpc_mapping.get("testnet").cloned().unwrap_or("v1.signer-prod.testnet".parse().unwrap());
+    let mut available_deposit = env::attached_deposit().as_yoctonear();
     for signed_delegate in signed_delegates {
         let sender_id = &signed_delegate.delegate_action.sender_id;
         // Verify signer matches sender_id
         if env::signer_account_id() != *sender_id {
             return Err(RelayerError::Unauthorized);
         }
-        let tx_hash = env::sha256(&borsh::to_vec(&signed_delegate.delegate_action).map_err(|_| RelayerError::InvalidNonce)?);
-        let promise = ext_mpc::ext(mpc_contract.clone())
-            .with_static_gas(Gas::from_tgas(relayer.cross_contract_gas))
-            .get_nonce(sender_id.clone(), Base64.encode(tx_hash))
-            .then(
-                ext_auth::ext(relayer.auth_contract.clone())
-                    .with_static_gas(Gas::from_tgas(relayer.cross_contract_gas))
-                    .is_authorized(sender_id.clone(), signed_delegate.public_key.clone(), signed_delegate.multi_signatures.clone())
-            )
-            .then(
-                ext_self::ext(env::current_account_id())
-                    .with_static_gas(Gas::from_tgas(relayer.cross_contract_gas))
-                    .handle_auth_result(sender_id.clone(), signed_delegate.clone(), true)
-            );
+        ensure_relay_allowed(relayer, &signed_delegate)?;
+        admit_delegate(relayer, &signed_delegate, &mut available_deposit)?;
+        let tx_hash = delegate_hash(&signed_delegate.delegate_action, signed_delegate.session_nonce)?;
+        let promise = authorize_delegate(relayer, nonce_oracle.clone(), &signed_delegate, tx_hash);
         promises.push(promise);
     }
+    fees::refund_deposit(available_deposit);
     let remaining_gas = env::prepaid_gas().as_tgas().saturating_sub(env::used_gas().as_tgas());
     if remaining_gas < 50 {
         RelayerEvent::LowGas { remaining_gas }.emit();
@@ -147,7 +437,17 @@ pub fn relay_chunked_meta_transactions(relayer: &mut Relayer, signed_delegates:
         RelayerEvent::LowBalance { balance }.emit();
         return Err(RelayerError::InsufficientBalance);
     }
-    let mpc_contract = relayer.chain_mpc_mapping.get("testnet").cloned().unwrap_or("v1.signer-prod.testnet".parse().unwrap());
+    ensure_batch_gas(relayer, &signed_delegates)?;
+    let nonce_oracle = nonce_oracle(relayer)?;
+    // Admit every delegate up front so a replayed or unpaid delegate rejects the whole call
+    let mut available_deposit = env::attached_deposit().as_yoctonear();
+    for signed_delegate in signed_delegates.iter() {
+        ensure_relay_allowed(relayer, signed_delegate)?;
+        if env::signer_account_id() == signed_delegate.delegate_action.sender_id {
+            admit_delegate(relayer, signed_delegate, &mut available_deposit)?;
+        }
+    }
+    fees::refund_deposit(available_deposit);
     let mut all_promises = Vec::new();
     for chunk in signed_delegates.chunks(relayer.chunk_size) {
         let chunk_promises: Vec<Promise> = chunk.iter()
@@ -159,32 +459,17 @@ pub fn relay_chunked_meta_transactions(relayer: &mut Relayer, signed_delegates:
                         "panic".to_string(),
                         borsh::to_vec(&RelayerError::Unauthorized).unwrap_or_default(),
                         NearToken::from_yoctonear(0),
-                        Gas::from_tgas(relayer.cross_contract_gas),
+                        Gas::from_gas(relayer.cross_contract_gas),
                     );
                 }
-                match borsh::to_vec(&signed_delegate.delegate_action) {
-                    Ok(payload) => {
-                        let tx_hash = env::sha256(&payload);
-                        ext_mpc::ext(mpc_contract.clone())
-                            .with_static_gas(Gas::from_tgas(relayer.cross_contract_gas))
-                            .get_nonce(sender_id.clone(), Base64.encode(tx_hash))
-                            .then(
-                                ext_auth::ext(relayer.auth_contract.clone())
-                                    .with_static_gas(Gas::from_tgas(relayer.cross_contract_gas))
-                                    .is_authorized(sender_id.clone(), signed_delegate.public_key.clone(), signed_delegate.multi_signatures.clone())
-                            )
-                            .then(
-                                ext_self::ext(env::current_account_id())
-                                    .with_static_gas(Gas::from_tgas(relayer.cross_contract_gas))
-                                    .handle_auth_result(sender_id.clone(), signed_delegate.clone(), true)
-                            )
-                    }
+                match delegate_hash(&signed_delegate.delegate_action, signed_delegate.session_nonce) {
+                    Ok(tx_hash) => authorize_delegate(relayer, nonce_oracle.clone(), signed_delegate, tx_hash),
                     Err(_) => {
                         Promise::new(env::current_account_id()).function_call(
                             "panic".to_string(),
                             borsh::to_vec(&RelayerError::InvalidNonce).unwrap_or_default(),
                             NearToken::from_yoctonear(0),
-                            Gas::from_tgas(relayer.cross_contract_gas),
+                            Gas::from_gas(relayer.cross_contract_gas),
                         )
                     }
                 }
@@ -205,130 +490,164 @@ pub fn relay_chunked_meta_transactions(relayer: &mut Relayer, signed_delegates:
     Ok(all_promises)
 }
 
+// Adds a receipt action to `promise`, so several of them can share one atomic receipt.
+pub fn receipt_action(relayer: &Relayer, promise: Promise, action: &Action) -> Result<Promise, RelayerError> {
+    match action {
+        Action::FunctionCall { method_name, args, gas, deposit } => {
+            let gas = policy::function_call_gas(relayer, *gas);
+            if gas > env::prepaid_gas().saturating_sub(env::used_gas()) {
+                return Err(RelayerError::GasRequestTooLarge);
+            }
+            Ok(promise.function_call(
+                method_name.clone(),
+                args.clone(),
+                NearToken::from_yoctonear(deposit.as_yoctonear()),
+                gas
+            ))
+        }
+        Action::Transfer { deposit } => Ok(promise.transfer(NearToken::from_yoctonear(deposit.as_yoctonear()))),
+        Action::AddKey { public_key, allowance, receiver_id, method_names } => Ok(promise.add_access_key_allowance(
+            public_key.clone(),
+            allowance.map_or(Allowance::Unlimited, |t| Allowance::Limited(NonZeroU128::new(t.as_yoctonear()).unwrap())),
+            receiver_id.clone(),
+            method_names.join(",")
+        )),
+        _ => Err(RelayerError::UnsupportedAction),
+    }
+}
+
+// Schedules `promise` after `previous`, if any. `promise` must not be chained yet.
+pub fn after(previous: Option<Promise>, promise: Promise) -> Promise {
+    match previous {
+        Some(previous) => previous.then(promise),
+        None => promise,
+    }
+}
+
+// Gas for the ft wrapper's is_registered checks. The registration callback runs on top of
+// the storage deposit it may send, which gets cross_contract_gas like the transfer itself.
+pub const FT_CHECK_GAS: Gas = Gas::from_tgas(10);
+const FT_CALLBACK_GAS: Gas = Gas::from_tgas(10);
+
+fn ft_registration_gas(relayer: &Relayer) -> Gas {
+    FT_CALLBACK_GAS.saturating_add(Gas::from_gas(relayer.cross_contract_gas.saturating_add(ledger::RECEIPT_GAS)))
+}
+
+// Gas an FtTransfer attaches: both registration checks with their callbacks, then the
+// transfer, each with its receipt fees.
+pub fn ft_transfer_gas(relayer: &Relayer) -> u64 {
+    FT_CHECK_GAS.saturating_add(ft_registration_gas(relayer)).as_gas()
+        .saturating_add(ledger::RECEIPT_GAS.saturating_mul(2))
+        .saturating_mul(2)
+        .saturating_add(relayer.cross_contract_gas.saturating_add(ledger::RECEIPT_GAS))
+}
+
 pub fn execute_action(
     relayer: &mut Relayer,
     action: &Action,
     sender_id: &AccountId,
-    _action_type: &str,
+    receiver_id: &AccountId,
+    quote_key: Option<&str>,
+    request_id: Option<u64>,
+) -> Result<Promise, RelayerError> {
+    execute_action_after(relayer, None, action, sender_id, receiver_id, quote_key, request_id)
+}
+
+// Like execute_action, but the action's first receipt waits for `previous`. A BridgeTransfer
+// charges the fee quoted under `quote_key` when its delegate was admitted.
+pub fn execute_action_after(
+    relayer: &mut Relayer,
+    previous: Option<Promise>,
+    action: &Action,
+    sender_id: &AccountId,
+    receiver_id: &AccountId,
+    quote_key: Option<&str>,
     request_id: Option<u64>,
 ) -> Result<Promise, RelayerError> {
-    assert!(env::prepaid_gas() >= Gas::from_tgas(250), "Attach at least 250 TGas");
     let initial_storage = env::storage_usage();
-    let mut promise = Promise::new(sender_id.clone());
-    match action {
-        Action::FunctionCall { method_name, args, gas: _, deposit } => {
-            promise = promise.function_call(
-                method_name.clone(),
-                args.clone(),
-                NearToken::from_yoctonear(deposit.as_yoctonear()),
-                Gas::from_tgas(relayer.cross_contract_gas)
-            );
-        }
-        Action::Transfer { deposit } => {
-            promise = promise.transfer(NearToken::from_yoctonear(deposit.as_yoctonear()));
-        }
-        Action::AddKey { public_key, allowance, receiver_id, method_names } => {
-            promise = promise.add_access_key_allowance(
-                public_key.clone(),
-                allowance.map_or(Allowance::Unlimited, |t| Allowance::Limited(NonZeroU128::new(t.as_yoctonear()).unwrap())),
-                receiver_id.clone(),
-                method_names.join(",")
-            );
+    // Actions land on the contract the user signed for, as in NEP-366. A contract can't emit
+    // a native Delegate action, so they run with the relayer as predecessor; AddKey is refused
+    // before relaying and needs the off-chain relayer to submit a native transaction.
+    let promise = match action {
+        Action::FunctionCall { .. } | Action::Transfer { .. } | Action::AddKey { .. } => {
+            receipt_action(relayer, after(previous, Promise::new(receiver_id.clone())), action)?
         }
         Action::ChainSignatureRequest { target_chain, derivation_path, payload } => {
-            let mpc_contract = relayer.chain_mpc_mapping.get(target_chain)
-                .ok_or(RelayerError::InvalidAccountId)?;
+            relayer.ensure_not_paused(Subsystem::Bridge)?;
+            let chain = chains::enabled_chain(relayer, target_chain)?;
+            let mpc_contract = chain.mpc_contract.clone();
             let request = SignRequest {
                 payload: payload.clone(),
-                path: derivation_path.clone(),
-                key_version: 0,
+                path: chain.derivation_path(sender_id, derivation_path),
+                key_version: chain.key_version,
                 request_id: request_id.ok_or(RelayerError::InvalidNonce)?,
             };
             let args = borsh::to_vec(&request).map_err(|_| RelayerError::InvalidAccountId)?;
-            promise = Promise::new(mpc_contract.clone())
+            after(previous, Promise::new(mpc_contract.clone())
                 .function_call(
                     "sign".to_string(),
                     args,
                     NearToken::from_yoctonear(1),
-                    Gas::from_tgas(relayer.cross_contract_gas)
-                );
+                    Gas::from_gas(relayer.cross_contract_gas)
+                ))
         }
         Action::FtTransfer { token, receiver_id, amount, memo } => {
-            let sender_promise = ext_ft_wrapper::ext(relayer.ft_wrapper_contract.clone())
-                .with_static_gas(Gas::from_tgas(100))
-                .is_registered(token.clone(), sender_id.clone())
+            // Each registration check feeds its own callback, so the steps run one after another
+            let sender_check = ext_ft_wrapper::ext(relayer.ft_wrapper_contract.clone())
+                .with_static_gas(FT_CHECK_GAS)
+                .is_registered(token.clone(), sender_id.clone());
+            after(previous, sender_check)
                 .then(
                     ext_self::ext(env::current_account_id())
-                        .with_static_gas(Gas::from_tgas(100))
+                        .with_static_gas(ft_registration_gas(relayer))
                         .handle_registration(sender_id.clone(), token.clone(), true, true)
-                );
-            let receiver_promise = ext_ft_wrapper::ext(relayer.ft_wrapper_contract.clone())
-                .with_static_gas(Gas::from_tgas(100))
-                .is_registered(token.clone(), receiver_id.clone())
+                )
+                .then(
+                    ext_ft_wrapper::ext(relayer.ft_wrapper_contract.clone())
+                        .with_static_gas(FT_CHECK_GAS)
+                        .is_registered(token.clone(), receiver_id.clone())
+                )
                 .then(
                     ext_self::ext(env::current_account_id())
-                        .with_static_gas(Gas::from_tgas(100))
+                        .with_static_gas(ft_registration_gas(relayer))
                         .handle_registration(receiver_id.clone(), token.clone(), false, true)
-                );
-            promise = sender_promise
-                .then(receiver_promise)
+                )
                 .then(
                     ext_ft_wrapper::ext(relayer.ft_wrapper_contract.clone())
-                        .with_static_gas(Gas::from_tgas(100))
+                        .with_static_gas(Gas::from_gas(relayer.cross_contract_gas))
                         .ft_transfer(token.clone(), receiver_id.clone(), *amount, memo.clone())
-                );
+                )
         }
         Action::BridgeTransfer { token, amount, destination_chain, recipient } => {
-            let fee = relayer.base_fee;
-            let balance = env::account_balance().as_yoctonear();
-            // Check if relayer can cover the fee
-            if balance < relayer.min_balance + fee {
-                RelayerEvent::LowBalance { balance }.emit();
-                return Err(RelayerError::InsufficientBalance);
+            relayer.ensure_not_paused(Subsystem::Bridge)?;
+            let chain = chains::enabled_chain(relayer, destination_chain)?.clone();
+            if amount.0 < chain.min_amount.0 {
+                return Err(RelayerError::AmountTooLow);
             }
-            let total_cost = 15_000_000_000_000; // 15 TGas for lock + sign
-            if fee > 0 && fee < total_cost / 1_000_000_000_000 * 1_000_000_000_000_000_000_000 {
-                return Err(RelayerError::FeeTooLow);
+            if amount.0 > chain.max_amount.0 {
+                return Err(RelayerError::AmountTooHigh);
             }
-            // Store pending transfer instead of incrementing nonce immediately
-            let nonce = relayer.get_pending_nonce(destination_chain);
-            let lock_promise = ext_omi_locker::ext(relayer.omni_locker_contract.get().clone().map(|x| x.clone()).unwrap_or_else(|| env::current_account_id()))
-                .with_static_gas(Gas::from_tgas(relayer.cross_contract_gas))
-                .lock(token.clone(), *amount, destination_chain.clone(), recipient.clone());
-            let mpc_contract = relayer.chain_mpc_mapping.get(destination_chain)
-                .ok_or(RelayerError::InvalidNonce)?;
-            let payload = BridgeTransferPayload {
-                token: token.clone(),
-                amount: amount.0,
-                destination_chain: destination_chain.clone(),
-                recipient: recipient.clone(),
-                sender: sender_id.clone(),
-                nonce,
+            bridge::make_room(relayer, sender_id)?;
+            // The fee was quoted and escrowed when the delegate was admitted; no quote means none was due
+            let (fee, fee_asset) = match quote_key.and_then(|key| relayer.bridge_fee_quotes.remove(key)) {
+                Some(quote) => (quote.fee.0, quote.asset),
+                None => (0, bridge::fee_asset(&chain).to_string()),
             };
-            let payload_bytes = borsh::to_vec(&payload).map_err(|_| RelayerError::InvalidAccountId)?;
-            let sign_promise = Promise::new(mpc_contract.clone())
-                .function_call(
-                    "sign".to_string(),
-                    borsh::to_vec(&SignRequest {
-                        payload: payload_bytes,
-                        path: "".to_string(),
-                        key_version: 0,
-                        request_id: request_id.unwrap_or(env::block_timestamp()),
-                    }).map_err(|_| RelayerError::InvalidAccountId)?,
-                    NearToken::from_yoctonear(0),
-                    Gas::from_tgas(relayer.cross_contract_gas)
-                );
-            // Store pending transfer
-            relayer.add_pending_transfer(
-                destination_chain.clone(),
-                nonce,
+            bridge::claim_escrow(relayer, sender_id, &fee_asset, fee)?;
+            let nonce = relayer.reserve_transfer_nonce(destination_chain);
+            let record = bridge::initiate(
+                relayer,
                 sender_id.clone(),
                 token.clone(),
                 *amount,
+                destination_chain.clone(),
                 recipient.clone(),
+                nonce,
                 fee,
+                fee_asset,
             );
             RelayerEvent::BridgeTransferInitiated {
+                id: record.id,
                 token: token.clone(),
                 amount: *amount,
                 destination_chain: destination_chain.clone(),
@@ -336,14 +655,19 @@ pub fn execute_action(
                 sender: sender_id.clone(),
                 nonce,
             }.emit();
-            RelayerEvent::FeeCharged { 
-                action: "BridgeTransfer".to_string(), 
-                fee, 
-                sender: sender_id.clone() 
-            }.emit();
-            promise = lock_promise.then(sign_promise);
+            // The lock callback records the Locked step before requesting the MPC signature,
+            // so it carries gas for the sign call and the final callback
+            let lock = ext_omi_locker::ext(relayer.omni_locker_contract.get().clone().unwrap_or_else(env::current_account_id))
+                .with_static_gas(Gas::from_gas(relayer.cross_contract_gas))
+                .lock(token.clone(), *amount, destination_chain.clone(), recipient.clone());
+            after(previous, lock)
+                .then(
+                    ext_self::ext(env::current_account_id())
+                        .with_static_gas(bridge::transfer_locked_gas(relayer))
+                        .handle_transfer_locked(record.id, request_id.unwrap_or(env::block_timestamp()))
+                )
         }
-    }
+    };
     // Check storage cost
     let storage_used = env::storage_usage() - initial_storage;
     let storage_cost = storage_used as u128 * env::storage_byte_cost().as_yoctonear();
@@ -0,0 +1,167 @@
+use near_sdk::{env, AccountId, NearToken, Promise, PublicKey};
+use near_sdk::json_types::U128;
+use crate::state::Relayer;
+use crate::types::{Action, PendingDelegate, SessionKey, SessionScope, SignedDelegateAction};
+use crate::errors::RelayerError;
+use crate::events::RelayerEvent;
+use crate::ledger;
+
+pub const MAX_SESSIONS_PER_ACCOUNT: usize = 10;
+pub const MAX_SESSION_DURATION_MS: u64 = 30 * 86_400_000;
+pub const MAX_SESSION_RECEIVERS: usize = 10;
+
+fn has_duplicates<T: PartialEq>(items: &[T]) -> bool {
+    items.iter().enumerate().any(|(i, item)| items[..i].contains(item))
+}
+
+fn refund_storage(account_id: &AccountId, amount: u128) {
+    if amount > 0 {
+        Promise::new(account_id.clone()).transfer(NearToken::from_yoctonear(amount));
+    }
+}
+
+// Registers an ephemeral key for the caller, who pays for the storage it takes. Re-registering
+// a key replaces its session, and the fresh session_nonce invalidates delegates signed for the
+// old one. Replaced and expired sessions hand their storage deposit on to the new session.
+pub fn register_session(relayer: &mut Relayer, public_key: PublicKey, expires_in_ms: u64, scope: SessionScope) -> Result<u64, RelayerError> {
+    let account_id = env::predecessor_account_id();
+    if expires_in_ms == 0 || expires_in_ms > MAX_SESSION_DURATION_MS {
+        return Err(RelayerError::InvalidSession);
+    }
+    if scope.action_types.is_empty() || scope.action_types.iter().any(|action_type| !Action::TYPE_NAMES.contains(&action_type.as_str())) {
+        return Err(RelayerError::InvalidSession);
+    }
+    if scope.receivers.len() > MAX_SESSION_RECEIVERS || has_duplicates(&scope.receivers) || has_duplicates(&scope.action_types) {
+        return Err(RelayerError::InvalidSession);
+    }
+    // Store collections write on flush, so measure what the session takes between flushes
+    relayer.sessions.flush();
+    let initial_storage = env::storage_usage();
+    let now = env::block_timestamp_ms();
+    let mut sessions = relayer.sessions.get(&account_id).cloned().unwrap_or_default();
+    let released: u128 = sessions.iter()
+        .filter(|session| session.expires_at_ms <= now || session.public_key == public_key)
+        .map(|session| session.storage_deposit.0)
+        .sum();
+    sessions.retain(|session| session.expires_at_ms > now && session.public_key != public_key);
+    if sessions.len() >= MAX_SESSIONS_PER_ACCOUNT {
+        return Err(RelayerError::InvalidSession);
+    }
+    let session_nonce = relayer.next_session_nonce;
+    relayer.next_session_nonce += 1;
+    let expires_at_ms = now.saturating_add(expires_in_ms);
+    sessions.push(SessionKey {
+        public_key: public_key.clone(),
+        session_nonce,
+        scope: scope.clone(),
+        expires_at_ms,
+        spent: U128(0),
+        storage_deposit: U128(0),
+    });
+    relayer.sessions.insert(account_id.clone(), sessions);
+    relayer.sessions.flush();
+    let storage_cost = env::storage_usage().saturating_sub(initial_storage) as u128 * env::storage_byte_cost().as_yoctonear();
+    let attached = env::attached_deposit().as_yoctonear();
+    if attached < storage_cost {
+        return Err(RelayerError::InsufficientDeposit);
+    }
+    if let Some(session) = relayer.sessions.get_mut(&account_id).and_then(|sessions| sessions.last_mut()) {
+        session.storage_deposit = U128(storage_cost + released);
+    }
+    refund_storage(&account_id, attached - storage_cost);
+    RelayerEvent::SessionRegistered { account_id, public_key, session_nonce, expires_at_ms, scope }.emit();
+    Ok(session_nonce)
+}
+
+pub fn revoke_session(relayer: &mut Relayer, public_key: PublicKey) -> Result<(), RelayerError> {
+    let account_id = env::predecessor_account_id();
+    let mut sessions = relayer.sessions.get(&account_id).cloned().unwrap_or_default();
+    let index = sessions.iter().position(|session| session.public_key == public_key).ok_or(RelayerError::SessionNotFound)?;
+    let session = sessions.remove(index);
+    if sessions.is_empty() {
+        relayer.sessions.remove(&account_id);
+    } else {
+        relayer.sessions.insert(account_id.clone(), sessions);
+    }
+    refund_storage(&account_id, session.storage_deposit.0);
+    RelayerEvent::SessionRevoked { account_id, public_key, session_nonce: session.session_nonce }.emit();
+    Ok(())
+}
+
+pub fn revoke_sessions(relayer: &mut Relayer) -> Result<(), RelayerError> {
+    let account_id = env::predecessor_account_id();
+    for session in relayer.sessions.remove(&account_id).unwrap_or_default() {
+        refund_storage(&account_id, session.storage_deposit.0);
+        RelayerEvent::SessionRevoked { account_id: account_id.clone(), public_key: session.public_key, session_nonce: session.session_nonce }.emit();
+    }
+    Ok(())
+}
+
+fn find<'a>(sessions: &'a [SessionKey], signed_delegate: &SignedDelegateAction) -> Result<&'a SessionKey, RelayerError> {
+    sessions.iter()
+        .find(|session| session.public_key == signed_delegate.public_key && session.session_nonce == signed_delegate.session_nonce)
+        .ok_or(RelayerError::SessionNotFound)
+}
+
+fn check_scope(session: &SessionKey, signed_delegate: &SignedDelegateAction) -> Result<(), RelayerError> {
+    if env::block_timestamp_ms() >= session.expires_at_ms {
+        return Err(RelayerError::SessionExpired);
+    }
+    let delegate = &signed_delegate.delegate_action;
+    let scope = &session.scope;
+    if !scope.receivers.is_empty() && !scope.receivers.contains(&delegate.receiver_id) {
+        return Err(RelayerError::SessionScopeExceeded);
+    }
+    if delegate.actions.iter().any(|action| !scope.action_types.iter().any(|action_type| action_type == action.type_name())) {
+        return Err(RelayerError::SessionScopeExceeded);
+    }
+    Ok(())
+}
+
+// Delegates with a session_nonce are signed by a session key rather than a key the auth
+// contract knows, so the session registry is what authorizes them.
+pub fn authorize(relayer: &Relayer, signed_delegate: &SignedDelegateAction) -> Result<(), RelayerError> {
+    let sessions = relayer.sessions.get(&signed_delegate.delegate_action.sender_id).ok_or(RelayerError::SessionNotFound)?;
+    check_scope(find(sessions, signed_delegate)?, signed_delegate)
+}
+
+// Charges a session delegate's estimated cost against the session's spend cap.
+pub fn consume(relayer: &mut Relayer, signed_delegate: &SignedDelegateAction) -> Result<(), RelayerError> {
+    if signed_delegate.session_nonce == 0 {
+        return Ok(());
+    }
+    let sender_id = &signed_delegate.delegate_action.sender_id;
+    let cost = ledger::estimate(relayer, signed_delegate).estimated_cost.0;
+    let mut sessions = relayer.sessions.get(sender_id).cloned().ok_or(RelayerError::SessionNotFound)?;
+    let session = sessions.iter_mut()
+        .find(|session| session.public_key == signed_delegate.public_key && session.session_nonce == signed_delegate.session_nonce)
+        .ok_or(RelayerError::SessionNotFound)?;
+    check_scope(session, signed_delegate)?;
+    let spent = session.spent.0.saturating_add(cost);
+    if spent > session.scope.spend_cap.0 {
+        return Err(RelayerError::SessionSpendCapReached);
+    }
+    session.spent = U128(spent);
+    relayer.sessions.insert(sender_id.clone(), sessions);
+    Ok(())
+}
+
+// Gives a rejected delegate's cost back to the session that spent it, if it still exists.
+pub fn release(relayer: &mut Relayer, pending: &PendingDelegate) {
+    if pending.session_nonce == 0 {
+        return;
+    }
+    let Some(mut sessions) = relayer.sessions.get(&pending.sender_id).cloned() else {
+        return;
+    };
+    let session = sessions.iter_mut()
+        .find(|session| session.public_key == pending.public_key && session.session_nonce == pending.session_nonce);
+    if let Some(session) = session {
+        session.spent = U128(session.spent.0.saturating_sub(pending.spend.estimated_cost.0));
+        relayer.sessions.insert(pending.sender_id.clone(), sessions);
+    }
+}
+
+pub fn list(relayer: &Relayer, account_id: &AccountId) -> Vec<SessionKey> {
+    relayer.sessions.get(account_id).cloned().unwrap_or_default()
+}
@@ -2,7 +2,7 @@ use near_sdk::{env, Promise, AccountId, PublicKey, NearToken, Gas};
 use crate::{state::Relayer, ext_auth};
 use crate::errors::RelayerError;
 use crate::events::RelayerEvent;
-use crate::types::SignedDelegateAction;
+use crate::types::{SignedDelegateAction, Subsystem};
 use crate::relay;
 use near_sdk::borsh::to_vec;
 
@@ -13,6 +13,7 @@ pub fn sponsor_account_with_registrar(
     is_multi_sig: bool,
     multi_sig_threshold: Option<u32>,
 ) -> Result<Promise, RelayerError> {
+    relayer.ensure_not_paused(Subsystem::Sponsor)?;
     let balance = env::account_balance();
     if balance.as_yoctonear() < relayer.min_balance {
         RelayerEvent::LowBalance { balance: balance.as_yoctonear() }.emit();
@@ -31,7 +32,7 @@ pub fn sponsor_account_with_registrar(
         .ok_or(RelayerError::InvalidAccountId)?;
     if is_mainnet {
         let len = account_name.len();
-        if len < 3 || len > 16 {
+        if !(3..=16).contains(&len) {
             return Err(RelayerError::InvalidAccountId);
         }
     } else if !account_id_str.ends_with(".testnet") {
@@ -53,7 +54,7 @@ pub fn sponsor_account_with_registrar(
             "create_account".to_string(),
             args,
             NearToken::from_yoctonear(creation_deposit),
-            Gas::from_tgas(relayer.cross_contract_gas),
+            Gas::from_gas(relayer.cross_contract_gas),
         )
         .then(
             Promise::new(new_account_id.clone())
@@ -62,7 +63,7 @@ pub fn sponsor_account_with_registrar(
         )
         .then(
             ext_auth::ext(relayer.auth_contract.clone())
-                .with_static_gas(Gas::from_tgas(relayer.cross_contract_gas))
+                .with_static_gas(Gas::from_gas(relayer.cross_contract_gas))
                 .register_key(new_account_id.clone(), public_key, Some(30), is_multi_sig, multi_sig_threshold)
         );
     RelayerEvent::AccountSponsored { account_id: new_account_id.clone() }.emit();
@@ -73,5 +74,6 @@ pub fn sponsor_account_signed(
     relayer: &mut Relayer,
     signed_delegate: SignedDelegateAction,
 ) -> Result<Promise, RelayerError> {
+    relayer.ensure_not_paused(Subsystem::Sponsor)?;
     relay::relay_meta_transaction(relayer, signed_delegate)
 }
\ No newline at end of file
@@ -1,21 +1,15 @@
 use near_sdk::{AccountId, env};
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::store::{LazyOption, LookupMap};
+use near_sdk::store::{IterableMap, LazyOption, LookupMap, LookupSet};
 use near_sdk_macros::NearSchema;
-use crate::state_versions::{StateV010, StateV011};
+use crate::state_versions::{StateV010, StateV011, StateV011Transfers};
+use crate::errors::RelayerError;
+use crate::types::{BridgeFeeQuote, BridgeFeeSchedule, BridgeTransferRecord, ChainConfig, GovernanceProposal, HeldFee, ManagerProposal, MultisigConfig, PendingDelegate, QuotaTier, QuotaUsage, ReceiverPolicy, Role, SessionKey, SpendRecord, StagedUpgrade, Subsystem, WebAuthnConfig};
+use crate::{chains, quota};
 use crate::events::RelayerEvent;
-use near_sdk::json_types::U128;
+use near_sdk::PublicKey;
 
-#[derive(BorshDeserialize, BorshSerialize, NearSchema)]
-#[abi(borsh)]
-pub struct PendingTransfer {
-    pub nonce: u64,
-    pub sender_id: AccountId,
-    pub token: String,
-    pub amount: U128,
-    pub recipient: String,
-    pub fee: u128,
-}
+const CURRENT_VERSION: &str = "0.1.2";
 
 #[derive(BorshDeserialize, BorshSerialize, NearSchema)]
 #[abi(borsh)]
@@ -26,7 +20,7 @@ pub struct Relayer {
     pub auth_contract: AccountId,
     pub ft_wrapper_contract: AccountId,
     pub omni_locker_contract: LazyOption<AccountId>,
-    pub chain_mpc_mapping: LookupMap<String, AccountId>,
+    pub chains: IterableMap<String, ChainConfig>,
     pub sponsor_amount: u128,
     pub sponsor_gas: u64,
     pub cross_contract_gas: u64,
@@ -36,7 +30,46 @@ pub struct Relayer {
     pub max_balance: u128,
     pub base_fee: u128,
     pub transfer_nonces: LookupMap<String, u64>,
-    pub pending_transfers: LookupMap<String, PendingTransfer>,
+    pub bridge_transfers: LookupMap<u64, BridgeTransferRecord>,
+    pub next_transfer_id: u64,
+    pub sender_transfers: LookupMap<AccountId, Vec<u64>>,
+    pub pending_chain_transfers: LookupMap<String, Vec<u64>>,
+    pub bridge_timeout_ms: u64,
+    pub bridge_escrow: LookupMap<String, u128>,
+    pub bridge_fee_schedules: LookupMap<String, BridgeFeeSchedule>,
+    pub bridge_fee_quotes: LookupMap<String, BridgeFeeQuote>,
+    pub locked_escrow: LookupMap<String, u128>,
+    pub delegate_nonces: LookupMap<String, u64>,
+    pub paused: Vec<Subsystem>,
+    pub max_actions: usize,
+    pub action_fees: LookupMap<String, u128>,
+    pub fee_tokens: LookupSet<String>,
+    pub fee_credits: LookupMap<String, u128>,
+    pub held_fees: LookupMap<String, HeldFee>,
+    pub pending_delegates: LookupMap<String, PendingDelegate>,
+    pub multisig_configs: LookupMap<AccountId, MultisigConfig>,
+    pub admin_nonce: u64,
+    pub roles: LookupMap<Role, Vec<AccountId>>,
+    pub pending_manager: Option<ManagerProposal>,
+    pub proposals: IterableMap<u64, GovernanceProposal>,
+    pub next_proposal_id: u64,
+    pub governance_delay_ms: u64,
+    pub staged_upgrade: Option<StagedUpgrade>,
+    pub staged_code: LazyOption<Vec<u8>>,
+    pub code_hash: Option<String>,
+    pub upgrade_approver_required: bool,
+    pub quota_tiers: LookupMap<String, QuotaTier>,
+    pub account_tiers: LookupMap<AccountId, String>,
+    pub quota_usage: LookupMap<AccountId, QuotaUsage>,
+    pub sender_spend: IterableMap<AccountId, SpendRecord>,
+    pub app_spend: IterableMap<AccountId, SpendRecord>,
+    pub sender_budgets: LookupMap<AccountId, u128>,
+    pub app_budgets: LookupMap<AccountId, u128>,
+    pub call_policies: LookupMap<AccountId, ReceiverPolicy>,
+    pub sessions: LookupMap<AccountId, Vec<SessionKey>>,
+    pub next_session_nonce: u64,
+    pub nonce_oracle: Option<AccountId>,
+    pub webauthn: Option<WebAuthnConfig>,
 }
 
 impl Relayer {
@@ -46,77 +79,150 @@ impl Relayer {
         auth_contract: AccountId,
         ft_wrapper_contract: AccountId,
     ) -> Self {
+        let mut roles = LookupMap::new(b"roles".to_vec());
+        roles.insert(Role::Owner, vec![manager.clone()]);
+        let mut quota_tiers = LookupMap::new(b"quota_tiers".to_vec());
+        quota_tiers.insert(quota::DEFAULT_TIER.to_string(), quota::default_tier());
         Self {
-            version: "0.1.1".to_string(),
+            version: CURRENT_VERSION.to_string(),
             manager,
             offload_recipient,
             auth_contract,
             ft_wrapper_contract,
             omni_locker_contract: LazyOption::new(b"omni_locker".to_vec(), Some(env::current_account_id())),
-            chain_mpc_mapping: LookupMap::new(b"chain_mpc".to_vec()),
+            chains: IterableMap::new(b"chains".to_vec()),
             sponsor_amount: 10_000_000_000_000_000_000_000,
             sponsor_gas: 100_000_000_000_000,
-            cross_contract_gas: 100_000_000_000_000, // Default: 100 TGas for cross-contract calls
+            cross_contract_gas: 30_000_000_000_000, // Default: 30 TGas, so a relay with its callbacks fits in 300 TGas
             migration_gas: 200_000_000_000_000, // Default: 200 TGas for migrations
             chunk_size: 5, // Default: 5 for chunked transactions
             min_balance: 10_000_000_000_000_000_000_000_000,
             max_balance: 1_000_000_000_000_000_000_000_000_000,
             base_fee: 100_000_000_000_000_000_000,
             transfer_nonces: LookupMap::new(b"nonces".to_vec()),
-            pending_transfers: LookupMap::new(b"pending_transfers".to_vec()),
+            bridge_transfers: LookupMap::new(b"bridge_transfers".to_vec()),
+            next_transfer_id: 0,
+            sender_transfers: LookupMap::new(b"sender_transfers".to_vec()),
+            pending_chain_transfers: LookupMap::new(b"pending_chain_transfers".to_vec()),
+            bridge_timeout_ms: 3_600_000, // Default: 1 hour before a stuck transfer can be reclaimed
+            bridge_escrow: LookupMap::new(b"bridge_escrow".to_vec()),
+            bridge_fee_schedules: LookupMap::new(b"bridge_fee_schedules".to_vec()),
+            bridge_fee_quotes: LookupMap::new(b"bridge_fee_quotes".to_vec()),
+            locked_escrow: LookupMap::new(b"locked_escrow".to_vec()),
+            delegate_nonces: LookupMap::new(b"delegate_nonces".to_vec()),
+            paused: Vec::new(),
+            max_actions: 4, // Default: 4 actions per delegate
+            action_fees: LookupMap::new(b"action_fees".to_vec()),
+            fee_tokens: LookupSet::new(b"fee_tokens".to_vec()),
+            fee_credits: LookupMap::new(b"fee_credits".to_vec()),
+            held_fees: LookupMap::new(b"held_fees".to_vec()),
+            pending_delegates: LookupMap::new(b"pending_delegates".to_vec()),
+            multisig_configs: LookupMap::new(b"multisig".to_vec()),
+            admin_nonce: 0,
+            roles,
+            pending_manager: None,
+            proposals: IterableMap::new(b"proposals".to_vec()),
+            next_proposal_id: 0,
+            governance_delay_ms: 86_400_000, // Default: 24 hours
+            staged_upgrade: None,
+            staged_code: LazyOption::new(b"staged_code".to_vec(), None),
+            code_hash: None,
+            upgrade_approver_required: true,
+            quota_tiers,
+            account_tiers: LookupMap::new(b"account_tiers".to_vec()),
+            quota_usage: LookupMap::new(b"quota_usage".to_vec()),
+            sender_spend: IterableMap::new(b"sender_spend".to_vec()),
+            app_spend: IterableMap::new(b"app_spend".to_vec()),
+            sender_budgets: LookupMap::new(b"sender_budgets".to_vec()),
+            app_budgets: LookupMap::new(b"app_budgets".to_vec()),
+            call_policies: LookupMap::new(b"call_policies".to_vec()),
+            sessions: LookupMap::new(b"sessions".to_vec()),
+            next_session_nonce: 1,
+            nonce_oracle: None,
+            webauthn: None,
         }
     }
 
-    pub fn is_manager(&self, account_id: &AccountId) -> bool {
-        &self.manager == account_id
+    pub fn role_holders(&self, role: Role) -> Vec<AccountId> {
+        self.roles.get(&role).cloned().unwrap_or_default()
+    }
+
+    pub fn has_role(&self, role: Role, account_id: &AccountId) -> bool {
+        self.role_holders(Role::Owner).contains(account_id) || self.role_holders(role).contains(account_id)
+    }
+
+    pub fn is_paused(&self, subsystem: Subsystem) -> bool {
+        self.paused.contains(&subsystem)
+    }
+
+    pub fn ensure_not_paused(&self, subsystem: Subsystem) -> Result<(), RelayerError> {
+        if self.is_paused(subsystem) {
+            return Err(RelayerError::Paused);
+        }
+        Ok(())
     }
 
     pub fn get_pending_nonce(&self, chain: &str) -> u64 {
         self.transfer_nonces.get(chain).copied().unwrap_or(0)
     }
 
-    pub fn add_pending_transfer(
+    pub fn get_delegate_nonce(&self, sender_id: &AccountId, public_key: &PublicKey) -> Option<u64> {
+        self.delegate_nonces.get(&delegate_nonce_key(sender_id, public_key)).copied()
+    }
+
+    // Records the nonce of a relayed delegate so the same SignedDelegateAction cannot be
+    // submitted twice. Nonces are tracked per (sender, public key) and must strictly increase.
+    // Returns the nonce it replaced.
+    pub fn consume_delegate_nonce(
         &mut self,
-        chain: String,
+        sender_id: &AccountId,
+        public_key: &PublicKey,
         nonce: u64,
-        sender_id: AccountId,
-        token: String,
-        amount: U128,
-        recipient: String,
-        fee: u128,
-    ) {
-        let key = format!("{}-{}", chain, nonce);
-        self.pending_transfers.insert(key, PendingTransfer {
-            nonce,
-            sender_id,
-            token,
-            amount,
-            recipient,
-            fee,
-        });
+        max_block_height: u64,
+    ) -> Result<Option<u64>, RelayerError> {
+        if env::block_height() > max_block_height {
+            return Err(RelayerError::DelegateExpired);
+        }
+        let key = delegate_nonce_key(sender_id, public_key);
+        let last_nonce = self.delegate_nonces.get(&key).copied();
+        if let Some(last_nonce) = last_nonce {
+            if nonce == last_nonce {
+                return Err(RelayerError::NonceAlreadyUsed);
+            }
+            if nonce < last_nonce {
+                return Err(RelayerError::StaleNonce);
+            }
+        }
+        self.delegate_nonces.insert(key, nonce);
+        Ok(last_nonce)
     }
 
-    pub fn confirm_pending_transfer(&mut self, chain: &str, nonce: u64) {
-        let key = format!("{}-{}", chain, nonce);
-        self.pending_transfers.remove(&key);
-        let current_nonce = self.transfer_nonces.get(chain).copied().unwrap_or(0);
-        if nonce >= current_nonce {
-            self.transfer_nonces.insert(chain.to_string(), nonce + 1);
+    // Puts back the nonce a rejected delegate replaced, unless a later delegate moved past it.
+    pub fn release_delegate_nonce(&mut self, sender_id: &AccountId, public_key: &PublicKey, nonce: u64, previous_nonce: Option<u64>) {
+        let key = delegate_nonce_key(sender_id, public_key);
+        if self.delegate_nonces.get(&key) != Some(&nonce) {
+            return;
         }
+        match previous_nonce {
+            Some(previous_nonce) => self.delegate_nonces.insert(key, previous_nonce),
+            None => self.delegate_nonces.remove(&key),
+        };
     }
 
-    pub fn revert_pending_transfer(&mut self, chain: &str, nonce: u64) -> Option<PendingTransfer> {
-        let key = format!("{}-{}", chain, nonce);
-        self.pending_transfers.remove(&key)
+    // Nonces are taken when a transfer starts, so concurrent transfers to a chain never share one.
+    pub fn reserve_transfer_nonce(&mut self, chain: &str) -> u64 {
+        let nonce = self.get_pending_nonce(chain);
+        self.transfer_nonces.insert(chain.to_string(), nonce + 1);
+        nonce
     }
 
-    pub fn migrate() -> Self {
-        const CURRENT_VERSION: &str = "0.1.1";
+    // `chains` names the entries of the old chain -> signer mapping to carry over, since a
+    // LookupMap can't be enumerated.
+    pub fn migrate(chains: &[String]) -> Self {
+        // Read the raw borsh bytes under STATE, default to empty if none
+        let state_bytes: Vec<u8> = env::storage_read(b"STATE").unwrap_or_default();
 
-        // Read raw state bytes, default to empty if none
-        let state_bytes: Vec<u8> = env::state_read().unwrap_or_default();
-
-        // Try current version (0.1.1)
+        // Try current version (0.1.2)
         if let Ok(state) = borsh::from_slice::<Relayer>(&state_bytes) {
             if state.version == CURRENT_VERSION {
                 env::log_str("State is already at latest version");
@@ -124,34 +230,19 @@ impl Relayer {
             }
         }
 
+        // Try version 0.1.1 with bridge transfer tracking
+        if let Ok(old_state) = borsh::from_slice::<StateV011Transfers>(&state_bytes) {
+            if old_state.version == "0.1.1" {
+                env::log_str("Migrating from state version 0.1.1");
+                return Self::upgrade_from(old_state, "0.1.1", chains);
+            }
+        }
+
         // Try version 0.1.1
         if let Ok(old_state) = borsh::from_slice::<StateV011>(&state_bytes) {
             if old_state.version == "0.1.1" {
                 env::log_str("Migrating from state version 0.1.1");
-                let new_state = Relayer {
-                    version: CURRENT_VERSION.to_string(),
-                    manager: old_state.manager,
-                    offload_recipient: old_state.offload_recipient,
-                    auth_contract: old_state.auth_contract,
-                    ft_wrapper_contract: old_state.ft_wrapper_contract,
-                    omni_locker_contract: old_state.omni_locker_contract,
-                    chain_mpc_mapping: old_state.chain_mpc_mapping,
-                    sponsor_amount: old_state.sponsor_amount,
-                    sponsor_gas: old_state.sponsor_gas,
-                    cross_contract_gas: old_state.cross_contract_gas,
-                    migration_gas: old_state.migration_gas,
-                    chunk_size: old_state.chunk_size,
-                    min_balance: old_state.min_balance,
-                    max_balance: old_state.max_balance,
-                    base_fee: old_state.base_fee,
-                    transfer_nonces: LookupMap::new(b"nonces".to_vec()),
-                    pending_transfers: LookupMap::new(b"pending_transfers".to_vec()),
-                };
-                RelayerEvent::StateMigrated {
-                    old_version: "0.1.1".to_string(),
-                    new_version: CURRENT_VERSION.to_string(),
-                }.emit();
-                return new_state;
+                return Self::upgrade_from(old_state.into(), "0.1.1", chains);
             }
         }
 
@@ -159,30 +250,7 @@ impl Relayer {
         if let Ok(old_state) = borsh::from_slice::<StateV010>(&state_bytes) {
             if old_state.version == "0.1.0" {
                 env::log_str("Migrating from state version 0.1.0");
-                let new_state = Relayer {
-                    version: CURRENT_VERSION.to_string(),
-                    manager: old_state.manager,
-                    offload_recipient: old_state.offload_recipient,
-                    auth_contract: old_state.auth_contract,
-                    ft_wrapper_contract: old_state.ft_wrapper_contract,
-                    omni_locker_contract: old_state.omni_locker_contract,
-                    chain_mpc_mapping: old_state.chain_mpc_mapping,
-                    sponsor_amount: old_state.sponsor_amount,
-                    sponsor_gas: old_state.sponsor_gas,
-                    cross_contract_gas: old_state.cross_contract_gas,
-                    migration_gas: old_state.migration_gas,
-                    chunk_size: old_state.chunk_size,
-                    min_balance: 10_000_000_000_000_000_000_000_000,
-                    max_balance: 1_000_000_000_000_000_000_000_000_000,
-                    base_fee: 100_000_000_000_000_000_000,
-                    transfer_nonces: LookupMap::new(b"nonces".to_vec()),
-                    pending_transfers: LookupMap::new(b"pending_transfers".to_vec()),
-                };
-                RelayerEvent::StateMigrated {
-                    old_version: "0.1.0".to_string(),
-                    new_version: CURRENT_VERSION.to_string(),
-                }.emit();
-                return new_state;
+                return Self::upgrade_from(StateV011::from(old_state).into(), "0.1.0", chains);
             }
         }
 
@@ -194,4 +262,91 @@ impl Relayer {
             "ft.testnet".parse::<AccountId>().unwrap(),
         )
     }
+
+    // Older layouts are first converted to the last released layout, so only this step
+    // needs to know about fields introduced since then.
+    fn upgrade_from(old_state: StateV011Transfers, old_version: &str, chains: &[String]) -> Self {
+        let mut registry = IterableMap::new(b"chains".to_vec());
+        for chain in chains {
+            match old_state.chain_mpc_mapping.get(chain) {
+                Some(mpc_contract) => {
+                    registry.insert(chain.clone(), chains::legacy_config(chain, mpc_contract.clone()));
+                }
+                None => env::panic_str(&format!("Chain {} is not in the old mapping", chain)),
+            }
+        }
+        let mut roles = LookupMap::new(b"roles".to_vec());
+        roles.insert(Role::Owner, vec![old_state.manager.clone()]);
+        let mut quota_tiers = LookupMap::new(b"quota_tiers".to_vec());
+        quota_tiers.insert(quota::DEFAULT_TIER.to_string(), quota::default_tier());
+        let new_state = Relayer {
+            version: CURRENT_VERSION.to_string(),
+            manager: old_state.manager,
+            offload_recipient: old_state.offload_recipient,
+            auth_contract: old_state.auth_contract,
+            ft_wrapper_contract: old_state.ft_wrapper_contract,
+            omni_locker_contract: old_state.omni_locker_contract,
+            chains: registry,
+            sponsor_amount: old_state.sponsor_amount,
+            sponsor_gas: old_state.sponsor_gas,
+            cross_contract_gas: old_state.cross_contract_gas,
+            migration_gas: old_state.migration_gas,
+            chunk_size: old_state.chunk_size,
+            min_balance: old_state.min_balance,
+            max_balance: old_state.max_balance,
+            base_fee: old_state.base_fee,
+            transfer_nonces: old_state.transfer_nonces,
+            // Old pending_transfers can't be enumerated, so in-flight transfers from before the
+            // upgrade aren't tracked; their callbacks find no record and settle nothing
+            bridge_transfers: LookupMap::new(b"bridge_transfers".to_vec()),
+            next_transfer_id: 0,
+            sender_transfers: LookupMap::new(b"sender_transfers".to_vec()),
+            pending_chain_transfers: LookupMap::new(b"pending_chain_transfers".to_vec()),
+            bridge_timeout_ms: 3_600_000,
+            bridge_escrow: LookupMap::new(b"bridge_escrow".to_vec()),
+            bridge_fee_schedules: LookupMap::new(b"bridge_fee_schedules".to_vec()),
+            bridge_fee_quotes: LookupMap::new(b"bridge_fee_quotes".to_vec()),
+            locked_escrow: LookupMap::new(b"locked_escrow".to_vec()),
+            delegate_nonces: LookupMap::new(b"delegate_nonces".to_vec()),
+            paused: Vec::new(),
+            max_actions: 4,
+            action_fees: LookupMap::new(b"action_fees".to_vec()),
+            fee_tokens: LookupSet::new(b"fee_tokens".to_vec()),
+            fee_credits: LookupMap::new(b"fee_credits".to_vec()),
+            held_fees: LookupMap::new(b"held_fees".to_vec()),
+            pending_delegates: LookupMap::new(b"pending_delegates".to_vec()),
+            multisig_configs: LookupMap::new(b"multisig".to_vec()),
+            admin_nonce: 0,
+            roles,
+            pending_manager: None,
+            proposals: IterableMap::new(b"proposals".to_vec()),
+            next_proposal_id: 0,
+            governance_delay_ms: 86_400_000,
+            staged_upgrade: None,
+            staged_code: LazyOption::new(b"staged_code".to_vec(), None),
+            code_hash: None,
+            upgrade_approver_required: true,
+            quota_tiers,
+            account_tiers: LookupMap::new(b"account_tiers".to_vec()),
+            quota_usage: LookupMap::new(b"quota_usage".to_vec()),
+            sender_spend: IterableMap::new(b"sender_spend".to_vec()),
+            app_spend: IterableMap::new(b"app_spend".to_vec()),
+            sender_budgets: LookupMap::new(b"sender_budgets".to_vec()),
+            app_budgets: LookupMap::new(b"app_budgets".to_vec()),
+            call_policies: LookupMap::new(b"call_policies".to_vec()),
+            sessions: LookupMap::new(b"sessions".to_vec()),
+            next_session_nonce: 1,
+            nonce_oracle: None,
+            webauthn: None,
+        };
+        RelayerEvent::StateMigrated {
+            old_version: old_version.to_string(),
+            new_version: CURRENT_VERSION.to_string(),
+        }.emit();
+        new_state
+    }
+}
+
+fn delegate_nonce_key(sender_id: &AccountId, public_key: &PublicKey) -> String {
+    format!("{}:{}", sender_id, String::from(public_key))
 }
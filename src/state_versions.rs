@@ -2,6 +2,19 @@ use near_sdk::borsh::{BorshSerialize, BorshDeserialize};
 use near_sdk::store::{LazyOption, LookupMap};
 use near_sdk::AccountId;
 use near_sdk_macros::NearSchema;
+use near_sdk::json_types::U128;
+
+// Bridge transfer record used by the 0.1.1 layout
+#[derive(BorshSerialize, BorshDeserialize, NearSchema)]
+#[abi(borsh)]
+pub struct PendingTransfer {
+    pub nonce: u64,
+    pub sender_id: AccountId,
+    pub token: String,
+    pub amount: U128,
+    pub recipient: String,
+    pub fee: u128,
+}
 
 // State for version 0.1.0
 #[derive(BorshSerialize, BorshDeserialize, NearSchema)]
@@ -40,4 +53,72 @@ pub struct StateV011 {
     pub min_balance: u128,
     pub max_balance: u128,
     pub base_fee: u128,
-}
\ No newline at end of file
+}
+// State for version 0.1.1 after bridge transfer tracking was added
+#[derive(BorshSerialize, BorshDeserialize, NearSchema)]
+#[abi(borsh)]
+pub struct StateV011Transfers {
+    pub version: String,
+    pub manager: AccountId,
+    pub offload_recipient: AccountId,
+    pub auth_contract: AccountId,
+    pub ft_wrapper_contract: AccountId,
+    pub omni_locker_contract: LazyOption<AccountId>,
+    pub chain_mpc_mapping: LookupMap<String, AccountId>,
+    pub sponsor_amount: u128,
+    pub sponsor_gas: u64,
+    pub cross_contract_gas: u64,
+    pub migration_gas: u64,
+    pub chunk_size: usize,
+    pub min_balance: u128,
+    pub max_balance: u128,
+    pub base_fee: u128,
+    pub transfer_nonces: LookupMap<String, u64>,
+    pub pending_transfers: LookupMap<String, PendingTransfer>,
+}
+
+impl From<StateV010> for StateV011 {
+    fn from(old_state: StateV010) -> Self {
+        Self {
+            version: old_state.version,
+            manager: old_state.manager,
+            offload_recipient: old_state.offload_recipient,
+            auth_contract: old_state.auth_contract,
+            ft_wrapper_contract: old_state.ft_wrapper_contract,
+            omni_locker_contract: old_state.omni_locker_contract,
+            chain_mpc_mapping: old_state.chain_mpc_mapping,
+            sponsor_amount: old_state.sponsor_amount,
+            sponsor_gas: old_state.sponsor_gas,
+            cross_contract_gas: old_state.cross_contract_gas,
+            migration_gas: old_state.migration_gas,
+            chunk_size: old_state.chunk_size,
+            min_balance: 10_000_000_000_000_000_000_000_000,
+            max_balance: 1_000_000_000_000_000_000_000_000_000,
+            base_fee: 100_000_000_000_000_000_000,
+        }
+    }
+}
+
+impl From<StateV011> for StateV011Transfers {
+    fn from(old_state: StateV011) -> Self {
+        Self {
+            version: old_state.version,
+            manager: old_state.manager,
+            offload_recipient: old_state.offload_recipient,
+            auth_contract: old_state.auth_contract,
+            ft_wrapper_contract: old_state.ft_wrapper_contract,
+            omni_locker_contract: old_state.omni_locker_contract,
+            chain_mpc_mapping: old_state.chain_mpc_mapping,
+            sponsor_amount: old_state.sponsor_amount,
+            sponsor_gas: old_state.sponsor_gas,
+            cross_contract_gas: old_state.cross_contract_gas,
+            migration_gas: old_state.migration_gas,
+            chunk_size: old_state.chunk_size,
+            min_balance: old_state.min_balance,
+            max_balance: old_state.max_balance,
+            base_fee: old_state.base_fee,
+            transfer_nonces: LookupMap::new(b"nonces".to_vec()),
+            pending_transfers: LookupMap::new(b"pending_transfers".to_vec()),
+        }
+    }
+}
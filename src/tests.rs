@@ -3,12 +3,17 @@ mod tests {
     use crate::{OnSocialRelayer, state_versions::{StateV010, StateV011}};
     use near_sdk::{
         env,
-        test_utils::{VMContextBuilder, get_logs},
+        test_utils::{VMContextBuilder, get_created_receipts, get_logs},
         testing_env, AccountId, NearToken, CurveType, PublicKey,
     };
     use near_sdk::store::{LazyOption, LookupMap};
     use near_sdk::borsh;
-    use crate::types::{SignedDelegateAction, DelegateAction, Action, SignatureScheme};
+    use near_sdk::json_types::U128;
+    use near_sdk::{PromiseOrValue, PromiseResult};
+    use ed25519_dalek::{Signer, SigningKey};
+    use crate::types::{AccessKey, AccessKeyPermission, AddressFormat, BridgeFeeQuote, BridgeFeeSchedule, ChainConfig, GovernanceAction, SignedDelegateAction, DelegateAction, Action, MethodPolicy, MultisigConfig, NativeAction, NativeDelegateAction, NativePublicKey, NativeSignature, NativeSignedDelegateAction, QuotaTier, ReceiverPolicy, Role, SessionScope, SignatureScheme, Subsystem, SurgePricing, TransferResolution, TransferStatus, WebAuthnAssertion, WebAuthnConfig};
+    use crate::{bridge, chains, fees, multisig, nep366, policy, relay, session};
+    use crate::errors::RelayerError;
 
     fn setup_context(predecessor: AccountId) -> VMContextBuilder {
         let mut context = VMContextBuilder::new();
@@ -20,6 +25,61 @@ mod tests {
         context
     }
 
+    // Signed with the test key, so the delegate passes the signature check at admission.
+    fn signed_delegate(sender: &str, nonce: u64, max_block_height: u64, actions: Vec<Action>) -> SignedDelegateAction {
+        sign_delegate(SignedDelegateAction {
+            delegate_action: DelegateAction {
+                sender_id: sender.parse().unwrap(),
+                receiver_id: "receiver.testnet".parse().unwrap(),
+                actions,
+                nonce,
+                max_block_height,
+            },
+            signature: vec![0u8; 64],
+            public_key: PublicKey::from_parts(CurveType::ED25519, vec![0u8; 32]).unwrap(),
+            session_nonce: 0,
+            scheme: SignatureScheme::Ed25519,
+            fee_action: None,
+            multi_signatures: None,
+        })
+    }
+
+    fn sign_delegate(mut signed: SignedDelegateAction) -> SignedDelegateAction {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let payload = relay::signing_payload(&signed.delegate_action, signed.session_nonce).unwrap();
+        signed.signature = signing_key.sign(&payload).to_bytes().to_vec();
+        signed.public_key = PublicKey::from_parts(CurveType::ED25519, signing_key.verifying_key().to_bytes().to_vec()).unwrap();
+        signed
+    }
+
+    fn chain_config() -> ChainConfig {
+        ChainConfig {
+            mpc_contract: "mpc.testnet".parse().unwrap(),
+            derivation_path: "{sender_id}/ethereum/{path}".to_string(),
+            key_version: 1,
+            address_format: AddressFormat::Evm,
+            min_amount: U128(10),
+            max_amount: U128(1_000),
+            fee: U128(0),
+            fee_token: None,
+            enabled: true,
+        }
+    }
+
+    fn setup_contract() -> OnSocialRelayer {
+        let mut contract = OnSocialRelayer::new(
+            "recipient.testnet".parse().unwrap(),
+            "auth.testnet".parse().unwrap(),
+            "ft.testnet".parse().unwrap(),
+        );
+        // Keep the mpc -> auth -> callback chain within the 300 TGas a test call gets
+        contract.relayer.cross_contract_gas = 15_000_000_000_000;
+        // Test delegates target receiver.testnet and may send it small transfers
+        let policy = ReceiverPolicy { methods: vec![], max_transfer: U128(1_000) };
+        contract.relayer.call_policies.insert("receiver.testnet".parse().unwrap(), policy);
+        contract
+    }
+
     #[test]
     fn test_migration_from_010_to_011() {
         let manager: AccountId = "manager.testnet".parse().unwrap();
@@ -42,11 +102,11 @@ mod tests {
         };
         // Serialize state to Borsh
         let state_bytes = borsh::to_vec(&state_v010).expect("Failed to serialize state");
-        env::state_write(&state_bytes);
+        env::storage_write(b"STATE", &state_bytes);
 
-        let new_contract = OnSocialRelayer::migrate();
+        let new_contract = OnSocialRelayer::migrate(None);
 
-        assert_eq!(new_contract.relayer.version, "0.1.1", "Version should be 0.1.1");
+        assert_eq!(new_contract.relayer.version, "0.1.2", "Version should be 0.1.2");
         assert_eq!(new_contract.relayer.manager, manager, "Manager should be preserved");
         assert_eq!(
             new_contract.relayer.offload_recipient,
@@ -85,7 +145,7 @@ mod tests {
             "Expected migration log, got: {:?}", logs
         );
         assert!(
-            logs.contains(&"EVENT_JSON:{\"standard\":\"nep297\",\"version\":\"1.0.0\",\"event\":\"state_migrated\",\"data\":{\"old_version\":\"0.1.0\",\"new_version\":\"0.1.1\"}}".to_string()),
+            logs.contains(&"EVENT_JSON:{\"standard\":\"nep297\",\"version\":\"1.0.0\",\"event\":\"state_migrated\",\"data\":{\"old_version\":\"0.1.0\",\"new_version\":\"0.1.2\"}}".to_string()),
             "Expected state_migrated event, got: {:?}", logs
         );
     }
@@ -115,11 +175,11 @@ mod tests {
         };
         // Serialize state to Borsh
         let state_bytes = borsh::to_vec(&state_v011).expect("Failed to serialize state");
-        env::state_write(&state_bytes);
+        env::storage_write(b"STATE", &state_bytes);
 
-        let new_contract = OnSocialRelayer::migrate();
+        let new_contract = OnSocialRelayer::migrate(None);
 
-        assert_eq!(new_contract.relayer.version, "0.1.1", "Version should be 0.1.1");
+        assert_eq!(new_contract.relayer.version, "0.1.2", "Version should be 0.1.2");
         assert_eq!(new_contract.relayer.manager, manager, "Manager should be preserved");
         assert_eq!(
             new_contract.relayer.offload_recipient,
@@ -158,20 +218,77 @@ mod tests {
             "Expected migration log, got: {:?}", logs
         );
         assert!(
-            logs.contains(&"EVENT_JSON:{\"standard\":\"nep297\",\"version\":\"1.0.0\",\"event\":\"state_migrated\",\"data\":{\"old_version\":\"0.1.1\",\"new_version\":\"0.1.1\"}}".to_string()),
+            logs.contains(&"EVENT_JSON:{\"standard\":\"nep297\",\"version\":\"1.0.0\",\"event\":\"state_migrated\",\"data\":{\"old_version\":\"0.1.1\",\"new_version\":\"0.1.2\"}}".to_string()),
             "Expected state_migrated event, got: {:?}", logs
         );
     }
 
+    #[test]
+    fn test_migration_keeps_listed_chains() {
+        let manager: AccountId = "manager.testnet".parse().unwrap();
+        testing_env!(setup_context(manager.clone()).build());
+
+        let mut state_v011 = StateV011 {
+            version: "0.1.1".to_string(),
+            manager: manager.clone(),
+            offload_recipient: "recipient.testnet".parse().unwrap(),
+            auth_contract: "auth.testnet".parse().unwrap(),
+            ft_wrapper_contract: "ft.testnet".parse().unwrap(),
+            omni_locker_contract: LazyOption::new(b"omni_locker".to_vec(), Some("locker.testnet".parse::<AccountId>().unwrap())),
+            chain_mpc_mapping: LookupMap::new(b"chain_mpc".to_vec()),
+            sponsor_amount: 10_000_000_000_000_000_000_000,
+            sponsor_gas: 100_000_000_000_000,
+            cross_contract_gas: 100_000_000_000_000,
+            migration_gas: 250_000_000_000_000,
+            chunk_size: 10,
+            min_balance: 10_000_000_000_000_000_000_000_000,
+            max_balance: 1_000_000_000_000_000_000_000_000_000,
+            base_fee: 100_000_000_000_000_000_000,
+        };
+        state_v011.chain_mpc_mapping.insert("ethereum".to_string(), "mpc.testnet".parse().unwrap());
+        state_v011.chain_mpc_mapping.flush();
+        env::storage_write(b"STATE", &borsh::to_vec(&state_v011).unwrap());
+
+        let new_contract = OnSocialRelayer::migrate(Some(vec!["ethereum".to_string()]));
+
+        let chain = new_contract.get_chain("ethereum".to_string()).expect("Mapped chain should be migrated");
+        assert_eq!(chain.mpc_contract, "mpc.testnet".parse::<AccountId>().unwrap(), "Signer should be preserved");
+        assert!(chain.enabled && chains::validate(&chain).is_ok());
+        assert!(new_contract.has_role(Role::Owner, manager), "Manager should become the owner");
+    }
+
+    #[test]
+    fn test_migration_from_012_to_012() {
+        let sender: AccountId = "sender.testnet".parse().unwrap();
+        let mut context = setup_context(sender.clone());
+        context.signer_account_id(sender.clone());
+        testing_env!(context.build());
+        let mut contract = setup_contract();
+        let signed = signed_delegate("sender.testnet", 5, 100, vec![Action::Transfer { deposit: NearToken::from_yoctonear(1) }]);
+        assert!(contract.relay_meta_transaction(signed.clone()).is_ok());
+        contract.relayer.delegate_nonces.flush();
+        env::storage_write(b"STATE", &borsh::to_vec(&contract.relayer).unwrap());
+
+        let new_contract = OnSocialRelayer::migrate(None);
+
+        assert_eq!(new_contract.relayer.version, "0.1.2", "Version should be 0.1.2");
+        assert_eq!(new_contract.get_delegate_nonce(sender, signed.public_key), Some(5), "Delegate nonces should be preserved");
+        let logs = get_logs();
+        assert!(
+            logs.contains(&"State is already at latest version".to_string()),
+            "Expected latest version log, got: {:?}", logs
+        );
+    }
+
     #[test]
     fn test_migration_no_prior_state() {
         let manager: AccountId = "manager.testnet".parse().unwrap();
         let context = setup_context(manager.clone());
         testing_env!(context.build());
 
-        let new_contract = OnSocialRelayer::migrate();
+        let new_contract = OnSocialRelayer::migrate(None);
 
-        assert_eq!(new_contract.relayer.version, "0.1.1", "Version should be 0.1.1");
+        assert_eq!(new_contract.relayer.version, "0.1.2", "Version should be 0.1.2");
         assert_eq!(new_contract.relayer.manager, env::current_account_id(), "Manager should be current account");
         assert_eq!(
             new_contract.relayer.offload_recipient,
@@ -203,11 +320,11 @@ mod tests {
         testing_env!(context.build());
 
         // Simulate corrupted state
-        env::state_write(&vec![0u8; 10]); // Invalid Borsh data
+        env::storage_write(b"STATE", &[0u8; 10]); // Invalid Borsh data
 
-        let new_contract = OnSocialRelayer::migrate();
+        let new_contract = OnSocialRelayer::migrate(None);
 
-        assert_eq!(new_contract.relayer.version, "0.1.1", "Version should be 0.1.1");
+        assert_eq!(new_contract.relayer.version, "0.1.2", "Version should be 0.1.2");
         assert_eq!(new_contract.relayer.manager, env::current_account_id(), "Manager should be current account");
         assert_eq!(
             new_contract.relayer.offload_recipient,
@@ -233,7 +350,7 @@ mod tests {
     }
 
     #[test]
-    fn test_gas_logging_relay_meta_transaction() {
+    fn test_gas_usage_relay_meta_transaction() {
         let manager: AccountId = "manager.testnet".parse().unwrap();
         let context = setup_context(manager.clone());
         testing_env!(context.build());
@@ -244,19 +361,15 @@ mod tests {
             "ft.testnet".parse().unwrap(),
         );
 
-        // Create a mock SignedDelegateAction with multiple actions to trigger InvalidNonce
+        // Create a mock SignedDelegateAction with more actions than max_actions to trigger TooManyActions
         let delegate_action = DelegateAction {
             sender_id: "sender.testnet".parse().unwrap(),
             receiver_id: "receiver.testnet".parse().unwrap(),
-            actions: vec![
-                Action::Transfer { deposit: NearToken::from_yoctonear(1_000_000_000_000_000_000_000) },
-                Action::Transfer { deposit: NearToken::from_yoctonear(1_000_000_000_000_000_000_000) },
-            ],
+            actions: vec![Action::Transfer { deposit: NearToken::from_yoctonear(1_000_000_000_000_000_000_000) }; 5],
             nonce: 1,
             max_block_height: 1_000_000,
         };
-        let mut dummy_key = vec![0u8; 33];
-        dummy_key[0] = 0; // ED25519 prefix
+        let dummy_key = vec![0u8; 32]; // ED25519 key data without the curve prefix
         let signed_delegate = SignedDelegateAction {
             delegate_action,
             signature: vec![0u8; 64], // Dummy signature
@@ -268,11 +381,1488 @@ mod tests {
         };
 
         let result = contract.relay_meta_transaction(signed_delegate);
-        assert!(result.is_err(), "Expected InvalidNonce error");
+        assert!(result.is_err(), "Expected TooManyActions error");
+        let used_gas = env::used_gas().as_tgas();
+        assert!(used_gas < 10, "Rejected relay should stay cheap, used {} TGas", used_gas);
+    }
+
+    #[test]
+    fn test_relay_rejects_reused_nonce() {
+        let sender: AccountId = "sender.testnet".parse().unwrap();
+        let mut context = setup_context(sender.clone());
+        context.signer_account_id(sender.clone());
+        testing_env!(context.build());
+        let mut contract = setup_contract();
+
+        let transfer = Action::Transfer { deposit: NearToken::from_yoctonear(1) };
+        // A forged delegate is refused before its nonce is recorded
+        let mut forged = signed_delegate("sender.testnet", 9, 100, vec![transfer.clone()]);
+        forged.signature = vec![0u8; 64];
+        assert_eq!(contract.relay_meta_transaction(forged).err(), Some(RelayerError::Unauthorized));
+        assert!(contract.relay_meta_transaction(signed_delegate("sender.testnet", 5, 100, vec![transfer.clone()])).is_ok());
+        assert_eq!(contract.get_delegate_nonce(sender, signed_delegate("sender.testnet", 5, 100, vec![]).public_key), Some(5));
+
+        testing_env!(context.build());
+        let result = contract.relay_meta_transaction(signed_delegate("sender.testnet", 5, 100, vec![transfer.clone()]));
+        assert_eq!(result.err(), Some(RelayerError::NonceAlreadyUsed));
+        let result = contract.relay_meta_transaction(signed_delegate("sender.testnet", 4, 100, vec![transfer]));
+        assert_eq!(result.err(), Some(RelayerError::StaleNonce));
+    }
+
+    #[test]
+    fn test_relay_rejects_expired_delegate() {
+        let sender: AccountId = "sender.testnet".parse().unwrap();
+        let mut context = setup_context(sender.clone());
+        context.signer_account_id(sender.clone()).block_height(101);
+        testing_env!(context.build());
+        let mut contract = setup_contract();
+
+        let transfer = Action::Transfer { deposit: NearToken::from_yoctonear(1) };
+        let result = contract.relay_meta_transaction(signed_delegate("sender.testnet", 1, 100, vec![transfer]));
+        assert_eq!(result.err(), Some(RelayerError::DelegateExpired));
+        assert_eq!(contract.get_delegate_nonce(sender, signed_delegate("sender.testnet", 1, 100, vec![]).public_key), None);
+    }
+
+    #[test]
+    fn test_batch_relay_rejects_duplicate_delegates() {
+        let sender: AccountId = "sender.testnet".parse().unwrap();
+        let mut context = setup_context(sender.clone());
+        context.signer_account_id(sender);
+        testing_env!(context.build());
+        let mut contract = setup_contract();
+        // Both delegates have to fit in one relay call
+        contract.relayer.cross_contract_gas = 5_000_000_000_000;
+
+        let transfer = Action::Transfer { deposit: NearToken::from_yoctonear(1) };
+        let delegate = signed_delegate("sender.testnet", 7, 100, vec![transfer]);
+        let result = contract.relay_meta_transactions(vec![delegate.clone(), delegate]);
+        assert_eq!(result.err(), Some(RelayerError::NonceAlreadyUsed));
+    }
+
+    #[test]
+    fn test_pause_blocks_only_selected_subsystem() {
+        let manager: AccountId = "manager.testnet".parse().unwrap();
+        let sender: AccountId = "sender.testnet".parse().unwrap();
+        let mut context = setup_context(manager.clone());
+        context.signer_account_id(sender.clone());
+        testing_env!(context.build());
+        let mut contract = setup_contract();
+
+        contract.pause(Some(Subsystem::Bridge)).unwrap();
+        assert!(contract.is_paused(Subsystem::Bridge));
+        assert!(!contract.is_paused(Subsystem::Relay));
+
+        let bridge = Action::BridgeTransfer {
+            token: "token.testnet".to_string(),
+            amount: U128(1_000),
+            destination_chain: "ethereum".to_string(),
+            recipient: "0xabc".to_string(),
+        };
+        let result = contract.relay_meta_transaction(signed_delegate("sender.testnet", 1, 100, vec![bridge]));
+        assert_eq!(result.err(), Some(RelayerError::Paused));
+        let transfer = Action::Transfer { deposit: NearToken::from_yoctonear(1) };
+        assert!(contract.relay_meta_transaction(signed_delegate("sender.testnet", 2, 100, vec![transfer])).is_ok());
+
+        testing_env!(context.build());
+        contract.pause(None).unwrap();
+        assert_eq!(contract.get_paused().len(), 4);
+        let transfer = Action::Transfer { deposit: NearToken::from_yoctonear(1) };
+        let result = contract.relay_meta_transaction(signed_delegate("sender.testnet", 3, 100, vec![transfer.clone()]));
+        assert_eq!(result.err(), Some(RelayerError::Paused));
+
+        contract.unpause(None).unwrap();
+        assert!(contract.get_paused().is_empty());
+        assert!(contract.relay_meta_transaction(signed_delegate("sender.testnet", 3, 100, vec![transfer])).is_ok());
+        assert!(get_logs().iter().any(|log| log.contains("\"event\":\"unpaused\"")));
+    }
+
+    #[test]
+    fn test_pause_requires_manager() {
+        let manager: AccountId = "manager.testnet".parse().unwrap();
+        testing_env!(setup_context(manager).build());
+        let mut contract = setup_contract();
+
+        testing_env!(setup_context("intruder.testnet".parse().unwrap()).build());
+        assert_eq!(contract.pause(None).err(), Some(RelayerError::Unauthorized));
+        assert!(contract.get_paused().is_empty());
+    }
+
+    #[test]
+    fn test_multi_action_delegate_is_batched() {
+        let sender: AccountId = "sender.testnet".parse().unwrap();
+        let mut context = setup_context(sender.clone());
+        context.signer_account_id(sender);
+        testing_env!(context.build());
+        let mut contract = setup_contract();
+
+        let transfer = Action::Transfer { deposit: NearToken::from_yoctonear(1) };
+        let ft_transfer = Action::FtTransfer { token: "usdc".to_string(), receiver_id: "bob.testnet".parse().unwrap(), amount: U128(5), memo: None };
+        let actions = vec![transfer.clone(), transfer.clone(), ft_transfer, transfer.clone()];
+        let signed = sign_delegate(signed_delegate("sender.testnet", 1, 100, actions));
+        let to_receiver = || -> Vec<_> {
+            get_created_receipts().into_iter().filter(|receipt| receipt.receiver_id.as_str() == "receiver.testnet").collect()
+        };
+        // Each callback runs on exactly the gas its caller attached
+        let callback_gas = (0..=4).map(|end| relay::step_callback_gas(&contract.relayer, &signed.delegate_action, end)).collect::<Vec<_>>();
+        let step_context = |result: PromiseResult, end: usize| {
+            let mut context = setup_context("relayer.testnet".parse().unwrap());
+            context.prepaid_gas(callback_gas[end]);
+            testing_env!(context.build(), near_sdk::test_vm_config(), near_sdk::RuntimeFeesConfig::test(), Default::default(), vec![result]);
+        };
+
+        // Consecutive receiver actions share one receipt, and only the first step is dispatched
+        step_context(PromiseResult::Successful(vec![]), 0);
+        drop(contract.handle_auth_result("sender.testnet".parse().unwrap(), signed.clone(), true).unwrap());
+        assert_eq!(to_receiver().iter().map(|receipt| receipt.actions.len()).collect::<Vec<_>>(), vec![2]);
+
+        // Each step's callback starts the next one once it succeeded
+        step_context(PromiseResult::Successful(vec![]), 2);
+        assert!(matches!(contract.handle_actions_result(signed.clone(), 0, 2, vec![]), PromiseOrValue::Promise(_)));
+        assert!(to_receiver().is_empty());
+        step_context(PromiseResult::Successful(vec![]), 3);
+        assert!(matches!(contract.handle_actions_result(signed.clone(), 0, 3, vec![true, true]), PromiseOrValue::Promise(_)));
+        assert_eq!(to_receiver().iter().map(|receipt| receipt.actions.len()).collect::<Vec<_>>(), vec![1]);
+        step_context(PromiseResult::Successful(vec![]), 4);
+        match contract.handle_actions_result(signed.clone(), 0, 4, vec![true, true, true]) {
+            PromiseOrValue::Value(results) => assert_eq!(results, vec![true; 4]),
+            PromiseOrValue::Promise(_) => panic!("all steps already ran"),
+        }
+
+        // A failed step stops the delegate: nothing after it is dispatched
+        step_context(PromiseResult::Failed, 2);
+        match contract.handle_actions_result(signed.clone(), 0, 2, vec![]) {
+            PromiseOrValue::Value(results) => assert_eq!(results, vec![false; 4]),
+            PromiseOrValue::Promise(_) => panic!("later steps ran after a failure"),
+        }
+        assert!(get_created_receipts().is_empty());
+
+        let too_many = signed_delegate("sender.testnet", 2, 100, vec![transfer; 5]);
+        let mut context = setup_context("sender.testnet".parse().unwrap());
+        context.signer_account_id("sender.testnet".parse().unwrap());
+        testing_env!(context.build());
+        assert_eq!(contract.relay_meta_transaction(too_many).err(), Some(RelayerError::TooManyActions));
+    }
+
+    #[test]
+    fn test_relay_collects_near_fee() {
+        testing_env!(setup_context("manager.testnet".parse().unwrap()).build());
+        let mut contract = setup_contract();
+        contract.set_action_fee("Transfer".to_string(), None, U128(100)).unwrap();
+
+        let mut context = setup_context("sender.testnet".parse().unwrap());
+        context.signer_account_id("sender.testnet".parse().unwrap()).attached_deposit(NearToken::from_yoctonear(150));
+        testing_env!(context.build());
+
+        let transfer = Action::Transfer { deposit: NearToken::from_yoctonear(1) };
+        let unpaid = signed_delegate("sender.testnet", 1, 100, vec![transfer.clone()]);
+        assert_eq!(contract.relay_meta_transaction(unpaid).err(), Some(RelayerError::FeeRequired));
+
+        let mut cheap = signed_delegate("sender.testnet", 2, 100, vec![transfer.clone()]);
+        cheap.fee_action = Some(Action::Transfer { deposit: NearToken::from_yoctonear(99) });
+        assert_eq!(contract.relay_meta_transaction(cheap).err(), Some(RelayerError::FeeTooLow));
+
+        let mut paid = signed_delegate("sender.testnet", 3, 100, vec![transfer]);
+        paid.fee_action = Some(Action::Transfer { deposit: NearToken::from_yoctonear(100) });
+        assert!(contract.relay_meta_transaction(paid).is_ok());
+        assert!(get_logs().iter().any(|log| log.contains("\"event\":\"fee_charged\"") && log.contains("\"fee\":100")));
+    }
+
+    #[test]
+    fn test_relay_collects_token_fee_from_credits() {
+        testing_env!(setup_context("manager.testnet".parse().unwrap()).build());
+        let mut contract = setup_contract();
+        contract.add_fee_token("usdc.testnet".to_string()).unwrap();
+        contract.set_action_fee("Transfer".to_string(), None, U128(100)).unwrap();
+
+        testing_env!(setup_context("usdc.testnet".parse().unwrap()).build());
+        assert_eq!(contract.ft_on_transfer("sender.testnet".parse().unwrap(), U128(25), String::new()), U128(0));
+        testing_env!(setup_context("other.testnet".parse().unwrap()).build());
+        assert_eq!(contract.ft_on_transfer("sender.testnet".parse().unwrap(), U128(25), String::new()), U128(25));
+        assert_eq!(contract.get_fee_credit("sender.testnet".parse().unwrap(), "usdc.testnet".to_string()), U128(25));
+
+        let mut context = setup_context("sender.testnet".parse().unwrap());
+        context.signer_account_id("sender.testnet".parse().unwrap());
+        testing_env!(context.build());
+        let transfer = Action::Transfer { deposit: NearToken::from_yoctonear(1) };
+        let usdc_fee = Some(Action::FtTransfer {
+            token: "usdc.testnet".to_string(),
+            receiver_id: "relayer.testnet".parse().unwrap(),
+            amount: U128(10),
+            memo: None,
+        });
+        // Priced in NEAR but not in usdc, so it can't be paid with usdc at all
+        let mut unpriced = signed_delegate("sender.testnet", 1, 100, vec![transfer.clone()]);
+        unpriced.fee_action = usdc_fee.clone();
+        assert_eq!(contract.relay_meta_transaction(unpriced).err(), Some(RelayerError::FeeTokenNotAllowed));
+        assert_eq!(contract.get_fee_credit("sender.testnet".parse().unwrap(), "usdc.testnet".to_string()), U128(25));
+
+        testing_env!(setup_context("manager.testnet".parse().unwrap()).build());
+        contract.set_action_fee("Transfer".to_string(), Some("usdc.testnet".to_string()), U128(10)).unwrap();
+        let mut context = setup_context("sender.testnet".parse().unwrap());
+        context.signer_account_id("sender.testnet".parse().unwrap());
+        testing_env!(context.build());
+        let mut paid = signed_delegate("sender.testnet", 2, 100, vec![transfer]);
+        paid.fee_action = usdc_fee;
+        let hold_key = fees::hold_key(&paid);
+        assert!(contract.relay_meta_transaction(paid).is_ok());
+        assert_eq!(contract.get_fee_credit("sender.testnet".parse().unwrap(), "usdc.testnet".to_string()), U128(15));
+
+        // The auth callback never marked the delegate authorized, so the held fee goes back to the sender
+        testing_env!(setup_context("relayer.testnet".parse().unwrap()).build());
+        assert!(!contract.settle_delegate(hold_key.clone()));
+        assert_eq!(contract.get_fee_credit("sender.testnet".parse().unwrap(), "usdc.testnet".to_string()), U128(25));
+        assert!(get_logs().iter().any(|log| log.contains("\"event\":\"fee_refunded\"")));
+        // Settling twice refunds nothing
+        assert!(!contract.settle_delegate(hold_key));
+        assert_eq!(contract.get_fee_credit("sender.testnet".parse().unwrap(), "usdc.testnet".to_string()), U128(25));
+    }
+
+    #[test]
+    fn test_rejected_delegate_is_rolled_back() {
+        let sender: AccountId = "sender.testnet".parse().unwrap();
+        testing_env!(setup_context("manager.testnet".parse().unwrap()).build());
+        let mut contract = setup_contract();
+        contract.set_action_fee("Transfer".to_string(), None, U128(100)).unwrap();
+        let relay = |contract: &mut OnSocialRelayer, nonce: u64| {
+            let mut context = setup_context(sender.clone());
+            context.signer_account_id(sender.clone()).attached_deposit(NearToken::from_yoctonear(100));
+            testing_env!(context.build());
+            let mut signed = signed_delegate("sender.testnet", nonce, 100, vec![Action::Transfer { deposit: NearToken::from_yoctonear(1) }]);
+            signed.fee_action = Some(Action::Transfer { deposit: NearToken::from_yoctonear(100) });
+            let signed = sign_delegate(signed);
+            contract.relay_meta_transaction(signed.clone()).map(|_| signed)
+        };
+        let accepted = relay(&mut contract, 1).unwrap();
+        let spent = contract.get_sender_spend(sender.clone());
+
+        // The auth callback records its verdict before dispatching; settlement reads that flag
+        testing_env!(setup_context("relayer.testnet".parse().unwrap()).build());
+        drop(contract.handle_auth_result(sender.clone(), accepted.clone(), true).unwrap());
+        assert!(contract.settle_delegate(fees::hold_key(&accepted)));
+        assert_eq!(contract.get_delegate_nonce(sender.clone(), accepted.public_key.clone()), Some(1));
+
+        // A rejected delegate gives back its nonce, quota, ledger spend and fee
+        let rejected = relay(&mut contract, 2).unwrap();
+        assert_eq!(contract.get_quota_status(sender.clone()).relays_used, 2);
+        testing_env!(setup_context("relayer.testnet".parse().unwrap()).build());
+        assert_eq!(contract.handle_auth_result(sender.clone(), rejected.clone(), false).err(), Some(RelayerError::Unauthorized));
+        assert!(!contract.settle_delegate(fees::hold_key(&rejected)));
+        assert_eq!(contract.get_delegate_nonce(sender.clone(), rejected.public_key.clone()), Some(1));
+        assert_eq!(contract.get_quota_status(sender.clone()).relays_used, 1);
+        assert_eq!(contract.get_sender_spend(sender.clone()), spent);
+        let refund = get_created_receipts().into_iter().find(|receipt| receipt.receiver_id == sender).unwrap();
+        assert!(matches!(refund.actions.as_slice(), [near_sdk::mock::MockAction::Transfer { deposit, .. }] if deposit.as_yoctonear() == 100));
+        assert!(get_logs().iter().any(|log| log.contains("\"event\":\"delegate_rejected\"")));
+        // So the sender can submit the same nonce again
+        assert!(relay(&mut contract, 2).is_ok());
+    }
+
+    fn multisig_signers() -> (Vec<SigningKey>, MultisigConfig) {
+        let keys: Vec<SigningKey> = (1u8..=3).map(|i| SigningKey::from_bytes(&[i; 32])).collect();
+        let signers = keys.iter()
+            .map(|key| PublicKey::from_parts(CurveType::ED25519, key.verifying_key().to_bytes().to_vec()).unwrap())
+            .collect();
+        (keys, MultisigConfig { signers, threshold: 2 })
+    }
+
+    #[test]
+    fn test_relay_enforces_multisig_threshold() {
+        let sender: AccountId = "sender.testnet".parse().unwrap();
+        let mut context = setup_context(sender.clone());
+        context.signer_account_id(sender.clone());
+        testing_env!(context.build());
+        let mut contract = setup_contract();
+        // Without a local signer set the signatures are left to the auth contract
+        let mut unconfigured = signed_delegate("sender.testnet", 9, 100, vec![Action::Transfer { deposit: NearToken::from_yoctonear(1) }]);
+        unconfigured.multi_signatures = Some(vec![vec![0u8; 64]]);
+        assert!(multisig::verify_delegate(&contract.relayer, &unconfigured).is_ok());
+        let (keys, config) = multisig_signers();
+        contract.set_multisig_config(sender, Some(config), None).unwrap();
+
+        let transfer = Action::Transfer { deposit: NearToken::from_yoctonear(1) };
+        let mut delegate = signed_delegate("sender.testnet", 1, 100, vec![transfer]);
+        let hash = relay::delegate_hash(&delegate.delegate_action, 0).unwrap();
+        let sign = |key: &SigningKey| key.sign(&hash).to_bytes().to_vec();
+
+        assert_eq!(contract.relay_meta_transaction(delegate.clone()).err(), Some(RelayerError::InsufficientSignatures));
+        delegate.multi_signatures = Some(vec![sign(&keys[0]), sign(&keys[0])]);
+        assert_eq!(contract.relay_meta_transaction(delegate.clone()).err(), Some(RelayerError::DuplicateSigner));
+        delegate.multi_signatures = Some(vec![sign(&keys[0]), sign(&SigningKey::from_bytes(&[9u8; 32]))]);
+        assert_eq!(contract.relay_meta_transaction(delegate.clone()).err(), Some(RelayerError::InvalidSignature));
+        delegate.multi_signatures = Some(vec![sign(&keys[2]), sign(&keys[0])]);
+        assert!(contract.relay_meta_transaction(delegate).is_ok());
+    }
+
+    #[test]
+    fn test_set_base_fee_requires_admin_multisig() {
+        testing_env!(setup_context("manager.testnet".parse().unwrap()).build());
+        let mut contract = setup_contract();
+        let fee: u128 = 200_000_000_000_000_000_000;
+        let bogus = Some(vec![vec![0u8; 64], vec![1u8; 64]]);
+        assert_eq!(contract.set_base_fee(U128(fee), bogus).err(), Some(RelayerError::InsufficientSignatures));
+
+        let (keys, config) = multisig_signers();
+        contract.set_multisig_config("relayer.testnet".parse().unwrap(), Some(config), None).unwrap();
+        let hash = multisig::admin_operation_hash("set_base_fee", &borsh::to_vec(&fee).unwrap(), 0);
+        let signatures: Vec<Vec<u8>> = keys[..2].iter().map(|key| key.sign(&hash).to_bytes().to_vec()).collect();
+        assert_eq!(contract.set_base_fee(U128(fee), Some(signatures[..1].to_vec())).err(), Some(RelayerError::InsufficientSignatures));
+        contract.set_base_fee(U128(fee), Some(signatures.clone())).unwrap();
+        assert_eq!(contract.get_base_fee(), U128(fee));
+        assert_eq!(contract.get_admin_nonce(), 1);
+        // The nonce moved on, so the same approvals can't be replayed
+        assert_eq!(contract.set_base_fee(U128(fee), Some(signatures)).err(), Some(RelayerError::InvalidSignature));
+    }
+
+    #[test]
+    fn test_pauser_role_cannot_reconfigure() {
+        let owner: AccountId = "manager.testnet".parse().unwrap();
+        let oncall: AccountId = "oncall.testnet".parse().unwrap();
+        testing_env!(setup_context(owner.clone()).build());
+        let mut contract = setup_contract();
+        contract.grant_role(Role::Pauser, oncall.clone()).unwrap();
+        assert_eq!(contract.get_role_holders(Role::Pauser), vec![oncall.clone()]);
+
+        testing_env!(setup_context(oncall.clone()).build());
+        contract.pause(Some(Subsystem::Relay)).unwrap();
+        assert_eq!(contract.set_offload_recipient(oncall.clone()).err(), Some(RelayerError::Unauthorized));
+        assert_eq!(contract.abort_upgrade().err(), Some(RelayerError::Unauthorized));
+        assert_eq!(contract.grant_role(Role::Owner, oncall.clone()).err(), Some(RelayerError::Unauthorized));
+
+        contract.renounce_role(Role::Pauser).unwrap();
+        assert!(!contract.has_role(Role::Pauser, oncall));
+        assert!(get_logs().iter().any(|log| log.contains("\"event\":\"role_revoked\"")));
+    }
+
+    #[test]
+    fn test_last_owner_cannot_leave() {
+        let owner: AccountId = "manager.testnet".parse().unwrap();
+        let backup: AccountId = "backup.testnet".parse().unwrap();
+        testing_env!(setup_context(owner.clone()).build());
+        let mut contract = setup_contract();
+        assert_eq!(contract.renounce_role(Role::Owner).err(), Some(RelayerError::LastOwner));
+
+        contract.grant_role(Role::Owner, backup.clone()).unwrap();
+        contract.renounce_role(Role::Owner).unwrap();
+        assert_eq!(contract.get_role_holders(Role::Owner), vec![backup.clone()]);
+        assert_eq!(contract.relayer.manager, backup);
+    }
+
+    #[test]
+    fn test_manager_transfer_needs_acceptance() {
+        let owner: AccountId = "manager.testnet".parse().unwrap();
+        let successor: AccountId = "successor.testnet".parse().unwrap();
+        testing_env!(setup_context(owner.clone()).build());
+        let mut contract = setup_contract();
+        contract.propose_manager(successor.clone(), Some(60_000)).unwrap();
+        assert_eq!(contract.get_pending_manager().unwrap().proposed_manager, successor);
+        assert_eq!(contract.relayer.manager, owner);
+
+        testing_env!(setup_context("typo.testnet".parse().unwrap()).build());
+        assert_eq!(contract.accept_manager().err(), Some(RelayerError::Unauthorized));
+
+        let mut context = setup_context(successor.clone());
+        context.block_timestamp(1_000_000_000_000 + 61_000_000_000);
+        testing_env!(context.build());
+        assert_eq!(contract.accept_manager().err(), Some(RelayerError::ProposalExpired));
+
+        testing_env!(setup_context(owner.clone()).build());
+        contract.propose_manager(successor.clone(), None).unwrap();
+        testing_env!(setup_context(successor.clone()).build());
+        contract.accept_manager().unwrap();
+        assert_eq!(contract.relayer.manager, successor);
+        assert_eq!(contract.get_role_holders(Role::Owner), vec![successor]);
+        assert!(contract.get_pending_manager().is_none());
+        assert_eq!(contract.cancel_manager_proposal().err(), Some(RelayerError::NoPendingProposal));
+    }
+
+    #[test]
+    fn test_governance_queue_enforces_delay() {
+        let owner: AccountId = "manager.testnet".parse().unwrap();
+        testing_env!(setup_context(owner.clone()).build());
+        let mut contract = setup_contract();
+
+        let id = contract.set_auth_contract("auth2.testnet".parse().unwrap()).unwrap();
+        assert_eq!(contract.get_proposals(None, None).len(), 1);
+        assert_eq!(contract.execute_proposal(id).err(), Some(RelayerError::ProposalNotReady));
+        assert_eq!(contract.get_auth_contract(), "auth.testnet".parse::<AccountId>().unwrap());
+
+        let cancelled = contract.register_chain("ethereum".to_string(), chain_config()).unwrap();
+        contract.cancel_proposal(cancelled).unwrap();
+        assert!(contract.get_proposal(cancelled).is_none());
+
+        let mut context = setup_context(owner);
+        context.block_timestamp(1_000_000_000_000 + 86_400_000_000_000);
+        testing_env!(context.build());
+        contract.execute_proposal(id).unwrap();
+        assert_eq!(contract.get_auth_contract(), "auth2.testnet".parse::<AccountId>().unwrap());
+        assert_eq!(contract.execute_proposal(id).err(), Some(RelayerError::ProposalNotFound));
         let logs = get_logs();
-        assert!(
-            logs.iter().any(|log| log.contains("Gas used in relay_meta_transaction")),
-            "Expected gas usage log, got: {:?}", logs
+        assert!(logs.iter().any(|log| log.contains("\"event\":\"proposal_executed\"")));
+    }
+
+    #[test]
+    fn test_nonce_oracle_follows_network() {
+        let owner: AccountId = "manager.testnet".parse().unwrap();
+        let on_network = |relayer: &str, predecessor: &AccountId, timestamp: u64| {
+            let mut context = setup_context(predecessor.clone());
+            context.current_account_id(relayer.parse().unwrap()).signer_account_id(predecessor.clone()).block_timestamp(timestamp);
+            testing_env!(context.build());
+        };
+        on_network("relayer.near", &owner, 1_000_000_000_000);
+        let mut contract = setup_contract();
+        assert_eq!(contract.get_nonce_oracle(), Some("v1.signer".parse().unwrap()));
+        on_network("relayer.testnet", &owner, 1_000_000_000_000);
+        assert_eq!(contract.get_nonce_oracle(), Some("v1.signer-prod.testnet".parse().unwrap()));
+
+        // A sandbox deployment has no default, so relaying fails until an oracle is configured
+        on_network("relayer.sandbox", &owner, 1_000_000_000_000);
+        assert_eq!(contract.get_nonce_oracle(), None);
+        let id = contract.set_nonce_oracle(Some("oracle.sandbox".parse().unwrap())).unwrap();
+        let sender: AccountId = "sender.testnet".parse().unwrap();
+        on_network("relayer.sandbox", &sender, 1_000_000_000_000);
+        let transfer = vec![Action::Transfer { deposit: NearToken::from_yoctonear(1) }];
+        let result = contract.relay_meta_transaction(signed_delegate("sender.testnet", 1, 100, transfer.clone()));
+        assert_eq!(result.err(), Some(RelayerError::MpcNotConfigured));
+        on_network("relayer.sandbox", &owner, 1_000_000_000_000 + 86_400_000_000_000);
+        contract.execute_proposal(id).unwrap();
+        assert_eq!(contract.get_nonce_oracle(), Some("oracle.sandbox".parse().unwrap()));
+        on_network("relayer.sandbox", &sender, 1_000_000_000_000 + 86_400_000_000_000);
+        assert!(contract.relay_meta_transaction(signed_delegate("sender.testnet", 2, 100, transfer)).is_ok());
+
+        // Chain signatures need an explicit per-chain signer
+        let request = Action::ChainSignatureRequest { target_chain: "ethereum".to_string(), derivation_path: "m/0".to_string(), payload: vec![1] };
+        let result = relay::execute_action(&mut contract.relayer, &request, &sender, &"receiver.testnet".parse().unwrap(), None, None);
+        assert_eq!(result.err(), Some(RelayerError::MpcNotConfigured));
+    }
+
+    #[test]
+    fn test_chain_registry_lists_and_gates_chains() {
+        let owner: AccountId = "manager.testnet".parse().unwrap();
+        let sender: AccountId = "sender.testnet".parse().unwrap();
+        testing_env!(setup_context(owner.clone()).build());
+        let mut contract = setup_contract();
+        let invalid = ChainConfig { min_amount: U128(2_000), ..chain_config() };
+        assert_eq!(contract.register_chain("ethereum".to_string(), invalid).err(), Some(RelayerError::InvalidChainConfig));
+        // A template without {sender_id} would share one derived key between all senders
+        let unbound = ChainConfig { derivation_path: "ethereum/{path}".to_string(), ..chain_config() };
+        assert_eq!(contract.register_chain("ethereum".to_string(), unbound).err(), Some(RelayerError::InvalidChainConfig));
+        let ethereum = contract.register_chain("ethereum".to_string(), chain_config()).unwrap();
+        let solana = ChainConfig { address_format: AddressFormat::Solana, ..chain_config() };
+        let solana = contract.register_chain("solana".to_string(), solana).unwrap();
+        assert!(contract.get_chains(None, None).is_empty());
+
+        let mut context = setup_context(owner);
+        context.block_timestamp(1_000_000_000_000 + 86_400_000_000_000);
+        testing_env!(context.build());
+        contract.execute_proposal(ethereum).unwrap();
+        contract.execute_proposal(solana).unwrap();
+        let chains: Vec<String> = contract.get_chains(None, None).into_iter().map(|(chain, _)| chain).collect();
+        assert_eq!(chains, vec!["ethereum".to_string(), "solana".to_string()]);
+        assert_eq!(contract.get_chains(Some(1), Some(1))[0].1.address_format, AddressFormat::Solana);
+        assert!(get_logs().iter().any(|log| log.contains("\"event\":\"chain_registered\"")));
+        // Proposals queued before the registry keep their discriminant
+        let legacy = GovernanceAction::AddChainMpcMapping { chain: "ethereum".to_string(), mpc_contract: "mpc.testnet".parse().unwrap() };
+        assert_eq!(borsh::to_vec(&legacy).unwrap()[0], 3);
+
+        let bridge = |amount: u128| Action::BridgeTransfer { token: "usdc".to_string(), amount: U128(amount), destination_chain: "ethereum".to_string(), recipient: "0xabc".to_string() };
+        let receiver: AccountId = "receiver.testnet".parse().unwrap();
+        assert_eq!(relay::execute_action(&mut contract.relayer, &bridge(5), &sender, &receiver, None, None).err(), Some(RelayerError::AmountTooLow));
+        assert_eq!(relay::execute_action(&mut contract.relayer, &bridge(5_000), &sender, &receiver, None, None).err(), Some(RelayerError::AmountTooHigh));
+        assert!(relay::execute_action(&mut contract.relayer, &bridge(500), &sender, &receiver, None, Some(1)).is_ok());
+
+        contract.set_chain_enabled("ethereum".to_string(), false).unwrap();
+        assert!(!contract.get_chain("ethereum".to_string()).unwrap().enabled);
+        assert_eq!(relay::execute_action(&mut contract.relayer, &bridge(500), &sender, &receiver, None, None).err(), Some(RelayerError::ChainDisabled));
+        contract.remove_chain("solana".to_string()).unwrap();
+        assert!(contract.get_chain("solana".to_string()).is_none());
+        assert_eq!(chain_config().derivation_path(&sender, "m/0"), "sender.testnet/ethereum/m/0");
+    }
+
+    #[test]
+    fn test_bridge_nonces_reserved_and_settled_per_transfer() {
+        let owner: AccountId = "manager.testnet".parse().unwrap();
+        testing_env!(setup_context(owner.clone()).build());
+        let mut contract = setup_contract();
+        let id = contract.register_chain("ethereum".to_string(), chain_config()).unwrap();
+        let mut context = setup_context(owner);
+        context.block_timestamp(1_000_000_000_000 + 86_400_000_000_000);
+        testing_env!(context.build());
+        contract.execute_proposal(id).unwrap();
+
+        // Two transfers to the same chain before either settles
+        let receiver: AccountId = "receiver.testnet".parse().unwrap();
+        for (sender, amount) in [("alice.testnet", 100), ("bob.testnet", 200)] {
+            let bridge = Action::BridgeTransfer { token: "usdc".to_string(), amount: U128(amount), destination_chain: "ethereum".to_string(), recipient: "0xabc".to_string() };
+            drop(relay::execute_action(&mut contract.relayer, &bridge, &sender.parse().unwrap(), &receiver, None, Some(1)).unwrap());
+        }
+        assert_eq!(contract.relayer.get_pending_nonce("ethereum"), 2);
+        let transfer = |contract: &OnSocialRelayer, id: u64| contract.get_bridge_transfer(id).map(|record| (record.sender_id.to_string(), record.nonce, record.status));
+        assert_eq!(transfer(&contract, 0), Some(("alice.testnet".to_string(), 0, TransferStatus::Initiated)));
+        assert_eq!(transfer(&contract, 1), Some(("bob.testnet".to_string(), 1, TransferStatus::Initiated)));
+
+        // Settle the later transfer first: bob's lock succeeds, then alice's fails
+        testing_env!(setup_context("relayer.testnet".parse().unwrap()).build());
+        assert!(matches!(contract.handle_transfer_locked(1, 1, Ok(())), PromiseOrValue::Promise(_)));
+        assert_eq!(transfer(&contract, 1).unwrap().2, TransferStatus::Locked);
+        assert!(matches!(contract.handle_transfer_locked(0, 1, Err(near_sdk::PromiseError::Failed)), PromiseOrValue::Value(false)));
+        assert_eq!(transfer(&contract, 0).unwrap().2, TransferStatus::Failed);
+        assert!(get_logs().iter().any(|log| log.contains("bridge_transfer_failed") && log.contains("alice.testnet") && log.contains("\"nonce\":0")));
+        // A replayed callback can't move a transfer backwards
+        assert!(matches!(contract.handle_transfer_locked(1, 1, Ok(())), PromiseOrValue::Value(false)));
+        assert_eq!(transfer(&contract, 1).unwrap().2, TransferStatus::Locked);
+        assert_eq!(contract.relayer.get_pending_nonce("ethereum"), 2);
+    }
+
+    #[test]
+    fn test_bridge_transfer_lifecycle_is_queryable() {
+        let owner: AccountId = "manager.testnet".parse().unwrap();
+        let sender: AccountId = "alice.testnet".parse().unwrap();
+        testing_env!(setup_context(owner.clone()).build());
+        let mut contract = setup_contract();
+        let id = contract.register_chain("ethereum".to_string(), chain_config()).unwrap();
+        let mut context = setup_context(owner.clone());
+        context.block_timestamp(1_000_000_000_000 + 86_400_000_000_000);
+        testing_env!(context.build());
+        contract.execute_proposal(id).unwrap();
+
+        let receiver: AccountId = "receiver.testnet".parse().unwrap();
+        for amount in [100, 200] {
+            let bridge = Action::BridgeTransfer { token: "usdc".to_string(), amount: U128(amount), destination_chain: "ethereum".to_string(), recipient: "0xabc".to_string() };
+            drop(relay::execute_action(&mut contract.relayer, &bridge, &sender, &receiver, None, None).unwrap());
+        }
+        assert_eq!(contract.get_transfers_by_sender(sender.clone(), None, None).iter().map(|record| record.amount.0).collect::<Vec<_>>(), vec![100, 200]);
+        assert_eq!(contract.get_transfers_by_sender(sender.clone(), Some(1), Some(1)).iter().map(|record| record.id).collect::<Vec<_>>(), vec![1]);
+        assert_eq!(contract.get_pending_transfers("ethereum".to_string()).len(), 2);
+
+        // Transfer 0 locks, gets signed and is completed by an operator
+        let mut context = setup_context("relayer.testnet".parse().unwrap());
+        context.block_timestamp(1_000_000_000_000 + 86_400_000_000_000 + 5_000_000_000);
+        testing_env!(context.build());
+        assert!(matches!(contract.handle_transfer_locked(0, 7, Ok(())), PromiseOrValue::Promise(_)));
+        assert_eq!(contract.complete_bridge_transfer(0).err(), Some(RelayerError::Unauthorized));
+        testing_env!(
+            setup_context("relayer.testnet".parse().unwrap()).build(),
+            near_sdk::test_vm_config(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(b"sig".to_vec())],
+        );
+        assert!(contract.handle_bridge_transfer_result(0));
+        let record = contract.get_bridge_transfer(0).unwrap();
+        assert_eq!((record.status, record.signature.map(|signature| signature.0)), (TransferStatus::Signed, Some(b"sig".to_vec())));
+        testing_env!(setup_context(owner).build());
+        assert_eq!(contract.complete_bridge_transfer(1).err(), Some(RelayerError::InvalidTransferStatus));
+        contract.complete_bridge_transfer(0).unwrap();
+        assert!(get_logs().iter().any(|log| log.contains("bridge_transfer_completed") && log.contains("\"id\":0")));
+        let history = contract.get_bridge_transfer(0).unwrap().history;
+        assert_eq!(history.iter().map(|step| step.status).collect::<Vec<_>>(), vec![TransferStatus::Initiated, TransferStatus::Locked, TransferStatus::Signed, TransferStatus::Completed]);
+        assert_eq!(history[1].timestamp_ms, 1_000_000 + 86_400_000 + 5_000);
+
+        // Transfer 1 fails at signing and leaves the pending list
+        testing_env!(
+            setup_context("relayer.testnet".parse().unwrap()).build(),
+            near_sdk::test_vm_config(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed],
+        );
+        assert!(matches!(contract.handle_transfer_locked(1, 8, Ok(())), PromiseOrValue::Promise(_)));
+        assert!(!contract.handle_bridge_transfer_result(1));
+        assert_eq!(contract.get_bridge_transfer(1).unwrap().status, TransferStatus::Failed);
+        assert!(contract.get_pending_transfers("ethereum".to_string()).is_empty());
+        assert!(contract.get_bridge_transfer(2).is_none());
+
+        // A full list makes room by dropping the oldest finished transfers, never live ones
+        let bridge = Action::BridgeTransfer { token: "usdc".to_string(), amount: U128(100), destination_chain: "ethereum".to_string(), recipient: "0xabc".to_string() };
+        let start = |contract: &mut OnSocialRelayer| {
+            testing_env!(setup_context("relayer.testnet".parse().unwrap()).build());
+            relay::execute_action(&mut contract.relayer, &bridge, &sender, &receiver, None, None).map(drop)
+        };
+        for _ in 2..bridge::MAX_SENDER_TRANSFERS {
+            start(&mut contract).unwrap();
+        }
+        start(&mut contract).unwrap();
+        assert!(contract.get_bridge_transfer(0).is_none());
+        let evicted = get_logs().into_iter().find(|log| log.contains("\"event\":\"bridge_transfer_evicted\"")).unwrap();
+        assert!(evicted.contains("\"id\":0") && evicted.contains("\"history\""));
+        start(&mut contract).unwrap();
+        assert!(contract.get_bridge_transfer(1).is_none());
+        assert_eq!(start(&mut contract).err(), Some(RelayerError::TooManyPendingTransfers));
+        let kept = contract.get_transfers_by_sender(sender, None, Some(100));
+        assert_eq!((kept.len(), kept[0].id), (bridge::MAX_SENDER_TRANSFERS, 2));
+    }
+
+    #[test]
+    fn test_stuck_bridge_transfers_reclaimed_or_force_resolved() {
+        let owner: AccountId = "manager.testnet".parse().unwrap();
+        let sender: AccountId = "alice.testnet".parse().unwrap();
+        let start = 1_000_000_000_000 + 86_400_000_000_000;
+        testing_env!(setup_context(owner.clone()).build());
+        let mut contract = setup_contract();
+        assert_eq!(contract.set_bridge_timeout(1_000).err(), Some(RelayerError::InvalidBridgeTimeout));
+        contract.set_bridge_timeout(600_000).unwrap();
+        assert_eq!(contract.get_bridge_timeout(), 600_000);
+        let fee = 100_000_000_000_000_000_000_000;
+        let id = contract.register_chain("ethereum".to_string(), ChainConfig { fee: U128(fee), ..chain_config() }).unwrap();
+        contract.relayer.bridge_escrow.insert(fees::credit_key(&sender, fees::NEAR_ASSET), 3 * fee);
+        let mut context = setup_context(owner.clone());
+        context.block_timestamp(start);
+        testing_env!(context.build());
+        contract.execute_proposal(id).unwrap();
+        let receiver: AccountId = "receiver.testnet".parse().unwrap();
+        for index in 0..3 {
+            let key = format!("admitted:{}", index);
+            contract.relayer.bridge_fee_quotes.insert(key.clone(), BridgeFeeQuote { fee: U128(fee), asset: fees::NEAR_ASSET.to_string(), surge_applied: false });
+            let bridge = Action::BridgeTransfer { token: "usdc".to_string(), amount: U128(100), destination_chain: "ethereum".to_string(), recipient: "0xabc".to_string() };
+            drop(relay::execute_action(&mut contract.relayer, &bridge, &sender, &receiver, Some(&key), None).unwrap());
+        }
+        // Transfer 2 gets locked and waits for its signature
+        let mut context = setup_context("relayer.testnet".parse().unwrap());
+        context.block_timestamp(start);
+        testing_env!(context.build());
+        assert!(matches!(contract.handle_transfer_locked(2, 1, Ok(())), PromiseOrValue::Promise(_)));
+
+        // Only the sender or a bridge operator may reclaim, and only once the timeout has passed
+        let mut context = setup_context(sender.clone());
+        context.block_timestamp(start + 599_000_000_000);
+        testing_env!(context.build());
+        assert_eq!(contract.reclaim_bridge_transfer(0).err(), Some(RelayerError::TransferNotExpired));
+        let mut context = setup_context("mallory.testnet".parse().unwrap());
+        context.block_timestamp(start + 600_000_000_000);
+        testing_env!(context.build());
+        assert_eq!(contract.reclaim_bridge_transfer(0).err(), Some(RelayerError::Unauthorized));
+        let mut context = setup_context(sender.clone());
+        context.block_timestamp(start + 600_000_000_000);
+        testing_env!(context.build());
+        // The fee is refunded at once, even if the lock callback never lands
+        contract.reclaim_bridge_transfer(0).unwrap();
+        assert!(get_logs().iter().any(|log| log.contains("bridge_transfer_reclaimed") && log.contains("alice.testnet")));
+        assert!(get_logs().iter().any(|log| log.contains(&format!("Refunded {} near to alice.testnet", fee))));
+        assert_eq!(contract.get_bridge_transfer(0).unwrap().status, TransferStatus::Refunded);
+        assert_eq!(contract.reclaim_bridge_transfer(0).err(), Some(RelayerError::InvalidTransferStatus));
+
+        // Late lock and sign callbacks leave a reclaimed transfer as it was settled
+        contract.reclaim_bridge_transfer(2).unwrap();
+        testing_env!(setup_context("relayer.testnet".parse().unwrap()).build());
+        assert!(matches!(contract.handle_transfer_locked(0, 1, Ok(())), PromiseOrValue::Value(false)));
+        testing_env!(
+            setup_context("relayer.testnet".parse().unwrap()).build(),
+            near_sdk::test_vm_config(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(b"sig".to_vec())],
+        );
+        assert!(!contract.handle_bridge_transfer_result(2));
+        let record = contract.get_bridge_transfer(2).unwrap();
+        assert_eq!((record.status, record.signature), (TransferStatus::Refunded, None));
+        assert_eq!(contract.get_bridge_transfer(0).unwrap().status, TransferStatus::Refunded);
+        assert!(!get_logs().iter().any(|log| log.contains("Refunded")));
+
+        // Only the owner can force an outcome, and it's recorded for audit
+        assert_eq!(contract.force_resolve_transfer(1, TransferResolution::Completed).err(), Some(RelayerError::Unauthorized));
+        testing_env!(setup_context(owner).build());
+        contract.force_resolve_transfer(1, TransferResolution::Completed).unwrap();
+        assert!(get_logs().iter().any(|log| log.contains("bridge_transfer_force_resolved") && log.contains("\"previous_status\":\"Initiated\"") && log.contains("manager.testnet")));
+        assert_eq!(contract.get_bridge_transfer(1).unwrap().status, TransferStatus::Completed);
+        assert_eq!(contract.force_resolve_transfer(1, TransferResolution::Refunded).err(), Some(RelayerError::InvalidTransferStatus));
+        assert!(contract.get_pending_transfers("ethereum".to_string()).is_empty());
+    }
+
+    #[test]
+    fn test_bridge_fees_held_in_escrow() {
+        let owner: AccountId = "manager.testnet".parse().unwrap();
+        let sender: AccountId = "sender.testnet".parse().unwrap();
+        let fee = 100_000_000_000_000_000_000_000;
+        testing_env!(setup_context(owner.clone()).build());
+        let mut contract = setup_contract();
+        let token_fee = ChainConfig { fee: U128(20), fee_token: Some("usdc.testnet".to_string()), ..chain_config() };
+        assert_eq!(contract.register_chain("solana".to_string(), token_fee.clone()).err(), Some(RelayerError::FeeTokenNotAllowed));
+        contract.add_fee_token("usdc.testnet".to_string()).unwrap();
+        let ids = [
+            contract.register_chain("ethereum".to_string(), ChainConfig { fee: U128(fee), ..chain_config() }).unwrap(),
+            contract.register_chain("solana".to_string(), token_fee).unwrap(),
+        ];
+        let mut context = setup_context(owner);
+        context.block_timestamp(1_000_000_000_000 + 86_400_000_000_000);
+        testing_env!(context.build());
+        ids.iter().for_each(|id| contract.execute_proposal(*id).unwrap());
+        testing_env!(setup_context("usdc.testnet".parse().unwrap()).build());
+        contract.ft_on_transfer(sender.clone(), U128(25), String::new());
+
+        // NEAR fees come out of the relay call's deposit
+        let bridge = |chain: &str| Action::BridgeTransfer { token: "usdc".to_string(), amount: U128(100), destination_chain: chain.to_string(), recipient: "0xabc".to_string() };
+        let mut context = setup_context(sender.clone());
+        context.signer_account_id(sender.clone()).attached_deposit(NearToken::from_yoctonear(fee - 1));
+        testing_env!(context.build());
+        assert_eq!(contract.relay_meta_transaction(signed_delegate("sender.testnet", 1, 100, vec![bridge("ethereum")])).err(), Some(RelayerError::InsufficientDeposit));
+        let mut context = setup_context(sender.clone());
+        context.signer_account_id(sender.clone()).attached_deposit(NearToken::from_yoctonear(fee));
+        testing_env!(context.build());
+        let near_paid = signed_delegate("sender.testnet", 2, 100, vec![bridge("ethereum")]);
+        assert!(contract.relay_meta_transaction(near_paid.clone()).is_ok());
+        assert_eq!(contract.get_bridge_escrow(sender.clone(), "near".to_string()), U128(fee));
+
+        // Token fees come out of fee credits topped up through ft_transfer_call
+        let mut context = setup_context(sender.clone());
+        context.signer_account_id(sender.clone());
+        testing_env!(context.build());
+        let token_paid = signed_delegate("sender.testnet", 3, 100, vec![bridge("solana")]);
+        assert!(contract.relay_meta_transaction(token_paid.clone()).is_ok());
+        assert_eq!(contract.get_fee_credit(sender.clone(), "usdc.testnet".to_string()), U128(5));
+        assert_eq!(contract.get_bridge_escrow(sender.clone(), "usdc.testnet".to_string()), U128(20));
+        testing_env!(context.build());
+        assert_eq!(contract.relay_meta_transaction(signed_delegate("sender.testnet", 4, 100, vec![bridge("solana")])).err(), Some(RelayerError::InsufficientDeposit));
+
+        // A transfer claims its quoted fee from escrow, and can't start once the escrow is gone
+        let receiver: AccountId = "receiver.testnet".parse().unwrap();
+        drop(relay::execute_action(&mut contract.relayer, &bridge("ethereum"), &sender, &receiver, Some(&bridge::quote_key(&near_paid, 0)), None).unwrap());
+        let record = contract.get_bridge_transfer(0).unwrap();
+        assert_eq!((record.fee, record.fee_asset), (U128(fee), "near".to_string()));
+        assert_eq!(contract.get_bridge_escrow(sender.clone(), "near".to_string()), U128(0));
+
+        // Refunds are paid from the transfer's escrow
+        assert!(matches!(contract.handle_transfer_locked(0, 1, Err(near_sdk::PromiseError::Failed)), PromiseOrValue::Value(false)));
+        assert!(get_logs().iter().any(|log| log.contains(&format!("Refunded {} near to sender.testnet", fee))));
+
+        // Escrow held by an in-flight delegate can't be withdrawn; a rejection releases it
+        assert_eq!(contract.withdraw_bridge_escrow("usdc.testnet".to_string()).err(), Some(RelayerError::FeeRequired));
+        assert!(!contract.settle_delegate(fees::hold_key(&token_paid)));
+        assert!(contract.relayer.bridge_fee_quotes.get(&bridge::quote_key(&token_paid, 0)).is_none());
+        assert_eq!(contract.get_bridge_escrow(sender.clone(), "usdc.testnet".to_string()), U128(20));
+        contract.withdraw_bridge_escrow("usdc.testnet".to_string()).unwrap();
+        assert_eq!(contract.get_fee_credit(sender.clone(), "usdc.testnet".to_string()), U128(25));
+        assert_eq!(contract.withdraw_bridge_escrow("usdc.testnet".to_string()).err(), Some(RelayerError::FeeRequired));
+
+        // Only escrow that no delegate holds can be withdrawn
+        let rejected = signed_delegate("sender.testnet", 5, 100, vec![bridge("ethereum")]);
+        let locked = signed_delegate("sender.testnet", 6, 100, vec![bridge("ethereum")]);
+        for delegate in [&rejected, &locked] {
+            let mut context = setup_context(sender.clone());
+            context.signer_account_id(sender.clone()).attached_deposit(NearToken::from_yoctonear(fee));
+            testing_env!(context.build());
+            assert!(contract.relay_meta_transaction(delegate.clone()).is_ok());
+        }
+        assert!(!contract.settle_delegate(fees::hold_key(&rejected)));
+        contract.withdraw_bridge_escrow("near".to_string()).unwrap();
+        assert!(get_logs().iter().any(|log| log.contains(&format!("Refunded {} near to sender.testnet", fee))));
+        assert_eq!(contract.get_bridge_escrow(sender.clone(), "near".to_string()), U128(fee));
+        assert_eq!(contract.withdraw_bridge_escrow("near".to_string()).err(), Some(RelayerError::FeeRequired));
+        testing_env!(setup_context(sender.clone()).build());
+        drop(relay::execute_action(&mut contract.relayer, &bridge("ethereum"), &sender, &receiver, Some(&bridge::quote_key(&locked, 0)), None).unwrap());
+        assert_eq!(contract.get_bridge_escrow(sender.clone(), "near".to_string()), U128(0));
+    }
+
+    #[test]
+    fn test_bridge_fee_schedule_quotes_exact_charge() {
+        let owner: AccountId = "manager.testnet".parse().unwrap();
+        let sender: AccountId = "sender.testnet".parse().unwrap();
+        testing_env!(setup_context(owner.clone()).build());
+        let mut contract = setup_contract();
+        // Two transfers and their callbacks have to fit in one relay call
+        contract.relayer.cross_contract_gas = 1_000_000_000_000;
+        contract.add_fee_token("usdc.testnet".to_string()).unwrap();
+        let id = contract.register_chain("ethereum".to_string(), ChainConfig { fee_token: Some("usdc.testnet".to_string()), ..chain_config() }).unwrap();
+        let mut context = setup_context(owner.clone());
+        context.block_timestamp(1_000_000_000_000 + 86_400_000_000_000);
+        testing_env!(context.build());
+        contract.execute_proposal(id).unwrap();
+
+        let schedule = BridgeFeeSchedule {
+            flat: U128(50),
+            bps: 100,
+            min_fee: U128(55),
+            max_fee: U128(80),
+            surge: Some(SurgePricing { pending_threshold: 1, multiplier_bps: 20_000 }),
+        };
+        let invalid = BridgeFeeSchedule { min_fee: U128(90), ..schedule.clone() };
+        assert_eq!(contract.set_bridge_fee_schedule("ethereum".to_string(), "usdc.testnet".to_string(), Some(invalid)).err(), Some(RelayerError::InvalidFeeSchedule));
+        assert_eq!(contract.set_bridge_fee_schedule("solana".to_string(), "usdc.testnet".to_string(), Some(schedule.clone())).err(), Some(RelayerError::MpcNotConfigured));
+        // Basis points of a dai transfer can't be charged in usdc; a flat dai schedule can
+        assert_eq!(contract.set_bridge_fee_schedule("ethereum".to_string(), "dai".to_string(), Some(schedule.clone())).err(), Some(RelayerError::InvalidFeeSchedule));
+        contract.set_bridge_fee_schedule("ethereum".to_string(), "dai".to_string(), Some(BridgeFeeSchedule { bps: 0, surge: None, ..schedule.clone() })).unwrap();
+        contract.set_bridge_fee_schedule("ethereum".to_string(), "usdc.testnet".to_string(), Some(schedule)).unwrap();
+        testing_env!(setup_context(sender.clone()).build());
+        assert_eq!(contract.set_bridge_fee_schedule("ethereum".to_string(), "usdc.testnet".to_string(), None).err(), Some(RelayerError::Unauthorized));
+
+        // Flat plus basis points, raised to the minimum; tokens without a schedule pay the chain's flat fee
+        let quote = |contract: &OnSocialRelayer, token: &str, amount: u128| contract.quote_bridge_fee(token.to_string(), U128(amount), "ethereum".to_string()).unwrap();
+        assert_eq!(quote(&contract, "usdc.testnet", 100), BridgeFeeQuote { fee: U128(55), asset: "usdc.testnet".to_string(), surge_applied: false });
+        assert_eq!(quote(&contract, "usdc.testnet", 1_000).fee, U128(60));
+        assert_eq!(quote(&contract, "dai", 1_000).fee, U128(55));
+        assert_eq!(quote(&contract, "wbtc", 1_000).fee, U128(0));
+
+        // The second transfer in a delegate is quoted with the first one pending, so it surges
+        testing_env!(setup_context("usdc.testnet".parse().unwrap()).build());
+        contract.ft_on_transfer(sender.clone(), U128(140), String::new());
+        let bridge = Action::BridgeTransfer { token: "usdc.testnet".to_string(), amount: U128(1_000), destination_chain: "ethereum".to_string(), recipient: "0xabc".to_string() };
+        let mut context = setup_context(sender.clone());
+        context.signer_account_id(sender.clone());
+        testing_env!(context.build());
+        let admitted = signed_delegate("sender.testnet", 1, 100, vec![bridge.clone(); 2]);
+        assert!(contract.relay_meta_transaction(admitted.clone()).is_ok());
+        assert_eq!(contract.get_bridge_escrow(sender.clone(), "usdc.testnet".to_string()), U128(140));
+
+        // Run in reverse, a fresh quote would swap the surge; each pays its admission quote
+        let receiver: AccountId = "receiver.testnet".parse().unwrap();
+        for index in [1, 0] {
+            testing_env!(setup_context(sender.clone()).build());
+            drop(relay::execute_action(&mut contract.relayer, &bridge, &sender, &receiver, Some(&bridge::quote_key(&admitted, index)), None).unwrap());
+        }
+        let fees: Vec<u128> = contract.get_transfers_by_sender(sender.clone(), None, None).iter().map(|record| record.fee.0).collect();
+        assert_eq!(fees, vec![80, 60]);
+        assert_eq!(contract.get_bridge_escrow(sender.clone(), "usdc.testnet".to_string()), U128(0));
+        assert_eq!(quote(&contract, "usdc.testnet", 1_000), BridgeFeeQuote { fee: U128(80), asset: "usdc.testnet".to_string(), surge_applied: true });
+        let mut context = setup_context(sender.clone());
+        context.signer_account_id(sender);
+        testing_env!(context.build());
+        assert_eq!(contract.relay_meta_transaction(signed_delegate("sender.testnet", 2, 100, vec![bridge; 2])).err(), Some(RelayerError::InsufficientDeposit));
+    }
+
+    #[test]
+    fn test_staged_upgrade_needs_second_approver() {
+        let owner: AccountId = "manager.testnet".parse().unwrap();
+        let approver: AccountId = "approver.testnet".parse().unwrap();
+        let code = vec![0u8, 97, 115, 109];
+        let code_hash = hex::encode(env::sha256(&code));
+        let mut context = setup_context(owner.clone());
+        context.context.input = code;
+        testing_env!(context.build());
+        let mut contract = setup_contract();
+        contract.grant_role(Role::Upgrader, approver.clone()).unwrap();
+        contract.stage_upgrade().unwrap();
+        let staged = contract.get_staged_upgrade().unwrap();
+        assert_eq!((staged.code_hash.clone(), staged.size), (code_hash.clone(), 4));
+        assert_eq!(contract.deploy_staged_upgrade(code_hash.clone(), None).err(), Some(RelayerError::Unauthorized));
+
+        testing_env!(setup_context(approver.clone()).build());
+        assert_eq!(contract.deploy_staged_upgrade(code_hash.clone(), None).err(), Some(RelayerError::ProposalNotReady));
+
+        let mut context = setup_context(approver.clone());
+        context.block_timestamp(1_000_000_000_000 + 86_400_000_000_000);
+        testing_env!(context.build());
+        assert_eq!(contract.deploy_staged_upgrade("00".repeat(32), None).err(), Some(RelayerError::CodeHashMismatch));
+        assert!(contract.deploy_staged_upgrade(code_hash.clone(), None).is_ok());
+        // The staged code stays until the deploy callback confirms
+        assert!(contract.get_staged_upgrade().is_some());
+        let settle = |contract: &mut OnSocialRelayer, result: PromiseResult| {
+            testing_env!(setup_context("relayer.testnet".parse().unwrap()).build(), near_sdk::test_vm_config(), near_sdk::RuntimeFeesConfig::test(), Default::default(), vec![result]);
+            contract.handle_upgrade_result(code_hash.clone(), approver.clone())
+        };
+        assert!(!settle(&mut contract, PromiseResult::Failed));
+        assert!(contract.get_staged_upgrade().is_some() && contract.get_code_hash().is_none());
+        assert!(get_logs().iter().any(|log| log.contains("\"event\":\"upgrade_failed\"")));
+        assert!(settle(&mut contract, PromiseResult::Successful(vec![])));
+        assert!(contract.get_staged_upgrade().is_none());
+        assert_eq!(contract.get_code_hash(), Some(code_hash.clone()));
+        assert!(get_logs().iter().any(|log| log.contains("\"event\":\"contract_upgraded\"") && log.contains(&code_hash)));
+        testing_env!(setup_context(approver.clone()).build());
+        assert_eq!(contract.abort_upgrade().err(), Some(RelayerError::NoStagedUpgrade));
+
+        // Owners can let a single upgrader deploy what they staged
+        let mut context = setup_context(owner.clone());
+        context.context.input = vec![0u8, 97, 115, 109, 1];
+        testing_env!(context.build());
+        let id = contract.set_upgrade_approver(false).unwrap();
+        contract.stage_upgrade().unwrap();
+        let mut context = setup_context(owner);
+        context.block_timestamp(1_000_000_000_000 + 86_400_000_000_000);
+        testing_env!(context.build());
+        contract.execute_proposal(id).unwrap();
+        assert!(!contract.get_upgrade_approver_required());
+        let staged_hash = contract.get_staged_upgrade().unwrap().code_hash;
+        assert!(contract.deploy_staged_upgrade(staged_hash, None).is_ok());
+    }
+
+    #[test]
+    fn test_relay_quota_by_tier() {
+        let sender: AccountId = "sender.testnet".parse().unwrap();
+        testing_env!(setup_context("manager.testnet".parse().unwrap()).build());
+        let mut contract = setup_contract();
+        let tier = QuotaTier { max_relays: 2, window_ms: 60_000, max_gas_per_day: 500_000_000_000_000 };
+        let no_gas = QuotaTier { max_gas_per_day: 0, ..tier.clone() };
+        assert_eq!(contract.set_quota_tier("trial".to_string(), no_gas).err(), Some(RelayerError::InvalidTier));
+        let longer_than_a_day = QuotaTier { window_ms: 2 * 86_400_000, ..tier.clone() };
+        assert_eq!(contract.set_quota_tier("trial".to_string(), longer_than_a_day).err(), Some(RelayerError::InvalidTier));
+        contract.set_quota_tier("trial".to_string(), tier).unwrap();
+        contract.set_account_tier(sender.clone(), Some("trial".to_string())).unwrap();
+        assert_eq!(contract.set_account_tier(sender.clone(), Some("missing".to_string())).err(), Some(RelayerError::InvalidTier));
+
+        let mut context = setup_context(sender.clone());
+        context.signer_account_id(sender.clone());
+        testing_env!(context.build());
+        let transfer = Action::Transfer { deposit: NearToken::from_yoctonear(1) };
+        assert!(contract.relay_meta_transaction(signed_delegate("sender.testnet", 1, 100, vec![transfer.clone()])).is_ok());
+        testing_env!(context.build());
+        assert!(contract.relay_meta_transaction(signed_delegate("sender.testnet", 2, 100, vec![transfer.clone()])).is_ok());
+        let status = contract.get_quota_status(sender.clone());
+        assert_eq!((status.tier.as_str(), status.relays_remaining), ("trial", 0));
+        // Each relay is charged the gas its whole promise chain reserves, not just the hops
+        assert_eq!(status.gas_used_today, 320_000_000_000_000);
+        testing_env!(context.build());
+        assert_eq!(
+            contract.relay_meta_transaction(signed_delegate("sender.testnet", 3, 100, vec![transfer.clone()])).err(),
+            Some(RelayerError::QuotaExceeded)
+        );
+        assert!(get_logs().iter().any(|log| log.contains("\"event\":\"quota_exceeded\"")));
+
+        // The rolling window still counts part of the previous one, then lets go of it
+        context.block_timestamp(1_000_000_000_000 + 61_000_000_000);
+        testing_env!(context.build());
+        assert_eq!(contract.get_quota_status(sender.clone()).relays_remaining, 1);
+        context.block_timestamp(1_000_000_000_000 + 121_000_000_000);
+        testing_env!(context.build());
+        assert_eq!(contract.get_quota_status(sender.clone()).relays_remaining, 2);
+
+        // but the daily gas budget still caps the sender
+        assert!(contract.relay_meta_transaction(signed_delegate("sender.testnet", 4, 100, vec![transfer.clone()])).is_ok());
+        testing_env!(context.build());
+        assert_eq!(
+            contract.relay_meta_transaction(signed_delegate("sender.testnet", 5, 100, vec![transfer])).err(),
+            Some(RelayerError::QuotaExceeded)
+        );
+        assert!(get_logs().iter().any(|log| log.contains("\"quota\":\"gas\"")));
+    }
+
+    #[test]
+    fn test_spend_ledger_and_app_budget() {
+        let sender: AccountId = "sender.testnet".parse().unwrap();
+        let app: AccountId = "receiver.testnet".parse().unwrap();
+        testing_env!(setup_context("manager.testnet".parse().unwrap()).build());
+        let mut contract = setup_contract();
+        // Room for one 45 TGas relay with a 1 yocto transfer, not two
+        contract.set_app_budget(app.clone(), Some(U128(5_000_000_000_000_000_000_000))).unwrap();
+
+        let mut context = setup_context(sender.clone());
+        context.signer_account_id(sender.clone());
+        testing_env!(context.build());
+        let transfer = Action::Transfer { deposit: NearToken::from_yoctonear(1) };
+        contract.relay_meta_transaction(signed_delegate("sender.testnet", 1, 100, vec![transfer.clone()])).unwrap();
+        let spend = contract.get_app_spend(app.clone());
+        assert_eq!((spend.relays, spend.gas, spend.deposit), (1, 45_000_000_000_000, U128(1)));
+        assert_eq!(spend.estimated_cost, U128(4_500_000_000_000_000_000_001));
+        assert_eq!(contract.get_sender_spends(None, None), vec![(sender.clone(), spend)]);
+
+        testing_env!(context.build());
+        let result = contract.relay_meta_transaction(signed_delegate("sender.testnet", 2, 100, vec![transfer.clone()]));
+        assert_eq!(result.err(), Some(RelayerError::BudgetExhausted));
+        assert!(get_logs().iter().any(|log| log.contains("\"event\":\"budget_exhausted\"") && log.contains("\"kind\":\"app\"")));
+
+        testing_env!(setup_context("manager.testnet".parse().unwrap()).build());
+        contract.reset_app_spend(app.clone()).unwrap();
+        assert_eq!(contract.get_app_spend(app).relays, 0);
+        testing_env!(context.build());
+        assert!(contract.relay_meta_transaction(signed_delegate("sender.testnet", 3, 100, vec![transfer])).is_ok());
+    }
+
+    #[test]
+    fn test_idle_spend_records_are_pruned() {
+        testing_env!(setup_context("manager.testnet".parse().unwrap()).build());
+        let mut contract = setup_contract();
+        contract.set_sender_budget("budgeted.testnet".parse().unwrap(), Some(U128(u128::MAX))).unwrap();
+        let transfer = Action::Transfer { deposit: NearToken::from_yoctonear(1) };
+        let relay_at = |contract: &mut OnSocialRelayer, sender: &str, timestamp_ms: u64| {
+            let mut context = setup_context(sender.parse().unwrap());
+            context.signer_account_id(sender.parse().unwrap()).block_timestamp(timestamp_ms * 1_000_000).random_seed([0u8; 32]);
+            testing_env!(context.build());
+            contract.relay_meta_transaction(signed_delegate(sender, 1, 100, vec![transfer.clone()])).unwrap();
+        };
+        relay_at(&mut contract, "idle.testnet", 1_000);
+        let month_later = 1_000 + crate::ledger::SPEND_RETENTION_MS;
+        relay_at(&mut contract, "budgeted.testnet", month_later);
+        let senders = |contract: &OnSocialRelayer| contract.get_sender_spends(None, None).into_iter().map(|(id, _)| id.to_string()).collect::<Vec<_>>();
+        assert_eq!(senders(&contract), vec!["budgeted.testnet"]);
+        assert!(get_logs().iter().any(|log| log.contains("\"event\":\"spend_pruned\"") && log.contains("idle.testnet")));
+        // The receiver kept relaying, so its app record survives
+        assert_eq!(contract.get_app_spend("receiver.testnet".parse().unwrap()).relays, 2);
+
+        // Accounts with a budget are never pruned
+        relay_at(&mut contract, "fresh.testnet", month_later * 2);
+        assert_eq!(senders(&contract), vec!["budgeted.testnet", "fresh.testnet"]);
+    }
+
+    #[test]
+    fn test_function_calls_follow_receiver_policy() {
+        let sender: AccountId = "sender.testnet".parse().unwrap();
+        testing_env!(setup_context("manager.testnet".parse().unwrap()).build());
+        let mut contract = setup_contract();
+        let policy = ReceiverPolicy {
+            methods: vec![MethodPolicy { method_name: "post".to_string(), max_deposit: U128(10), max_gas: near_sdk::Gas::from_tgas(20) }],
+            max_transfer: U128(5),
+        };
+        let empty = ReceiverPolicy { methods: vec![], max_transfer: U128(0) };
+        assert_eq!(contract.set_call_policy("receiver.testnet".parse().unwrap(), Some(empty)).err(), Some(RelayerError::InvalidPolicy));
+        contract.set_call_policy("receiver.testnet".parse().unwrap(), Some(policy)).unwrap();
+
+        let mut context = setup_context(sender.clone());
+        context.signer_account_id(sender);
+        testing_env!(context.build());
+        let call = |method: &str, deposit: u128, tgas: u64| Action::FunctionCall {
+            method_name: method.to_string(),
+            args: vec![],
+            gas: near_sdk::Gas::from_tgas(tgas),
+            deposit: NearToken::from_yoctonear(deposit),
+        };
+        let mut elsewhere = signed_delegate("sender.testnet", 1, 100, vec![call("post", 0, 10)]);
+        elsewhere.delegate_action.receiver_id = "defi.testnet".parse().unwrap();
+        assert_eq!(contract.relay_meta_transaction(elsewhere).err(), Some(RelayerError::ReceiverNotAllowed));
+        let result = contract.relay_meta_transaction(signed_delegate("sender.testnet", 1, 100, vec![call("withdraw", 0, 10)]));
+        assert_eq!(result.err(), Some(RelayerError::MethodNotAllowed));
+        let result = contract.relay_meta_transaction(signed_delegate("sender.testnet", 1, 100, vec![call("post", 11, 10)]));
+        assert_eq!(result.err(), Some(RelayerError::PolicyLimitExceeded));
+        let result = contract.relay_meta_transaction(signed_delegate("sender.testnet", 1, 100, vec![call("post", 0, 21)]));
+        assert_eq!(result.err(), Some(RelayerError::PolicyLimitExceeded));
+        assert!(contract.relay_meta_transaction(signed_delegate("sender.testnet", 1, 100, vec![call("post", 10, 20)])).is_ok());
+
+        // Transfers are paid by the relayer, so they stay within the receiver's cap
+        let transfer = |deposit: u128| Action::Transfer { deposit: NearToken::from_yoctonear(deposit) };
+        let mut elsewhere = signed_delegate("sender.testnet", 2, 100, vec![transfer(1)]);
+        elsewhere.delegate_action.receiver_id = "defi.testnet".parse().unwrap();
+        assert_eq!(contract.relay_meta_transaction(sign_delegate(elsewhere)).err(), Some(RelayerError::ReceiverNotAllowed));
+        testing_env!(context.build());
+        let result = contract.relay_meta_transaction(signed_delegate("sender.testnet", 2, 100, vec![transfer(3), transfer(3)]));
+        assert_eq!(result.err(), Some(RelayerError::PolicyLimitExceeded));
+        assert!(contract.relay_meta_transaction(signed_delegate("sender.testnet", 2, 100, vec![transfer(5)])).is_ok());
+    }
+
+    #[test]
+    fn test_function_call_gas_is_honored_and_checked() {
+        let sender: AccountId = "sender.testnet".parse().unwrap();
+        let receiver: AccountId = "receiver.testnet".parse().unwrap();
+        testing_env!(setup_context("manager.testnet".parse().unwrap()).build());
+        let mut contract = setup_contract();
+        let policy = ReceiverPolicy {
+            methods: vec![
+                MethodPolicy { method_name: "post".to_string(), max_deposit: U128(0), max_gas: near_sdk::Gas::from_tgas(250) },
+                MethodPolicy { method_name: "like".to_string(), max_deposit: U128(0), max_gas: near_sdk::Gas::from_tgas(10) },
+            ],
+            max_transfer: U128(0),
+        };
+        contract.set_call_policy(receiver.clone(), Some(policy)).unwrap();
+        let gas_for = |tgas: u64| policy::function_call_gas(&contract.relayer, near_sdk::Gas::from_tgas(tgas)).as_tgas();
+        assert_eq!((gas_for(0), gas_for(120)), (15, 120));
+
+        let mut context = setup_context(sender.clone());
+        context.signer_account_id(sender);
+        testing_env!(context.build());
+        let call = |tgas: u64| Action::FunctionCall {
+            method_name: "post".to_string(),
+            args: vec![],
+            gas: near_sdk::Gas::from_tgas(tgas),
+            deposit: NearToken::from_yoctonear(0),
+        };
+        // 160 TGas for the mpc, auth, callback and settlement hops and their receipts leaves
+        // 140 of the 300 TGas attached
+        let result = contract.relay_meta_transaction(signed_delegate("sender.testnet", 1, 100, vec![call(141)]));
+        assert_eq!(result.err(), Some(RelayerError::GasRequestTooLarge));
+        // A zero request gets cross_contract_gas, held to the method's limit like an explicit one
+        let like = |tgas: u64| Action::FunctionCall { method_name: "like".to_string(), args: vec![], gas: near_sdk::Gas::from_tgas(tgas), deposit: NearToken::from_yoctonear(0) };
+        for request in [like(0), like(15), call(400)] {
+            let result = contract.relay_meta_transaction(signed_delegate("sender.testnet", 1, 100, vec![request]));
+            assert_eq!(result.err(), Some(RelayerError::PolicyLimitExceeded));
+        }
+        let signed = sign_delegate(signed_delegate("sender.testnet", 2, 100, vec![call(120)]));
+        assert!(contract.relay_meta_transaction(signed.clone()).is_ok());
+        assert_eq!(contract.get_sender_spend("sender.testnet".parse().unwrap()).gas, 165_000_000_000_000);
+
+        // Two 280 TGas delegates each fit on their own but not together
+        let mut context = setup_context("sender.testnet".parse().unwrap());
+        context.signer_account_id("sender.testnet".parse().unwrap());
+        testing_env!(context.build());
+        let batch = vec![
+            signed_delegate("sender.testnet", 3, 100, vec![call(120)]),
+            signed_delegate("sender.testnet", 4, 100, vec![call(120)]),
+        ];
+        assert_eq!(contract.relay_meta_transactions(batch.clone()).err(), Some(RelayerError::GasRequestTooLarge));
+        assert_eq!(contract.relay_chunked_meta_transactions(batch.clone()).err(), Some(RelayerError::GasRequestTooLarge));
+        assert!(contract.relay_meta_transaction(batch[0].clone()).is_ok());
+
+        // The auth callback gets by on the gas it was attached
+        let mut context = setup_context("relayer.testnet".parse().unwrap());
+        context.prepaid_gas(relay::step_callback_gas(&contract.relayer, &signed.delegate_action, 0));
+        testing_env!(context.build());
+        assert!(contract.handle_auth_result("sender.testnet".parse().unwrap(), signed, true).is_ok());
+    }
+
+    #[test]
+    fn test_actions_target_delegate_receiver() {
+        let sender: AccountId = "sender.testnet".parse().unwrap();
+        let receiver: AccountId = "receiver.testnet".parse().unwrap();
+        testing_env!(setup_context("relayer.testnet".parse().unwrap()).build());
+        let mut contract = setup_contract();
+        let key = PublicKey::from_parts(CurveType::ED25519, vec![1u8; 32]).unwrap();
+        let actions = [
+            (Action::FunctionCall { method_name: "post".to_string(), args: vec![], gas: near_sdk::Gas::from_tgas(10), deposit: NearToken::from_yoctonear(0) }, &receiver),
+            (Action::Transfer { deposit: NearToken::from_yoctonear(1) }, &receiver),
+        ];
+        for (action, target) in &actions {
+            drop(relay::execute_action(&mut contract.relayer, action, &sender, target, None, None).unwrap());
+        }
+        let targets: Vec<AccountId> = get_created_receipts().into_iter().map(|receipt| receipt.receiver_id).collect();
+        assert_eq!(targets, vec![receiver.clone(), receiver]);
+
+        let mut context = setup_context(sender.clone());
+        context.signer_account_id(sender);
+        testing_env!(context.build());
+        let transfer = vec![Action::Transfer { deposit: NearToken::from_yoctonear(1) }];
+        let mut to_self = signed_delegate("sender.testnet", 1, 100, transfer);
+        to_self.delegate_action.receiver_id = "relayer.testnet".parse().unwrap();
+        assert_eq!(contract.relay_meta_transaction(to_self).err(), Some(RelayerError::ReceiverNotAllowed));
+        // AddKey can't reach the sender's keys from a contract, even on the sender's own account
+        let add_key = vec![Action::AddKey { public_key: key, allowance: None, receiver_id: "receiver.testnet".parse().unwrap(), method_names: vec![] }];
+        let result = contract.relay_meta_transaction(signed_delegate("sender.testnet", 1, 100, add_key.clone()));
+        assert_eq!(result.err(), Some(RelayerError::UnsupportedAction));
+        let mut own_account = signed_delegate("sender.testnet", 1, 100, add_key);
+        own_account.delegate_action.receiver_id = "sender.testnet".parse().unwrap();
+        assert_eq!(contract.relay_meta_transaction(own_account).err(), Some(RelayerError::UnsupportedAction));
+    }
+
+    #[test]
+    fn test_signatures_bound_to_chain_and_relayer() {
+        testing_env!(setup_context("relayer.testnet".parse().unwrap()).build());
+        let contract = setup_contract();
+        let signed = sign_delegate(signed_delegate("sender.testnet", 1, 100, vec![Action::Transfer { deposit: NearToken::from_yoctonear(1) }]));
+        let view = contract.get_signing_payload(signed.delegate_action.clone(), None).unwrap();
+        assert_eq!((view.domain.chain_id.as_str(), view.domain.version), ("testnet", relay::SIGNING_SCHEMA_VERSION));
+        assert_eq!(view.payload.0, relay::signing_payload(&signed.delegate_action, 0).unwrap());
+        assert!(view.payload.0.starts_with(&borsh::to_vec(&view.domain).unwrap()));
+        assert_eq!(view.hash.0, env::sha256(&view.payload.0));
+        let tx_hash = view.hash.0;
+        assert!(relay::verify_signature(&contract.relayer, &signed, &tx_hash).is_ok());
+
+        // Another deployment on the same chain, or the same name on mainnet, derives a different payload
+        for relayer in ["relayer2.testnet", "relayer.near"] {
+            let mut context = setup_context("relayer.testnet".parse().unwrap());
+            context.current_account_id(relayer.parse().unwrap());
+            testing_env!(context.build());
+            let tx_hash = relay::delegate_hash(&signed.delegate_action, 0).unwrap();
+            assert_eq!(relay::verify_signature(&contract.relayer, &signed, &tx_hash), Err(RelayerError::Unauthorized));
+        }
+        assert_eq!(relay::chain_id(), "mainnet");
+
+        // Sandbox and localnet deployments sign for neither network
+        for relayer in ["relayer", "relayer.sandbox"] {
+            let mut context = setup_context("relayer.testnet".parse().unwrap());
+            context.current_account_id(relayer.parse().unwrap());
+            testing_env!(context.build());
+            assert_eq!(relay::chain_id(), "local");
+            assert_eq!(contract.get_signing_payload(signed.delegate_action.clone(), None).unwrap().domain.chain_id, "local");
+        }
+    }
+
+    #[test]
+    fn test_relay_wallet_signed_nep366_delegate() {
+        testing_env!(setup_context("manager.testnet".parse().unwrap()).build());
+        let mut contract = setup_contract();
+
+        let signing_key = SigningKey::from_bytes(&[9u8; 32]);
+        let native = |nonce: u64, actions: Vec<NativeAction>| NativeDelegateAction {
+            sender_id: "sender.testnet".parse().unwrap(),
+            receiver_id: "receiver.testnet".parse().unwrap(),
+            actions,
+            nonce,
+            max_block_height: 100,
+            public_key: NativePublicKey::Ed25519(signing_key.verifying_key().to_bytes()),
+        };
+        let sign = |delegate_action: NativeDelegateAction| {
+            let hash = nep366::signing_hash(&delegate_action).unwrap();
+            NativeSignedDelegateAction { signature: NativeSignature::Ed25519(signing_key.sign(&hash).to_bytes()), delegate_action }
+        };
+        assert_eq!(nep366::SIGNED_DELEGATE_PREFIX.to_le_bytes(), [0x6e, 0x01, 0x00, 0x40]);
+        // Submitted by a third party rather than the sender, as the call's raw borsh input
+        let submit = |contract: &mut OnSocialRelayer, signed: &NativeSignedDelegateAction, deposit: u128| {
+            let mut context = setup_context("submitter.testnet".parse().unwrap());
+            context.signer_account_id("submitter.testnet".parse().unwrap()).attached_deposit(NearToken::from_yoctonear(deposit));
+            context.context.input = borsh::to_vec(signed).unwrap();
+            testing_env!(context.build());
+            contract.relay_signed_delegate(signed.clone()).map(drop)
+        };
+
+        // Wallets hand over raw borsh bytes
+        let bytes = borsh::to_vec(&sign(native(1, vec![NativeAction::Transfer { deposit: 5 }]))).unwrap();
+        let signed: NativeSignedDelegateAction = borsh::from_slice(&bytes).unwrap();
+        let mut tampered = signed.clone();
+        tampered.delegate_action.actions = vec![NativeAction::Transfer { deposit: 6 }];
+        assert_eq!(submit(&mut contract, &tampered, 6).err(), Some(RelayerError::InvalidSignature));
+        let deploy = sign(native(2, vec![NativeAction::DeployContract { code: vec![0] }]));
+        assert_eq!(submit(&mut contract, &deploy, 0).err(), Some(RelayerError::UnsupportedNativeAction("DeployContract".to_string())));
+        let add_key = sign(native(1, vec![NativeAction::AddKey {
+            public_key: NativePublicKey::Ed25519([1u8; 32]),
+            access_key: AccessKey { nonce: 0, permission: AccessKeyPermission::FullAccess },
+        }]));
+        assert_eq!(submit(&mut contract, &add_key, 0).err(), Some(RelayerError::UnsupportedNativeAction("AddKey".to_string())));
+        let delete_key = sign(native(1, vec![NativeAction::DeleteKey { public_key: NativePublicKey::Ed25519([1u8; 32]) }]));
+        assert_eq!(submit(&mut contract, &delete_key, 0).err(), Some(RelayerError::UnsupportedNativeAction("DeleteKey".to_string())));
+        // A wallet-signed call runs with the relayer as predecessor, so only listed methods are relayed
+        let ft_transfer = sign(native(1, vec![NativeAction::FunctionCall { method_name: "ft_transfer".to_string(), args: vec![], gas: 0, deposit: 1 }]));
+        assert_eq!(submit(&mut contract, &ft_transfer, 1).err(), Some(RelayerError::MethodNotAllowed));
+        // The relayer would otherwise fund the transfer itself
+        assert_eq!(submit(&mut contract, &signed, 0).err(), Some(RelayerError::InsufficientDeposit));
+        assert!(submit(&mut contract, &signed, 5).is_ok());
+        assert_eq!(submit(&mut contract, &signed, 5).err(), Some(RelayerError::NonceAlreadyUsed));
+
+        testing_env!(setup_context("relayer.testnet".parse().unwrap()).build());
+        let converted = nep366::into_signed_delegate(signed).unwrap();
+        assert!(contract.handle_auth_result("sender.testnet".parse().unwrap(), converted, true).is_ok());
+    }
+
+    #[test]
+    fn test_relay_wallet_signed_nep366_function_call() {
+        testing_env!(setup_context("manager.testnet".parse().unwrap()).build());
+        let mut contract = setup_contract();
+        let policy = ReceiverPolicy {
+            methods: vec![MethodPolicy { method_name: "post".to_string(), max_deposit: U128(1), max_gas: near_sdk::Gas::from_tgas(10) }],
+            max_transfer: U128(0),
+        };
+        contract.set_call_policy("receiver.testnet".parse().unwrap(), Some(policy)).unwrap();
+
+        // Encoded and signed the way wallets do, with the protocol's FunctionCall action
+        let signing_key = SigningKey::from_bytes(&[9u8; 32]);
+        let sign = |nonce: u64, gas: u64| {
+            let delegate_action = NativeDelegateAction {
+                sender_id: "sender.testnet".parse().unwrap(),
+                receiver_id: "receiver.testnet".parse().unwrap(),
+                actions: vec![NativeAction::FunctionCall { method_name: "post".to_string(), args: b"{\"text\":\"hi\"}".to_vec(), gas, deposit: 1 }],
+                nonce,
+                max_block_height: 100,
+                public_key: NativePublicKey::Ed25519(signing_key.verifying_key().to_bytes()),
+            };
+            let hash = nep366::signing_hash(&delegate_action).unwrap();
+            let signed = NativeSignedDelegateAction { signature: NativeSignature::Ed25519(signing_key.sign(&hash).to_bytes()), delegate_action };
+            borsh::to_vec(&signed).unwrap()
+        };
+        let submit = |contract: &mut OnSocialRelayer, bytes: Vec<u8>| {
+            let mut context = setup_context("submitter.testnet".parse().unwrap());
+            context.signer_account_id("submitter.testnet".parse().unwrap()).attached_deposit(NearToken::from_yoctonear(1));
+            context.context.input = bytes.clone();
+            testing_env!(context.build());
+            let signed: NativeSignedDelegateAction = borsh::from_slice(&bytes).unwrap();
+            contract.relay_signed_delegate(signed).map(drop)
+        };
+
+        // The method's policy limits apply as they do to any sponsored call
+        assert_eq!(submit(&mut contract, sign(1, 20_000_000_000_000)).err(), Some(RelayerError::PolicyLimitExceeded));
+        let bytes = sign(1, 5_000_000_000_000);
+        assert!(submit(&mut contract, bytes.clone()).is_ok());
+
+        let signed: NativeSignedDelegateAction = borsh::from_slice(&bytes).unwrap();
+        let converted = nep366::into_signed_delegate(signed).unwrap();
+        assert!(matches!(&converted.delegate_action.actions[..], [Action::FunctionCall { gas, .. }] if gas.as_tgas() == 5));
+        testing_env!(setup_context("relayer.testnet".parse().unwrap()).build());
+        assert!(contract.handle_auth_result("sender.testnet".parse().unwrap(), converted, true).is_ok());
+    }
+
+    #[test]
+    fn test_session_key_scope_cap_and_revocation() {
+        let sender: AccountId = "sender.testnet".parse().unwrap();
+        testing_env!(setup_context("manager.testnet".parse().unwrap()).build());
+        let mut contract = setup_contract();
+        let policy = ReceiverPolicy {
+            methods: vec![MethodPolicy { method_name: "post".to_string(), max_deposit: U128(0), max_gas: near_sdk::Gas::from_tgas(10) }],
+            max_transfer: U128(1),
+        };
+        contract.set_call_policy("receiver.testnet".parse().unwrap(), Some(policy)).unwrap();
+
+        let session_key = sign_delegate(signed_delegate("sender.testnet", 0, 100, vec![])).public_key;
+        let scope = |action_types: Vec<&str>| SessionScope {
+            action_types: action_types.into_iter().map(String::from).collect(),
+            receivers: vec!["receiver.testnet".parse().unwrap()],
+            spend_cap: U128(10_000_000_000_000_000_000_000), // covers a single 55 TGas relay at the estimated gas price
+        };
+        let mut registration = setup_context(sender.clone());
+        registration.signer_account_id(sender.clone());
+        testing_env!(registration.build());
+        registration.attached_deposit(NearToken::from_millinear(100));
+        testing_env!(registration.build());
+        assert_eq!(contract.register_session(session_key.clone(), 3_600_000, scope(vec!["Stake"])).err(), Some(RelayerError::InvalidSession));
+        assert_eq!(contract.register_session(session_key.clone(), 3_600_000, scope(vec!["FunctionCall", "FunctionCall"])).err(), Some(RelayerError::InvalidSession));
+        let mut crowded = scope(vec!["FunctionCall"]);
+        crowded.receivers = (0..=session::MAX_SESSION_RECEIVERS).map(|i| format!("app{}.testnet", i).parse().unwrap()).collect();
+        assert_eq!(contract.register_session(session_key.clone(), 3_600_000, crowded).err(), Some(RelayerError::InvalidSession));
+        let session_nonce = contract.register_session(session_key.clone(), 3_600_000, scope(vec!["FunctionCall"])).unwrap();
+        let storage_deposit = contract.get_sessions(sender.clone())[0].storage_deposit;
+        assert!(storage_deposit.0 > 0 && storage_deposit.0 < NearToken::from_millinear(100).as_yoctonear());
+
+        let mut context = setup_context(sender.clone());
+        context.signer_account_id(sender.clone());
+        testing_env!(context.build());
+
+        let post = Action::FunctionCall { method_name: "post".to_string(), args: vec![], gas: near_sdk::Gas::from_tgas(10), deposit: NearToken::from_yoctonear(0) };
+        let session_delegate = |nonce: u64, action: Action| {
+            let mut signed = signed_delegate("sender.testnet", nonce, 100, vec![action]);
+            signed.session_nonce = session_nonce;
+            sign_delegate(signed)
+        };
+        let transfer = session_delegate(1, Action::Transfer { deposit: NearToken::from_yoctonear(1) });
+        assert_eq!(contract.relay_meta_transaction(transfer).err(), Some(RelayerError::SessionScopeExceeded));
+        let signed = session_delegate(2, post.clone());
+        assert!(contract.relay_meta_transaction(signed.clone()).is_ok());
+        assert_eq!(contract.get_sessions(sender.clone())[0].spent, U128(5_500_000_000_000_000_000_000));
+        testing_env!(context.build());
+        assert_eq!(contract.relay_meta_transaction(session_delegate(3, post)).err(), Some(RelayerError::SessionSpendCapReached));
+
+        // The auth contract doesn't know session keys; the registry decides
+        testing_env!(setup_context("relayer.testnet".parse().unwrap()).build());
+        assert!(contract.handle_auth_result(sender.clone(), signed.clone(), false).is_ok());
+        let mut late = setup_context("relayer.testnet".parse().unwrap());
+        late.block_timestamp(1_000_000_000_000 + 3_600_000 * 1_000_000);
+        testing_env!(late.build());
+        assert_eq!(contract.handle_auth_result(sender.clone(), signed.clone(), false).err(), Some(RelayerError::SessionExpired));
+
+        // The session nonce is part of the signed bytes, so it can't be swapped after signing
+        let mut replayed = signed.clone();
+        replayed.session_nonce = session_nonce + 1;
+        let tx_hash = relay::delegate_hash(&replayed.delegate_action, replayed.session_nonce).unwrap();
+        assert_eq!(relay::verify_signature(&contract.relayer, &replayed, &tx_hash), Err(RelayerError::Unauthorized));
+
+        testing_env!(setup_context(sender.clone()).build());
+        contract.revoke_session(session_key).unwrap();
+        assert!(contract.get_sessions(sender.clone()).is_empty());
+        let refund = get_created_receipts().into_iter().find(|receipt| receipt.receiver_id == sender).unwrap();
+        assert!(matches!(refund.actions.as_slice(), [near_sdk::mock::MockAction::Transfer { deposit, .. }] if deposit.as_yoctonear() == storage_deposit.0));
+        // The account pays for the session's storage
+        testing_env!(setup_context("other.testnet".parse().unwrap()).build());
+        assert_eq!(contract.register_session(PublicKey::from_parts(CurveType::ED25519, vec![3u8; 32]).unwrap(), 3_600_000, scope(vec!["FunctionCall"])).err(), Some(RelayerError::InsufficientDeposit));
+        testing_env!(setup_context("relayer.testnet".parse().unwrap()).build());
+        assert_eq!(contract.handle_auth_result(sender, signed, true).err(), Some(RelayerError::SessionNotFound));
+    }
+
+    #[test]
+    fn test_actions_result_reports_each_action() {
+        let results_for = |contract: &mut OnSocialRelayer, signed: &SignedDelegateAction, result: PromiseResult, end: u32, results: Vec<bool>| {
+            testing_env!(
+                setup_context("relayer.testnet".parse().unwrap()).build(),
+                near_sdk::test_vm_config(),
+                near_sdk::RuntimeFeesConfig::test(),
+                Default::default(),
+                vec![result],
+            );
+            match contract.handle_actions_result(signed.clone(), 0, end, results) {
+                PromiseOrValue::Value(results) => results,
+                PromiseOrValue::Promise(_) => panic!("expected the final report"),
+            }
+        };
+        testing_env!(setup_context("relayer.testnet".parse().unwrap()).build());
+        let mut contract = setup_contract();
+        let call = Action::FunctionCall { method_name: "post".to_string(), args: vec![], gas: near_sdk::Gas::from_tgas(10), deposit: NearToken::from_yoctonear(0) };
+        let sign = Action::ChainSignatureRequest { target_chain: "ethereum".to_string(), derivation_path: "m/0".to_string(), payload: vec![1] };
+        let signed = signed_delegate("sender.testnet", 1, 100, vec![sign.clone(), call.clone(), Action::Transfer { deposit: NearToken::from_yoctonear(1) }]);
+
+        // One shared receipt fails or succeeds as a whole, and nothing runs after it
+        let results = results_for(&mut contract, &signed, PromiseResult::Failed, 3, vec![true]);
+        assert_eq!(results, vec![true, false, false]);
+        assert!(get_logs().iter().any(|log| log.contains("\"event\":\"actions_executed\"")));
+        // A receipt step succeeds whatever its calls returned, the relayer's callbacks report false on failure
+        assert_eq!(results_for(&mut contract, &signed, PromiseResult::Successful(b"false".to_vec()), 3, vec![true]), vec![true; 3]);
+        assert_eq!(results_for(&mut contract, &signed, PromiseResult::Successful(b"false".to_vec()), 1, vec![]), vec![false; 3]);
+    }
+
+    #[test]
+    fn test_single_action_delegate_reports_actions_executed() {
+        testing_env!(setup_context("relayer.testnet".parse().unwrap()).build());
+        let mut contract = setup_contract();
+        let call = Action::FunctionCall { method_name: "post".to_string(), args: vec![], gas: near_sdk::Gas::from_tgas(10), deposit: NearToken::from_yoctonear(0) };
+        let signed = signed_delegate("sender.testnet", 1, 100, vec![call]);
+        drop(contract.handle_auth_result("sender.testnet".parse().unwrap(), signed.clone(), true).unwrap());
+        let callbacks: Vec<String> = get_created_receipts()
+            .into_iter()
+            .filter(|receipt| receipt.receiver_id.as_str() == "relayer.testnet")
+            .flat_map(|receipt| receipt.actions)
+            .filter_map(|action| match action {
+                near_sdk::mock::MockAction::FunctionCallWeight { method_name, .. } => String::from_utf8(method_name).ok(),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(callbacks, vec!["handle_actions_result".to_string()]);
+
+        testing_env!(
+            setup_context("relayer.testnet".parse().unwrap()).build(),
+            near_sdk::test_vm_config(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(vec![])],
         );
+        assert!(matches!(contract.handle_actions_result(signed, 0, 1, vec![]), PromiseOrValue::Value(results) if results == vec![true]));
+        assert!(get_logs().iter().any(|log| log.contains("\"event\":\"actions_executed\"")));
+
+        // The callback only runs the actions of the account the auth contract checked
+        let other = signed_delegate("other.testnet", 1, 100, vec![Action::Transfer { deposit: NearToken::from_yoctonear(1) }]);
+        assert_eq!(contract.handle_auth_result("sender.testnet".parse().unwrap(), other, true).err(), Some(RelayerError::Unauthorized));
+    }
+
+    // Vectors below were produced off-chain: tx_hash = sha256("onsocial-relayer known answer"),
+    // secp256k1 secret key = [0x11; 32], P-256 secret key = [0x22; 32].
+    const KAT_TX_HASH: &str = "cb90ad9dd861b4ebb463191d78e4603e0d7b5a7ba6fed015e2b35984327f6ccf";
+
+    #[test]
+    fn test_secp256k1_known_answer() {
+        testing_env!(setup_context("relayer.testnet".parse().unwrap()).build());
+        let tx_hash = hex::decode(KAT_TX_HASH).unwrap();
+        let public_key = PublicKey::from_parts(
+            CurveType::SECP256K1,
+            hex::decode("4f355bdcb7cc0af728ef3cceb9615d90684bb5b2ca5f859ab0f0b704075871aa385b6b1b8ead809ca67454d9683fcf2ba03456d6fe2c4abe2b07f0fbdbb2f1c1").unwrap(),
+        ).unwrap();
+        let mut signature = hex::decode("cc7c922825d349932dea5b2e17d907b73595d1cae4bdfaaca0e3cd8b2438c116015145fb272b900165ad68c533ed9beaa44147faf3b578b311f33da8277b906c1c").unwrap();
+
+        assert_eq!(relay::verify_secp256k1(&public_key, &signature, &tx_hash), Ok(()));
+        signature[64] -= 27; // raw recovery id is accepted as well
+        assert_eq!(relay::verify_secp256k1(&public_key, &signature, &tx_hash), Ok(()));
+        signature[10] ^= 0xff;
+        assert_eq!(relay::verify_secp256k1(&public_key, &signature, &tx_hash), Err(RelayerError::Unauthorized));
+    }
+
+    #[test]
+    fn test_webauthn_p256_known_answer() {
+        testing_env!(setup_context("relayer.testnet".parse().unwrap()).build());
+        let tx_hash = hex::decode(KAT_TX_HASH).unwrap();
+        let p256_key = hex::decode("03d65a93977caa3d1b081852ff57a79e465f1660577304baead505dd3a48589cf3").unwrap();
+        let key_id = PublicKey::from_parts(CurveType::ED25519, env::sha256(&p256_key)).unwrap();
+        let assertion = WebAuthnAssertion {
+            public_key: p256_key,
+            authenticator_data: hex::decode("28546c0722c8d8f6c683b517dbe88625a95ff22c0c7b46661831133d1194e95f0500000001").unwrap(),
+            client_data_json: r#"{"type":"webauthn.get","challenge":"y5CtndhhtOu0YxkdeORgPg17Wnum_tAV4rNZhDJ_bM8","origin":"https://onsocial.id","crossOrigin":false}"#.to_string(),
+            signature: hex::decode("fe79eecd9810555b5a47c50b9e18b363b108f1b8038ca992718ab37555260d6df67cb1bf60257ab94d0ddd80433fa8b64e8fffd9a7d61bfe95cecb6f4ac9ce6a").unwrap(),
+        };
+
+        let config = WebAuthnConfig { rp_id: "onsocial.id".to_string(), origins: vec!["https://onsocial.id".to_string()] };
+        let verify = |assertion: &WebAuthnAssertion, key: &PublicKey, hash: &[u8]| relay::verify_webauthn_p256(&config, key, &borsh::to_vec(assertion).unwrap(), hash);
+        assert_eq!(verify(&assertion, &key_id, &tx_hash), Ok(()));
+
+        let other_key = PublicKey::from_parts(CurveType::ED25519, vec![0u8; 32]).unwrap();
+        assert_eq!(verify(&assertion, &other_key, &tx_hash), Err(RelayerError::Unauthorized));
+
+        let mut other_hash = tx_hash.clone();
+        other_hash[0] ^= 0x01;
+        assert_eq!(verify(&assertion, &key_id, &other_hash), Err(RelayerError::InvalidSignature));
+
+        // Assertions for another relying party or origin, or without user verification, are refused
+        let other_rp = WebAuthnConfig { rp_id: "evil.example".to_string(), ..config.clone() };
+        assert_eq!(relay::verify_webauthn_p256(&other_rp, &key_id, &borsh::to_vec(&assertion).unwrap(), &tx_hash), Err(RelayerError::InvalidSignature));
+        let mut other_origin = assertion.clone();
+        other_origin.client_data_json = other_origin.client_data_json.replace("https://onsocial.id", "https://evil.example");
+        assert_eq!(verify(&other_origin, &key_id, &tx_hash), Err(RelayerError::InvalidSignature));
+        let mut cross_origin = assertion.clone();
+        cross_origin.client_data_json = cross_origin.client_data_json.replace("\"crossOrigin\":false", "\"crossOrigin\":true");
+        assert_eq!(verify(&cross_origin, &key_id, &tx_hash), Err(RelayerError::InvalidSignature));
+        let mut unverified = assertion.clone();
+        unverified.authenticator_data[32] = 0x01;
+        assert_eq!(verify(&unverified, &key_id, &tx_hash), Err(RelayerError::InvalidSignature));
+
+        let mut tampered = assertion.clone();
+        tampered.signature[5] ^= 0x01;
+        assert_eq!(verify(&tampered, &key_id, &tx_hash), Err(RelayerError::Unauthorized));
+
+        // Without a configured relying party, passkey delegates don't verify at all
+        testing_env!(setup_context("manager.testnet".parse().unwrap()).build());
+        let mut contract = setup_contract();
+        let mut signed = signed_delegate("sender.testnet", 1, 100, vec![Action::Transfer { deposit: NearToken::from_yoctonear(1) }]);
+        (signed.scheme, signed.public_key, signed.signature) = (SignatureScheme::WebAuthnP256, key_id, borsh::to_vec(&assertion).unwrap());
+        assert_eq!(relay::verify_signature(&contract.relayer, &signed, &relay::delegate_hash(&signed.delegate_action, 0).unwrap()), Err(RelayerError::Unauthorized));
+        assert_eq!(contract.set_webauthn_config(Some(WebAuthnConfig { origins: vec![], ..config.clone() })).err(), Some(RelayerError::InvalidWebAuthnConfig));
+        let id = contract.set_webauthn_config(Some(config.clone())).unwrap();
+        let mut context = setup_context("manager.testnet".parse().unwrap());
+        context.block_timestamp(1_000_000_000_000 + 86_400_000_000_000);
+        testing_env!(context.build());
+        contract.execute_proposal(id).unwrap();
+        assert_eq!(contract.get_webauthn_config(), Some(config));
     }
 }
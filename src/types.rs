@@ -1,6 +1,6 @@
 use near_sdk::borsh::{BorshDeserialize, BorshSerialize};
 use near_sdk::{AccountId, Gas, NearToken, PublicKey};
-use near_sdk::json_types::U128;
+use near_sdk::json_types::{Base64VecU8, U128};
 use serde::{Serialize, Deserialize};
 use near_sdk_macros::NearSchema;
 
@@ -42,6 +42,8 @@ pub enum Action {
 }
 
 impl Action {
+    pub const TYPE_NAMES: [&'static str; 6] = ["ChainSignatureRequest", "FunctionCall", "Transfer", "AddKey", "FtTransfer", "BridgeTransfer"];
+
     pub fn type_name(&self) -> &str {
         match self {
             Action::ChainSignatureRequest { .. } => "ChainSignatureRequest",
@@ -52,6 +54,15 @@ impl Action {
             Action::BridgeTransfer { .. } => "BridgeTransfer",
         }
     }
+
+    // Actions that run as part of the receipt sent to the delegate's receiver.
+    pub fn is_receipt_action(&self) -> bool {
+        matches!(self, Action::FunctionCall { .. } | Action::Transfer { .. } | Action::AddKey { .. })
+    }
+
+    pub fn is_cross_chain(&self) -> bool {
+        matches!(self, Action::ChainSignatureRequest { .. } | Action::BridgeTransfer { .. })
+    }
 }
 
 #[derive(Clone, Eq, PartialEq, BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema)]
@@ -64,10 +75,250 @@ pub struct DelegateAction {
     pub max_block_height: u64,
 }
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq, BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema)]
+#[abi(borsh, json)]
+pub enum Subsystem {
+    Relay,
+    Sponsor,
+    Bridge,
+    Keys,
+}
+
+impl Subsystem {
+    pub const ALL: [Subsystem; 4] = [Subsystem::Relay, Subsystem::Sponsor, Subsystem::Bridge, Subsystem::Keys];
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema)]
+#[abi(borsh, json)]
+pub enum Role {
+    Owner, // Implicitly holds every other role
+    ConfigAdmin,
+    BridgeOperator,
+    Pauser,
+    FeeManager,
+    Upgrader,
+}
+
+impl Role {
+    pub const ALL: [Role; 6] = [Role::Owner, Role::ConfigAdmin, Role::BridgeOperator, Role::Pauser, Role::FeeManager, Role::Upgrader];
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema)]
+#[abi(borsh, json)]
+pub struct ManagerProposal {
+    pub proposed_manager: AccountId,
+    pub proposed_by: AccountId,
+    pub expires_at_ms: Option<u64>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema)]
+#[abi(borsh, json)]
+pub enum AddressFormat {
+    Evm,
+    Bitcoin,
+    Solana,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema)]
+#[abi(borsh, json)]
+pub struct ChainConfig {
+    pub mpc_contract: AccountId,
+    pub derivation_path: String, // template; {sender_id} and {path} are filled in per request
+    pub key_version: u32,
+    pub address_format: AddressFormat,
+    pub min_amount: U128,
+    pub max_amount: U128,
+    pub fee: U128, // per bridge transfer, in yoctoNEAR or units of fee_token
+    pub fee_token: Option<String>, // fee token paid through ft_transfer_call; None charges NEAR
+    pub enabled: bool,
+}
+
+impl ChainConfig {
+    // Binding the path to the sender keeps one account from signing under another's keys.
+    pub fn derivation_path(&self, sender_id: &AccountId, path: &str) -> String {
+        self.derivation_path.replace("{sender_id}", sender_id.as_str()).replace("{path}", path)
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema)]
+#[abi(borsh, json)]
+pub enum GovernanceAction {
+    SetAuthContract { new_auth_contract: AccountId },
+    SetFtWrapperContract { new_ft_wrapper_contract: AccountId },
+    SetOmniLockerContract { new_locker_contract: AccountId },
+    AddChainMpcMapping { chain: String, mpc_contract: AccountId }, // kept so queued proposals still decode
+    SetGovernanceDelay { delay_ms: u64 },
+    SetNonceOracle { nonce_oracle: Option<AccountId> }, // None falls back to the network default
+    RegisterChain { chain: String, config: ChainConfig },
+    SetWebAuthnConfig { config: Option<WebAuthnConfig> }, // None refuses passkey signatures
+    SetUpgradeApprover { required: bool }, // whether someone other than the stager must deploy
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema)]
+#[abi(borsh, json)]
+pub struct GovernanceProposal {
+    pub id: u64,
+    pub action: GovernanceAction,
+    pub proposer: AccountId,
+    pub eta_ms: u64,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema)]
+#[abi(borsh, json)]
+pub struct StagedUpgrade {
+    pub code_hash: String, // hex sha256 of the staged code
+    pub size: u64,
+    pub staged_by: AccountId,
+    pub eta_ms: u64,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema)]
+#[abi(borsh, json)]
+pub struct QuotaTier {
+    pub max_relays: u32, // Relays allowed per rolling window
+    pub window_ms: u64,
+    pub max_gas_per_day: u64,
+}
+
+#[derive(Clone, Default, BorshSerialize, BorshDeserialize, NearSchema)]
+#[abi(borsh)]
+pub struct QuotaUsage {
+    pub window_start_ms: u64, // Start of the current fixed window
+    pub relays_in_window: u32,
+    pub relays_in_previous_window: u32,
+    pub day_start_ms: u64,
+    pub gas_used: u64,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, NearSchema)]
+#[abi(json)]
+pub struct QuotaStatus {
+    pub tier: String,
+    pub relays_used: u32,
+    pub relays_remaining: u32,
+    pub window_ms: u64,
+    pub gas_used_today: u64,
+    pub gas_remaining_today: u64,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema)]
+#[abi(borsh, json)]
+pub struct SpendRecord {
+    pub relays: u64,
+    pub actions: u64,
+    pub gas: u64, // Gas the relayer attached
+    pub deposit: U128, // NEAR the relayer attached
+    pub estimated_cost: U128, // gas at the minimum gas price plus deposit, in yoctoNEAR
+    pub last_relay_ms: u64,
+}
+
+impl SpendRecord {
+    pub fn add(&mut self, other: &SpendRecord) {
+        self.relays += other.relays;
+        self.actions += other.actions;
+        self.gas = self.gas.saturating_add(other.gas);
+        self.deposit = U128(self.deposit.0.saturating_add(other.deposit.0));
+        self.estimated_cost = U128(self.estimated_cost.0.saturating_add(other.estimated_cost.0));
+        self.last_relay_ms = self.last_relay_ms.max(other.last_relay_ms);
+    }
+
+    pub fn sub(&mut self, other: &SpendRecord) {
+        self.relays = self.relays.saturating_sub(other.relays);
+        self.actions = self.actions.saturating_sub(other.actions);
+        self.gas = self.gas.saturating_sub(other.gas);
+        self.deposit = U128(self.deposit.0.saturating_sub(other.deposit.0));
+        self.estimated_cost = U128(self.estimated_cost.0.saturating_sub(other.estimated_cost.0));
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema)]
+#[abi(borsh, json)]
+pub struct MethodPolicy {
+    pub method_name: String,
+    pub max_deposit: U128,
+    pub max_gas: Gas,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema)]
+#[abi(borsh, json)]
+pub struct ReceiverPolicy {
+    pub methods: Vec<MethodPolicy>,
+    pub max_transfer: U128, // NEAR a single delegate may Transfer to the receiver
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema)]
+#[abi(borsh, json)]
+pub struct SessionScope {
+    pub action_types: Vec<String>, // Action type names the key may sign
+    pub receivers: Vec<AccountId>, // empty allows any receiver
+    pub spend_cap: U128, // estimated sponsorship cost, as the ledger prices it
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema)]
+#[abi(borsh, json)]
+pub struct SessionKey {
+    pub public_key: PublicKey,
+    pub session_nonce: u64, // delegates signed by the key carry this in session_nonce
+    pub scope: SessionScope,
+    pub expires_at_ms: u64,
+    pub spent: U128,
+    pub storage_deposit: U128, // paid by the account at registration, refunded on revoke
+}
+
 #[derive(Clone, Eq, PartialEq, BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema)]
 #[abi(borsh, json)]
 pub enum SignatureScheme {
     Ed25519,
+    // 65-byte r || s || v over the EIP-191 personal_sign digest of the delegate hash
+    Secp256k1,
+    // Borsh-encoded WebAuthnAssertion whose challenge is the delegate hash
+    WebAuthnP256,
+    // Wallet signature over the NEP-461 prefixed hash of the canonical NEP-366 delegate
+    Nep366,
+}
+
+// Prefix of every delegate signing payload.
+#[derive(Clone, Debug, Eq, PartialEq, BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema)]
+#[abi(borsh, json)]
+pub struct SigningDomain {
+    pub tag: String,
+    pub chain_id: String, // "mainnet", "testnet" or "local"
+    pub relayer_id: AccountId,
+    pub version: u32,
+    pub session_nonce: u64, // 0 unless signed by a session key
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, NearSchema)]
+#[abi(json)]
+pub struct SigningPayload {
+    pub domain: SigningDomain,
+    pub payload: Base64VecU8, // signed as-is by ed25519 keys
+    pub hash: Base64VecU8, // sha256(payload), signed by secp256k1 and WebAuthn keys
+}
+
+#[derive(Clone, Eq, PartialEq, BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema)]
+#[abi(borsh, json)]
+pub struct WebAuthnAssertion {
+    pub public_key: Vec<u8>, // SEC1-encoded P-256 key
+    pub authenticator_data: Vec<u8>,
+    pub client_data_json: String,
+    pub signature: Vec<u8>, // DER or 64-byte r || s
+}
+
+// Relying party passkey assertions must come from: authenticator data carries sha256(rp_id)
+// and the client data names one of the origins.
+#[derive(Clone, Debug, Eq, PartialEq, BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema)]
+#[abi(borsh, json)]
+pub struct WebAuthnConfig {
+    pub rp_id: String,
+    pub origins: Vec<String>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema)]
+#[abi(borsh, json)]
+pub struct MultisigConfig {
+    pub signers: Vec<PublicKey>, // ed25519 or secp256k1
+    pub threshold: u32,
 }
 
 #[derive(Clone, Eq, PartialEq, BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema)]
@@ -76,8 +327,203 @@ pub struct SignedDelegateAction {
     pub delegate_action: DelegateAction,
     pub signature: Vec<u8>,
     pub public_key: PublicKey,
-    pub session_nonce: u64,
+    pub session_nonce: u64, // 0 unless signed by a session key
     pub scheme: SignatureScheme,
     pub fee_action: Option<Action>,
-    pub multi_signatures: Option<Vec<Vec<u8>>>, // Added for multi-sig support
+    pub multi_signatures: Option<Vec<Vec<u8>>>, // Signatures over the delegate hash from the sender's signer set
+}
+
+// Protocol-level NEP-366 types, laid out exactly as nearcore borsh-encodes them so payloads
+// from standard wallets and SDKs deserialize as-is.
+#[derive(Clone, Debug, Eq, PartialEq, BorshSerialize, BorshDeserialize, NearSchema)]
+#[abi(borsh)]
+pub enum NativePublicKey {
+    Ed25519([u8; 32]),
+    Secp256k1([u8; 64]),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, BorshSerialize, BorshDeserialize, NearSchema)]
+#[abi(borsh)]
+pub enum NativeSignature {
+    Ed25519([u8; 64]),
+    Secp256k1([u8; 65]), // r || s || v
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, BorshSerialize, BorshDeserialize, NearSchema)]
+#[abi(borsh)]
+pub enum AccessKeyPermission {
+    FunctionCall {
+        allowance: Option<u128>,
+        receiver_id: String,
+        method_names: Vec<String>,
+    },
+    FullAccess,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, BorshSerialize, BorshDeserialize, NearSchema)]
+#[abi(borsh)]
+pub struct AccessKey {
+    pub nonce: u64,
+    pub permission: AccessKeyPermission,
+}
+
+// The protocol's NonDelegateAction: variants keep their on-chain discriminants, and a nested
+// Delegate (8) or anything newer fails to deserialize.
+#[derive(Clone, Debug, Eq, PartialEq, BorshSerialize, BorshDeserialize, NearSchema)]
+#[abi(borsh)]
+pub enum NativeAction {
+    CreateAccount,
+    DeployContract { code: Vec<u8> },
+    FunctionCall { method_name: String, args: Vec<u8>, gas: u64, deposit: u128 },
+    Transfer { deposit: u128 },
+    Stake { stake: u128, public_key: NativePublicKey },
+    AddKey { public_key: NativePublicKey, access_key: AccessKey },
+    DeleteKey { public_key: NativePublicKey },
+    DeleteAccount { beneficiary_id: AccountId },
+}
+
+impl NativeAction {
+    pub fn type_name(&self) -> &str {
+        match self {
+            NativeAction::CreateAccount => "CreateAccount",
+            NativeAction::DeployContract { .. } => "DeployContract",
+            NativeAction::FunctionCall { .. } => "FunctionCall",
+            NativeAction::Transfer { .. } => "Transfer",
+            NativeAction::Stake { .. } => "Stake",
+            NativeAction::AddKey { .. } => "AddKey",
+            NativeAction::DeleteKey { .. } => "DeleteKey",
+            NativeAction::DeleteAccount { .. } => "DeleteAccount",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, BorshSerialize, BorshDeserialize, NearSchema)]
+#[abi(borsh)]
+pub struct NativeDelegateAction {
+    pub sender_id: AccountId,
+    pub receiver_id: AccountId,
+    pub actions: Vec<NativeAction>,
+    pub nonce: u64,
+    pub max_block_height: u64,
+    pub public_key: NativePublicKey,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, BorshSerialize, BorshDeserialize, NearSchema)]
+#[abi(borsh)]
+pub struct NativeSignedDelegateAction {
+    pub delegate_action: NativeDelegateAction,
+    pub signature: NativeSignature,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema)]
+#[abi(borsh, json)]
+pub enum TransferStatus {
+    Initiated,
+    Locked,
+    Signed,
+    Completed,
+    Failed,
+    Refunded,
+}
+
+impl TransferStatus {
+    pub fn is_terminal(self) -> bool {
+        matches!(self, TransferStatus::Completed | TransferStatus::Failed | TransferStatus::Refunded)
+    }
+
+    // Initiated -> Locked -> Signed -> Completed; any live step may fail, and a failed
+    // transfer that charged a fee moves on to Refunded.
+    pub fn can_become(self, next: TransferStatus) -> bool {
+        matches!(
+            (self, next),
+            (TransferStatus::Initiated, TransferStatus::Locked)
+                | (TransferStatus::Locked, TransferStatus::Signed)
+                | (TransferStatus::Signed, TransferStatus::Completed)
+                | (TransferStatus::Initiated | TransferStatus::Locked | TransferStatus::Signed, TransferStatus::Failed)
+                | (TransferStatus::Failed, TransferStatus::Refunded)
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema)]
+#[abi(borsh, json)]
+pub enum TransferResolution {
+    Completed,
+    Refunded,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema)]
+#[abi(borsh, json)]
+pub struct TransferStep {
+    pub status: TransferStatus,
+    pub timestamp_ms: u64,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema)]
+#[abi(borsh, json)]
+pub struct BridgeTransferRecord {
+    pub id: u64,
+    pub sender_id: AccountId,
+    pub token: String,
+    pub amount: U128,
+    pub destination_chain: String,
+    pub recipient: String,
+    pub nonce: u64, // per-chain nonce signed into the payload
+    pub fee: U128, // held in escrow until the transfer completes or is refunded
+    pub fee_asset: String,
+    pub status: TransferStatus,
+    pub signature: Option<Base64VecU8>, // raw MPC response once Signed
+    pub history: Vec<TransferStep>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema)]
+#[abi(borsh, json)]
+pub struct SurgePricing {
+    pub pending_threshold: u64, // pending transfers on the chain at which surge applies
+    pub multiplier_bps: u32, // 15_000 charges 1.5x
+}
+
+// Bridge fee for one (destination_chain, token), in the chain's fee asset.
+#[derive(Clone, Debug, Eq, PartialEq, BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema)]
+#[abi(borsh, json)]
+pub struct BridgeFeeSchedule {
+    pub flat: U128,
+    pub bps: u32, // of the transfer amount; only when the fee asset is the bridged token
+    pub min_fee: U128,
+    pub max_fee: U128,
+    pub surge: Option<SurgePricing>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, BorshSerialize, BorshDeserialize, Serialize, Deserialize, NearSchema)]
+#[abi(borsh, json)]
+pub struct BridgeFeeQuote {
+    pub fee: U128,
+    pub asset: String,
+    pub surge_applied: bool,
+}
+
+// A relay fee taken at admission and held until the auth callback settles the delegate.
+#[derive(Clone, Debug, Eq, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct HeldFee {
+    pub sender_id: AccountId,
+    pub payer: AccountId,
+    pub asset: String,
+    pub amount: u128,
+}
+
+// What admitting a delegate spent, kept until its auth callback settles so a rejected
+// delegate can be rolled back. `authorized` is set by the auth callback before it dispatches.
+#[derive(Clone, Debug, Eq, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct PendingDelegate {
+    pub sender_id: AccountId,
+    pub receiver_id: AccountId,
+    pub public_key: PublicKey,
+    pub nonce: u64,
+    pub previous_nonce: Option<u64>,
+    pub session_nonce: u64,
+    pub admitted_at_ms: u64,
+    pub quota_gas: u64,
+    pub spend: SpendRecord,
+    pub action_count: u32,
+    pub authorized: bool,
 }
@@ -0,0 +1,104 @@
+use near_sdk::{env, AccountId, Gas, NearToken, Promise};
+use crate::ext_self;
+use crate::state::Relayer;
+use crate::types::{GovernanceAction, Role, StagedUpgrade};
+use crate::errors::RelayerError;
+use crate::{events::RelayerEvent, governance};
+
+pub const UPGRADE_CALLBACK_GAS: Gas = Gas::from_tgas(10);
+
+// Phase one: keep the code in storage and record its hash. It can be deployed once the
+// governance delay has passed.
+pub fn stage_upgrade(relayer: &mut Relayer, code: Vec<u8>) -> Result<(), RelayerError> {
+    let caller = env::predecessor_account_id();
+    if !relayer.has_role(Role::Upgrader, &caller) {
+        return Err(RelayerError::Unauthorized);
+    }
+    if code.is_empty() {
+        return Err(RelayerError::MissingInput);
+    }
+    let staged = StagedUpgrade {
+        code_hash: hex::encode(env::sha256(&code)),
+        size: code.len() as u64,
+        staged_by: caller,
+        eta_ms: env::block_timestamp_ms().saturating_add(relayer.governance_delay_ms),
+    };
+    relayer.staged_code.set(Some(code));
+    RelayerEvent::UpgradeStaged {
+        code_hash: staged.code_hash.clone(),
+        size: staged.size,
+        staged_by: staged.staged_by.clone(),
+        eta_ms: staged.eta_ms,
+    }.emit();
+    relayer.staged_upgrade = Some(staged);
+    Ok(())
+}
+
+// Phase two: an Upgrader approves the exact staged hash, so a restage racing the approval
+// can't slip different code in. Unless the owners turned it off, the approver must not be the
+// one who staged it. The staged code stays until handle_upgrade_result confirms the deploy, so
+// a failed migrate can be retried. `migrate_chains` is handed to migrate.
+pub fn deploy_staged_upgrade(relayer: &mut Relayer, code_hash: String, migrate_chains: Option<Vec<String>>) -> Result<Promise, RelayerError> {
+    let caller = env::predecessor_account_id();
+    if !relayer.has_role(Role::Upgrader, &caller) {
+        return Err(RelayerError::Unauthorized);
+    }
+    let staged = relayer.staged_upgrade.as_ref().ok_or(RelayerError::NoStagedUpgrade)?;
+    if relayer.upgrade_approver_required && staged.staged_by == caller {
+        return Err(RelayerError::Unauthorized);
+    }
+    if staged.code_hash != code_hash {
+        return Err(RelayerError::CodeHashMismatch);
+    }
+    if env::block_timestamp_ms() < staged.eta_ms {
+        return Err(RelayerError::ProposalNotReady);
+    }
+    let code = relayer.staged_code.get().clone().ok_or(RelayerError::NoStagedUpgrade)?;
+    let migrate_args = serde_json::to_vec(&serde_json::json!({ "chains": migrate_chains })).map_err(|_| RelayerError::MissingInput)?;
+    Ok(Promise::new(env::current_account_id())
+        .deploy_contract(code)
+        .function_call("migrate".to_string(), migrate_args, NearToken::from_yoctonear(0), Gas::from_gas(relayer.migration_gas))
+        .then(
+            ext_self::ext(env::current_account_id())
+                .with_static_gas(UPGRADE_CALLBACK_GAS)
+                .handle_upgrade_result(code_hash, caller)
+        ))
+}
+
+// Runs after the deploy and migrate receipt. If it failed, the old code is still live and
+// the staged upgrade is kept for another attempt.
+pub fn settle_upgrade(relayer: &mut Relayer, code_hash: String, approved_by: AccountId, deployed: bool) -> bool {
+    if !deployed {
+        RelayerEvent::UpgradeFailed { code_hash }.emit();
+        return false;
+    }
+    if relayer.staged_upgrade.as_ref().is_some_and(|staged| staged.code_hash == code_hash) {
+        relayer.staged_upgrade = None;
+        relayer.staged_code.set(None);
+    }
+    let old_code_hash = relayer.code_hash.replace(code_hash.clone());
+    RelayerEvent::ContractUpgraded {
+        manager: approved_by,
+        timestamp: env::block_timestamp_ms(),
+        old_code_hash,
+        new_code_hash: code_hash,
+    }.emit();
+    true
+}
+
+// Whether deploy_staged_upgrade needs an Upgrader other than the stager; off lets a single
+// upgrader ship on their own after the delay.
+pub fn set_upgrade_approver(relayer: &mut Relayer, required: bool) -> Result<u64, RelayerError> {
+    governance::schedule(relayer, GovernanceAction::SetUpgradeApprover { required })
+}
+
+pub fn abort_upgrade(relayer: &mut Relayer) -> Result<(), RelayerError> {
+    let caller = env::predecessor_account_id();
+    if !relayer.has_role(Role::Upgrader, &caller) {
+        return Err(RelayerError::Unauthorized);
+    }
+    let staged = relayer.staged_upgrade.take().ok_or(RelayerError::NoStagedUpgrade)?;
+    relayer.staged_code.set(None);
+    RelayerEvent::UpgradeAborted { code_hash: staged.code_hash, aborted_by: caller }.emit();
+    Ok(())
+}